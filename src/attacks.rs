@@ -0,0 +1,80 @@
+//! Attack detection on a bare board, for when there is no rules engine holding the game.
+
+use crate::bridge::{Board, Color, Piece, PieceType};
+
+pub const KNIGHT_OFFSETS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+pub const KING_OFFSETS: [(i32, i32); 8] = [(0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1)];
+pub const STRAIGHT_DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+pub const DIAGONAL_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+/// Whether the king of `color` is attacked by any of the opponent's pieces.
+pub fn is_in_check(board: &Board, color: Color) -> bool {
+    find_king(board, color).is_some_and(|(file, rank)| is_attacked(board, file, rank, color.opposite()))
+}
+
+/// Whether any piece of color `by` attacks the square at `file` and `rank`.
+pub fn is_attacked(board: &Board, file: i32, rank: i32, by: Color) -> bool {
+    let attacker_at = |file: i32, rank: i32, is_type: fn(PieceType) -> bool| {
+        piece_at(board, file, rank).is_some_and(|piece| piece.color == by && is_type(piece.kind))
+    };
+
+    // Pawns attack diagonally forwards, so look one rank backwards from the target.
+    let pawn_rank = if by == Color::White { rank - 1 } else { rank + 1 };
+    let is_pawn = |piece| matches!(piece, PieceType::Pawn);
+    if attacker_at(file - 1, pawn_rank, is_pawn) || attacker_at(file + 1, pawn_rank, is_pawn) {
+        return true;
+    }
+
+    let is_knight = |piece| matches!(piece, PieceType::Knight);
+    if KNIGHT_OFFSETS.iter().any(|(df, dr)| attacker_at(file + df, rank + dr, is_knight)) {
+        return true;
+    }
+
+    let is_king = |piece| matches!(piece, PieceType::King);
+    if KING_OFFSETS.iter().any(|(df, dr)| attacker_at(file + df, rank + dr, is_king)) {
+        return true;
+    }
+
+    let is_straight_slider = |piece| matches!(piece, PieceType::Rook | PieceType::Queen);
+    let is_diagonal_slider = |piece| matches!(piece, PieceType::Bishop | PieceType::Queen);
+    STRAIGHT_DIRECTIONS.iter().any(|direction| slider_attacks(board, file, rank, *direction, by, is_straight_slider))
+        || DIAGONAL_DIRECTIONS.iter().any(|direction| slider_attacks(board, file, rank, *direction, by, is_diagonal_slider))
+}
+
+/// Walk from the target in `direction` and check whether the first piece in the way is
+/// an attacker that can slide back along the same line.
+fn slider_attacks(board: &Board, file: i32, rank: i32, (df, dr): (i32, i32), by: Color, is_type: fn(PieceType) -> bool) -> bool {
+    let (mut file, mut rank) = (file + df, rank + dr);
+    while in_bounds(file, rank) {
+        if let Some(piece) = piece_at(board, file, rank) {
+            return piece.color == by && is_type(piece.kind);
+        }
+        file += df;
+        rank += dr;
+    }
+    false
+}
+
+fn find_king(board: &Board, color: Color) -> Option<(i32, i32)> {
+    for (rank, row) in board.iter().enumerate() {
+        for (file, piece) in row.iter().enumerate() {
+            if let Some(piece) = piece {
+                if piece.color == color && matches!(piece.kind, PieceType::King) {
+                    return Some((file as i32, rank as i32));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn piece_at(board: &Board, file: i32, rank: i32) -> Option<Piece> {
+    if !in_bounds(file, rank) {
+        return None;
+    }
+    board[rank as usize][file as usize]
+}
+
+fn in_bounds(file: i32, rank: i32) -> bool {
+    (0..8).contains(&file) && (0..8).contains(&rank)
+}
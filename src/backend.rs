@@ -0,0 +1,68 @@
+//! The rules engines games can be played with.
+//!
+//! A backend is a crate that knows the rules of chess, wrapped so that it implements
+//! [`ChessGame`] with the bridge's types. Which one is used is chosen at startup with the
+//! `backend` setting. Adding a backend means implementing [`ChessGame`] for its game type,
+//! usually in a module like [`erikfran_chess_impl`](crate::erikfran_chess_impl), and adding
+//! it to [`Backend`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::bridge::{self, Board, ChessGame, Color, PlayedMove};
+use crate::builtin_rules::BuiltinGame;
+use crate::erikfran_chess_impl::ErikfranGame;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The `chess` crate from INDA23PlusPlus/erikfran-chess.
+    #[default]
+    Erikfran,
+    /// Our own rules, see [`builtin_rules`](crate::builtin_rules).
+    Builtin,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Erikfran, Backend::Builtin];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Erikfran => "erikfran",
+            Backend::Builtin => "builtin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backend| backend.name() == value)
+    }
+
+    /// A game in the starting position.
+    pub fn new_game(self) -> Box<dyn ChessGame> {
+        match self {
+            Backend::Erikfran => Box::new(ErikfranGame::new()),
+            Backend::Builtin => Box::new(BuiltinGame::new()),
+        }
+    }
+
+    /// A game with the pieces on `board` where it is `turn`'s turn to move.
+    ///
+    /// This is used to get a rules engine for positions we only know the board of. How
+    /// things that can't be seen on the board, like castling rights, are guessed depends on
+    /// the backend.
+    pub fn seeded(self, board: &Board, turn: Color) -> Box<dyn ChessGame> {
+        match self {
+            Backend::Erikfran => Box::new(ErikfranGame::seeded(board, turn)),
+            Backend::Builtin => Box::new(BuiltinGame::seeded(board, turn)),
+        }
+    }
+
+    /// A game where `history` has been played from the starting position.
+    ///
+    /// # Panics
+    /// This method panics if a move in `history` is illegal.
+    pub fn replay(self, history: &[PlayedMove]) -> Box<dyn ChessGame> {
+        let mut game = self.new_game();
+        bridge::replay_onto(&mut game, history);
+        game
+    }
+}
@@ -1,7 +1,131 @@
 //! This module describes an abstract api for interacting with a chess game
 //! regardless of backend.
+//!
+//! The types here belong to the bridge rather than to a rules engine, so that the views and
+//! the network code work the same whichever [backend](crate::backend) plays the game. Each
+//! backend converts between these types and its own. What a game is besides its rules is in
+//! [`session`](crate::session).
 
-use erikfran_chess::{Piece, util::{Square, BoardMove}, Color, Move, PieceTypes, MoveError};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub kind: PieceType,
+    pub color: Color,
+}
+
+/// The pieces on the board, indexed by rank and then file, so `board[0][0]` is a1 and
+/// `board[7][0]` is a8.
+pub type Board = [[Option<Piece>; 8]; 8];
+
+/// A square on the board. Files and ranks go from 0 to 7, so a1 is (0, 0) and h8 is (7, 7).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Square {
+    file: u8,
+    rank: u8,
+}
+
+impl Square {
+    pub fn file(self) -> i32 {
+        i32::from(self.file)
+    }
+
+    pub fn rank(self) -> i32 {
+        i32::from(self.rank)
+    }
+}
+
+/// Returned when a file or rank is off the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SquareError;
+
+impl fmt::Display for SquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The square is off the board")
+    }
+}
+
+impl TryFrom<(i32, i32)> for Square {
+    type Error = SquareError;
+
+    /// The square at `(file, rank)`.
+    fn try_from((file, rank): (i32, i32)) -> Result<Self, SquareError> {
+        if !(0..8).contains(&file) || !(0..8).contains(&rank) {
+            return Err(SquareError);
+        }
+        Ok(Square { file: file as u8, rank: rank as u8 })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CastlingSide {
+    KingSide,
+    QueenSide,
+}
+
+impl CastlingSide {
+    /// The squares the king of `color` moves from and to when castling on this side.
+    pub fn king_squares(self, color: Color) -> (Square, Square) {
+        let rank = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        let to_file = match self {
+            CastlingSide::KingSide => 6,
+            CastlingSide::QueenSide => 2,
+        };
+        (Square { file: 4, rank }, Square { file: to_file, rank })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Move {
+    Normal { from: Square, to: Square },
+    Castle { side: CastlingSide },
+}
+
+/// Why a backend refused a move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveError {
+    message: String,
+}
+
+impl MoveError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GameState {
@@ -9,10 +133,17 @@ pub enum GameState {
     Check,
 }
 
+/// A move that has been played, together with the piece a pawn was promoted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayedMove {
+    pub mv: Move,
+    pub promotion: Option<PieceType>,
+}
+
 pub trait ChessGame {
     fn update(&mut self);
 
-    fn get_pieces(&self) -> [[Option<Piece>; 8]; 8];
+    fn get_pieces(&self) -> Board;
 
     fn get_piece(&self, at: Square) -> Option<Piece>;
 
@@ -22,13 +153,146 @@ pub trait ChessGame {
 
     fn current_turn(&self) -> Color;
 
-    fn promote(&mut self, promotion_square: Square, piece: PieceTypes);
+    fn promote(&mut self, promotion_square: Square, piece: PieceType);
 
-    fn possible_moves(&mut self, at: Square) -> Result<(BoardMove, Vec<Move>), MoveError>;
+    /// The moves the piece on `at` can make, including castling if it is a king.
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError>;
 
     fn perform_move(&mut self, mv: Move) -> Result<(), MoveError>;
 
     fn can_play_right_now(&self) -> bool;
 
     fn has_possible_moves(&self) -> bool;
-}
\ No newline at end of file
+
+    /// The move this game would make for the side to move, for backends that play by
+    /// themselves. Hosts make the move for them when it is their turn.
+    fn engine_move(&mut self) -> Option<PlayedMove> {
+        None
+    }
+
+    /// Whether this game plays by itself through [`engine_move`](ChessGame::engine_move).
+    fn is_engine(&self) -> bool {
+        false
+    }
+
+    /// Whether the game has ended, by checkmate or stalemate, or because a network game
+    /// was decided some other way. This is asked every frame, so it should be cheap.
+    fn is_over(&self) -> bool;
+}
+
+/// Boxed games, such as the ones [`Backend`](crate::backend::Backend) creates, are games too.
+impl<G: ChessGame + ?Sized> ChessGame for Box<G> {
+    fn update(&mut self) {
+        (**self).update()
+    }
+
+    fn get_pieces(&self) -> Board {
+        (**self).get_pieces()
+    }
+
+    fn get_piece(&self, at: Square) -> Option<Piece> {
+        (**self).get_piece(at)
+    }
+
+    fn get_state(&self) -> GameState {
+        (**self).get_state()
+    }
+
+    fn is_check(&self) -> bool {
+        (**self).is_check()
+    }
+
+    fn current_turn(&self) -> Color {
+        (**self).current_turn()
+    }
+
+    fn promote(&mut self, promotion_square: Square, piece: PieceType) {
+        (**self).promote(promotion_square, piece)
+    }
+
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        (**self).possible_moves(at)
+    }
+
+    fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        (**self).perform_move(mv)
+    }
+
+    fn can_play_right_now(&self) -> bool {
+        (**self).can_play_right_now()
+    }
+
+    fn has_possible_moves(&self) -> bool {
+        (**self).has_possible_moves()
+    }
+
+    fn engine_move(&mut self) -> Option<PlayedMove> {
+        (**self).engine_move()
+    }
+
+    fn is_engine(&self) -> bool {
+        (**self).is_engine()
+    }
+
+    fn is_over(&self) -> bool {
+        (**self).is_over()
+    }
+}
+
+/// Play `history` in `game`, which should be in the starting position.
+///
+/// Backends can neither be cloned nor rewound, so this is how earlier positions are
+/// restored.
+///
+/// # Panics
+/// This function panics if a move in `history` is illegal.
+pub fn replay_onto(game: &mut (impl ChessGame + ?Sized), history: &[PlayedMove]) {
+    try_replay_onto(game, history).unwrap_or_else(|err| panic!("Replayed move was illegal: {err}"));
+}
+
+/// Like [`replay_onto`], but stops at the first illegal move and returns its error, for
+/// histories that come from somewhere else.
+pub fn try_replay_onto(game: &mut (impl ChessGame + ?Sized), history: &[PlayedMove]) -> Result<(), MoveError> {
+    for played in history {
+        game.perform_move(played.mv)?;
+        if let (Move::Normal { to, .. }, Some(piece)) = (played.mv, played.promotion) {
+            game.promote(to, piece);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the side to move in `game` has a move it can make.
+pub fn has_legal_moves(game: &mut (impl ChessGame + ?Sized)) -> bool {
+    let turn = game.current_turn();
+    let own_squares: Vec<Square> = (0..8).flat_map(|rank| (0..8).map(move |file| Square { file, rank }))
+        .filter(|&square| game.get_piece(square).is_some_and(|piece| piece.color == turn))
+        .collect();
+    own_squares.into_iter().any(|square| game.possible_moves(square).is_ok_and(|moves| !moves.is_empty()))
+}
+
+/// Every move the side to move in `game` can make.
+pub fn all_moves(game: &mut (impl ChessGame + ?Sized)) -> Vec<Move> {
+    let turn = game.current_turn();
+    let own_squares: Vec<Square> = (0..8).flat_map(|rank| (0..8).map(move |file| Square { file, rank }))
+        .filter(|&square| game.get_piece(square).is_some_and(|piece| piece.color == turn))
+        .collect();
+    own_squares.into_iter()
+        .flat_map(|square| game.possible_moves(square).unwrap_or_default())
+        .collect()
+}
+
+/// Whether `mv` on `board` takes a pawn to the last rank, where it has to be promoted.
+pub fn is_promotion(board: &Board, mv: Move) -> bool {
+    let Move::Normal { from, to } = mv else {
+        return false;
+    };
+    let last_rank = |color| if color == Color::White { 7 } else { 0 };
+    board[from.rank() as usize][from.file() as usize]
+        .is_some_and(|piece| piece.kind == PieceType::Pawn && to.rank() == last_rank(piece.color))
+}
+
+/// The color that made the move at `index` in the history.
+pub fn color_of_ply(index: usize) -> Color {
+    if index.is_multiple_of(2) { Color::White } else { Color::Black }
+}
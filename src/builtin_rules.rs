@@ -0,0 +1,315 @@
+//! A backend with rules of its own, written against the bridge's types so that it needs no
+//! conversions. It keeps track of castling rights and en passant itself, which also lets it
+//! guess them for boards it is [seeded](BuiltinGame::seeded) with.
+
+use crate::attacks::{self, DIAGONAL_DIRECTIONS, KING_OFFSETS, KNIGHT_OFFSETS, STRAIGHT_DIRECTIONS};
+use crate::bridge::{self, Board, CastlingSide, Color, GameState, Move, MoveError, Piece, PieceType, Square};
+
+const BACK_RANK: [PieceType; 8] = [
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Queen,
+    PieceType::King,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Rook,
+];
+
+pub struct BuiltinGame {
+    board: Board,
+    turn: Color,
+    /// Whether each color may still castle on each side, see `castling_index`.
+    castling: [bool; 4],
+    /// The square a pawn skipped over in the last move, where it can be taken en passant.
+    en_passant: Option<Square>,
+}
+
+impl BuiltinGame {
+    /// A game in the starting position.
+    pub fn new() -> Self {
+        let mut board = [[None; 8]; 8];
+        for (file, kind) in BACK_RANK.into_iter().enumerate() {
+            board[0][file] = Some(Piece { kind, color: Color::White });
+            board[1][file] = Some(Piece { kind: PieceType::Pawn, color: Color::White });
+            board[6][file] = Some(Piece { kind: PieceType::Pawn, color: Color::Black });
+            board[7][file] = Some(Piece { kind, color: Color::Black });
+        }
+        Self { board, turn: Color::White, castling: [true; 4], en_passant: None }
+    }
+
+    /// Create a game with the pieces on `board` where it is `turn`'s turn to move.
+    ///
+    /// Castling is allowed where the king and the rook are on their starting squares, since
+    /// whether they have moved and come back can't be seen on the board.
+    pub fn seeded(board: &Board, turn: Color) -> Self {
+        let mut game = Self { board: *board, turn, castling: [false; 4], en_passant: None };
+        for color in [Color::White, Color::Black] {
+            for side in [CastlingSide::KingSide, CastlingSide::QueenSide] {
+                let (king_from, _) = side.king_squares(color);
+                let rook_from = rook_squares(side, color).0;
+                game.castling[castling_index(color, side)] = game.piece(king_from) == Some(Piece { kind: PieceType::King, color })
+                    && game.piece(rook_from) == Some(Piece { kind: PieceType::Rook, color });
+            }
+        }
+        game
+    }
+
+    fn piece(&self, at: Square) -> Option<Piece> {
+        self.board[at.rank() as usize][at.file() as usize]
+    }
+
+    fn set_piece(&mut self, at: Square, piece: Option<Piece>) {
+        self.board[at.rank() as usize][at.file() as usize] = piece;
+    }
+
+    /// The squares the piece on `from` moves to, without checking whether that leaves its
+    /// own king in check.
+    fn targets(&self, from: Square, piece: Piece) -> Vec<Square> {
+        let (file, rank) = (from.file(), from.rank());
+        let free = |square: Square| self.piece(square).is_none_or(|other| other.color != piece.color);
+        let steps = |offsets: &[(i32, i32)]| -> Vec<Square> {
+            offsets.iter()
+                .filter_map(|(df, dr)| Square::try_from((file + df, rank + dr)).ok())
+                .filter(|&square| free(square))
+                .collect()
+        };
+        let slides = |directions: &[(i32, i32)]| -> Vec<Square> {
+            let mut targets = vec![];
+            for (df, dr) in directions {
+                let mut to = Square::try_from((file + df, rank + dr));
+                while let Ok(square) = to {
+                    if free(square) {
+                        targets.push(square);
+                    }
+                    if self.piece(square).is_some() {
+                        break;
+                    }
+                    to = Square::try_from((square.file() + df, square.rank() + dr));
+                }
+            }
+            targets
+        };
+        match piece.kind {
+            PieceType::Pawn => self.pawn_targets(from, piece.color),
+            PieceType::Knight => steps(&KNIGHT_OFFSETS),
+            PieceType::Bishop => slides(&DIAGONAL_DIRECTIONS),
+            PieceType::Rook => slides(&STRAIGHT_DIRECTIONS),
+            PieceType::Queen => [slides(&STRAIGHT_DIRECTIONS), slides(&DIAGONAL_DIRECTIONS)].concat(),
+            PieceType::King => steps(&KING_OFFSETS),
+        }
+    }
+
+    fn pawn_targets(&self, from: Square, color: Color) -> Vec<Square> {
+        let (forward, start_rank) = match color {
+            Color::White => (1, 1),
+            Color::Black => (-1, 6),
+        };
+        let (file, rank) = (from.file(), from.rank());
+        let mut targets = vec![];
+        if let Ok(one) = Square::try_from((file, rank + forward)) {
+            if self.piece(one).is_none() {
+                targets.push(one);
+                let two = Square::try_from((file, rank + 2 * forward));
+                if let Ok(two) = two {
+                    if rank == start_rank && self.piece(two).is_none() {
+                        targets.push(two);
+                    }
+                }
+            }
+        }
+        for df in [-1, 1] {
+            let Ok(to) = Square::try_from((file + df, rank + forward)) else {
+                continue;
+            };
+            if self.piece(to).is_some_and(|piece| piece.color != color) || self.en_passant == Some(to) {
+                targets.push(to);
+            }
+        }
+        targets
+    }
+
+    /// The board after the piece on `from` moves to `to`, taking en passant if it can.
+    fn board_after(&self, from: Square, to: Square) -> Board {
+        let mut board = self.board;
+        let piece = board[from.rank() as usize][from.file() as usize].take();
+        if piece.is_some_and(|piece| piece.kind == PieceType::Pawn) && self.en_passant == Some(to) {
+            // The pawn that is taken is beside the one taking it.
+            board[from.rank() as usize][to.file() as usize] = None;
+        }
+        board[to.rank() as usize][to.file() as usize] = piece;
+        board
+    }
+
+    /// The moves the piece on `from` can make without leaving its own king in check.
+    fn legal_moves(&self, from: Square) -> Vec<Move> {
+        let Some(piece) = self.piece(from).filter(|piece| piece.color == self.turn) else {
+            return vec![];
+        };
+        let mut moves: Vec<Move> = self.targets(from, piece).into_iter()
+            .filter(|&to| !attacks::is_in_check(&self.board_after(from, to), self.turn))
+            .map(|to| Move::Normal { from, to })
+            .collect();
+        if piece.kind == PieceType::King {
+            for side in [CastlingSide::KingSide, CastlingSide::QueenSide] {
+                if self.can_castle(side) {
+                    moves.push(Move::Castle { side });
+                }
+            }
+        }
+        moves
+    }
+
+    fn can_castle(&self, side: CastlingSide) -> bool {
+        let color = self.turn;
+        if !self.castling[castling_index(color, side)] || attacks::is_in_check(&self.board, color) {
+            return false;
+        }
+        let (king_from, king_to) = side.king_squares(color);
+        let (rook_from, _) = rook_squares(side, color);
+        let rank = king_from.rank();
+        let (low, high) = (king_from.file().min(rook_from.file()), king_from.file().max(rook_from.file()));
+        let between_is_empty = (low + 1..high)
+            .all(|file| self.board[rank as usize][file as usize].is_none());
+        // The king may not pass through or land on an attacked square.
+        let step = (king_to.file() - king_from.file()).signum();
+        let path_is_safe = [king_from.file() + step, king_to.file()].into_iter()
+            .all(|file| !attacks::is_attacked(&self.board, file, rank, color.opposite()));
+        between_is_empty && path_is_safe
+    }
+
+    fn has_legal_moves(&self) -> bool {
+        (0..8).flat_map(|rank| (0..8).filter_map(move |file| Square::try_from((file, rank)).ok()))
+            .any(|square| !self.legal_moves(square).is_empty())
+    }
+
+    /// Castling is no longer allowed with a king or rook that moves away from or is taken
+    /// on its starting square.
+    fn update_castling(&mut self, touched: Square) {
+        for color in [Color::White, Color::Black] {
+            for side in [CastlingSide::KingSide, CastlingSide::QueenSide] {
+                if touched == side.king_squares(color).0 || touched == rook_squares(side, color).0 {
+                    self.castling[castling_index(color, side)] = false;
+                }
+            }
+        }
+    }
+}
+
+impl Default for BuiltinGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl bridge::ChessGame for BuiltinGame {
+    fn update(&mut self) {
+
+    }
+
+    fn get_pieces(&self) -> Board {
+        self.board
+    }
+
+    fn get_piece(&self, at: Square) -> Option<Piece> {
+        self.piece(at)
+    }
+
+    fn get_state(&self) -> GameState {
+        if self.is_check() {
+            GameState::Check
+        } else {
+            GameState::Normal
+        }
+    }
+
+    fn is_check(&self) -> bool {
+        attacks::is_in_check(&self.board, self.turn)
+    }
+
+    fn current_turn(&self) -> Color {
+        self.turn
+    }
+
+    fn promote(&mut self, promotion_square: Square, piece: PieceType) {
+        let pawn = self.piece(promotion_square).expect("Expected piece to promote");
+        self.set_piece(promotion_square, Some(Piece { kind: piece, color: pawn.color }));
+    }
+
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        match self.piece(at) {
+            None => Err(MoveError::new("No piece there")),
+            Some(piece) if piece.color != self.turn => Err(MoveError::new("Not your piece")),
+            Some(_) => Ok(self.legal_moves(at)),
+        }
+    }
+
+    fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        let from = match mv {
+            Move::Normal { from, .. } => from,
+            Move::Castle { side } => side.king_squares(self.turn).0,
+        };
+        if !self.possible_moves(from)?.contains(&mv) {
+            return Err(MoveError::new("Illegal move"));
+        }
+        let mut en_passant = None;
+        match mv {
+            Move::Normal { from, to } => {
+                let piece = self.piece(from);
+                if piece.is_some_and(|piece| piece.kind == PieceType::Pawn) && (to.rank() - from.rank()).abs() == 2 {
+                    en_passant = Square::try_from((from.file(), (from.rank() + to.rank()) / 2)).ok();
+                }
+                self.board = self.board_after(from, to);
+                self.update_castling(from);
+                self.update_castling(to);
+            }
+            Move::Castle { side } => {
+                let (king_from, king_to) = side.king_squares(self.turn);
+                let (rook_from, rook_to) = rook_squares(side, self.turn);
+                let (king, rook) = (self.piece(king_from), self.piece(rook_from));
+                self.set_piece(king_from, None);
+                self.set_piece(rook_from, None);
+                self.set_piece(king_to, king);
+                self.set_piece(rook_to, rook);
+                self.update_castling(king_from);
+            }
+        }
+        self.en_passant = en_passant;
+        self.turn = self.turn.opposite();
+        Ok(())
+    }
+
+    fn can_play_right_now(&self) -> bool {
+        // Local games control both sides
+        true
+    }
+
+    fn has_possible_moves(&self) -> bool {
+        true
+    }
+
+    fn is_over(&self) -> bool {
+        !self.has_legal_moves()
+    }
+}
+
+fn castling_index(color: Color, side: CastlingSide) -> usize {
+    let color_index = match color {
+        Color::White => 0,
+        Color::Black => 2,
+    };
+    color_index + usize::from(side == CastlingSide::QueenSide)
+}
+
+/// The squares the rook of `color` moves from and to when castling on `side`.
+fn rook_squares(side: CastlingSide, color: Color) -> (Square, Square) {
+    let rank = match color {
+        Color::White => 0,
+        Color::Black => 7,
+    };
+    let (from_file, to_file) = match side {
+        CastlingSide::KingSide => (7, 5),
+        CastlingSide::QueenSide => (0, 3),
+    };
+    (Square::try_from((from_file, rank)).unwrap(), Square::try_from((to_file, rank)).unwrap())
+}
@@ -1,50 +1,359 @@
-use std::io::Write;
-use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
 use chess_network_protocol::{ClientToServerHandshake, Color as ProtocolColor, Joever, Piece as ProtocolPiece, Move as ProtocolMove, ServerToClient, ServerToClientHandshake, Features, ClientToServer};
-use erikfran_chess::{Color, Move, MoveError, Piece, PieceTypes};
-use erikfran_chess::util::{BoardMove, Rows, Square};
-use crate::bridge::{ChessGame, GameState};
+use serde::Serialize;
+use crate::attacks;
+use crate::backend::Backend;
+use crate::bridge::{self, Board, ChessGame, Color, GameState, Move, MoveError, Piece, PieceType, PlayedMove, Square};
+use crate::extension::{self, ExtensionMessage, Incoming};
 use crate::json_tcp_stream::JsonTcpStream;
-use crate::server::convert_move;
+use crate::logging;
+use crate::notation;
+use crate::saved_game::SavedSession;
+use crate::session::{ChatMessage, Decision, DrawState, GameSession, RematchState, TakebackState};
+use crate::server::{convert_move, convert_promotion, parse_move, promotion_piece, protocol_square};
+use crate::tls::Transport;
 
 pub struct ClientGame {
     socket: JsonTcpStream,
-    board: [[Option<Piece>; 8]; 8],
+    server_addr: SocketAddr,
+    /// The rules engine used when the server doesn't tell us the possible moves.
+    backend: Backend,
+    board: Board,
+    /// The board as the server last sent it, without a move of ours it hasn't answered yet.
+    server_board: Board,
     joever: Joever,
     moves: Vec<ProtocolMove>,
+    /// The color we play. We ask to play white, but it may change in a rematch.
+    color: Color,
+    /// Whose turn it is according to the last message from the server.
     current_turn: Color,
+    /// A move we have sent that the server hasn't answered yet. It is shown on the board
+    /// right away and reverted if the server rejects it.
+    pending_move: Option<Move>,
+    /// A pawn move to the last rank, which is sent once the piece to promote to has been
+    /// chosen.
+    unsent_promotion: Option<ProtocolMove>,
+    /// An error from the server that hasn't been shown yet.
+    error: Option<String>,
+    /// Added to the length of the history to count the moves played, for games that were
+    /// joined with black to move and no history.
+    ply_offset: usize,
+    /// Why we think we disagree with the server about the game, if we do.
+    sync_problem: Option<String>,
+    /// Whether we have asked the server for the whole game and are waiting for it.
+    resync_requested: bool,
     handshaking: bool,
     server_features: Vec<Features>,
+    /// The extensions both we and the server understand.
+    extensions: Vec<String>,
+    history: Vec<PlayedMove>,
+    takeback: TakebackState,
+    chat: Vec<ChatMessage>,
+    rematch: RematchState,
+    rematches: usize,
+    /// Used to generate moves when the server doesn't send them.
+    hint_game: Option<HintGame>,
+    /// Whether we have offered a draw since the last move. The server doesn't tell us
+    /// about its offers.
+    draw: DrawState,
+    /// Set when the server says the game was resigned or drawn by agreement.
+    decision: Option<Decision>,
+    /// Whether the side to move had no moves in the last position the server sent.
+    out_of_moves: bool,
 }
 
-impl ClientGame {
-    pub fn connect(addr: Ipv4Addr, port: u16) -> Self {
-        let socket_addr = SocketAddrV4::new(addr, port);
+/// Our rules engine with the history we know played, kept until the history or the board
+/// changes.
+struct HintGame {
+    history: Vec<PlayedMove>,
+    board: Board,
+    /// None if the history doesn't lead to the board.
+    game: Option<Box<dyn ChessGame>>,
+}
 
-        println!("Connecting to server...");
-        let mut socket = TcpStream::connect(socket_addr).unwrap();
-        socket.set_nonblocking(true).unwrap();
+impl ClientGame {
+    /// Connect to the server at `address`. If it resolves to several addresses, such as
+    /// a hostname with both an IPv4 and an IPv6 address, they are tried in order.
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self, String> {
+        let (socket, socket_addr) = open(address)?;
+        Ok(Self::start(JsonTcpStream::new(socket), socket_addr))
+    }
 
-        println!("Sending handshake...");
-        let handshake = ClientToServerHandshake {
-            server_color: ProtocolColor::Black,
-        };
-        serde_json::to_writer(&socket, &handshake).unwrap();
-        socket.flush().unwrap();
+    /// Connect to a server that requires TLS. The server's certificate is checked with
+    /// `config`, see [`tls::client_config`](crate::tls::client_config).
+    pub fn connect_tls(address: impl ToSocketAddrs, config: Arc<rustls::ClientConfig>) -> Result<Self, String> {
+        let (socket, socket_addr) = open(address)?;
+        log::info!(target: logging::NETWORK, "Starting TLS with {socket_addr}...");
+        let transport = Transport::connect(socket, config).map_err(|err| format!("TLS handshake with {socket_addr} failed: {err}"))?;
+        Ok(Self::start(JsonTcpStream::with_transport(transport), socket_addr))
+    }
 
-        Self {
-            socket: JsonTcpStream::new(socket),
+    fn start(socket: JsonTcpStream, socket_addr: SocketAddr) -> Self {
+        let mut client = Self {
+            socket,
+            server_addr: socket_addr,
+            backend: Backend::default(),
             board: [[None; 8]; 8],
+            server_board: [[None; 8]; 8],
             joever: Joever::Ongoing,
             moves: vec![],
+            color: Color::White,
             current_turn: Color::White,
+            pending_move: None,
+            unsent_promotion: None,
+            error: None,
+            ply_offset: 0,
+            sync_problem: None,
+            resync_requested: false,
             handshaking: true,
             server_features: vec![],
+            extensions: vec![],
+            history: vec![],
+            takeback: TakebackState::None,
+            chat: vec![],
+            rematch: RematchState::None,
+            rematches: 0,
+            hint_game: None,
+            draw: DrawState::None,
+            decision: None,
+            out_of_moves: false,
+        };
+
+        log::debug!(target: logging::NETWORK, "Sending handshake");
+        let handshake = ClientToServerHandshake {
+            server_color: ProtocolColor::Black,
+        };
+        client.send(&handshake);
+        client
+    }
+
+    /// Rejoin a game where `history` has been played. The server sends the position in
+    /// the handshake, so the history is only needed for the move list and counting moves.
+    pub fn with_history(mut self, history: Vec<PlayedMove>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Use `backend` to check our moves when the server doesn't tell us the possible moves.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// The fingerprint of the server's certificate if the connection is encrypted.
+    pub fn server_fingerprint(&self) -> Option<String> {
+        self.socket.server_fingerprint()
+    }
+
+    /// Whether the server has answered the handshake.
+    pub fn is_ready(&self) -> bool {
+        !self.handshaking
+    }
+
+    /// The board after `mv`, shown until the server answers.
+    fn board_after(&self, mv: Move) -> Board {
+        let mut game = self.backend.seeded(&self.board, self.current_turn);
+        if game.perform_move(mv).is_ok() {
+            return game.get_pieces();
+        }
+        // Our rules engine disagrees with the server about the move, so just move the piece.
+        let mut board = self.board;
+        if let Move::Normal { from, to } = mv {
+            let piece = board[from.rank() as usize][from.file() as usize].take();
+            board[to.rank() as usize][to.file() as usize] = piece;
+        }
+        board
+    }
+
+    /// Our rules engine with the history played, for generating moves when the server
+    /// doesn't send them. This is none when the history doesn't lead to the server's board,
+    /// like when we joined a game in progress.
+    fn hint_game(&mut self) -> Option<&mut Box<dyn ChessGame>> {
+        if self.pending_move.is_some() || self.ply_offset != 0 {
+            return None;
+        }
+        if !self.hint_game.as_ref().is_some_and(|hints| hints.history == self.history && hints.board == self.board) {
+            let mut game = self.backend.new_game();
+            let replayed = bridge::try_replay_onto(&mut game, &self.history).is_ok();
+            let game = (replayed && game.get_pieces() == self.board).then_some(game);
+            self.hint_game = Some(HintGame { history: self.history.clone(), board: self.board, game });
         }
+        self.hint_game.as_mut().and_then(|hints| hints.game.as_mut())
+    }
+
+    /// The color whose turn it is according to the number of moves played.
+    fn turn_from_move_count(&self) -> Color {
+        bridge::color_of_ply(self.history.len() + self.ply_offset)
+    }
+
+    /// Work out whose turn it is from the board and moves the server sent, and check that
+    /// it agrees with the number of moves played. `move_made` is the move the board is the
+    /// result of, if any.
+    fn update_turn(&mut self, move_made: Option<ProtocolMove>) {
+        let by_move_count = self.turn_from_move_count();
+        let mover = move_made.map(|mv| piece_at(&self.board, mv.end_x, mv.end_y).map(|piece| piece.color));
+        let turn = match mover {
+            Some(Some(mover)) => mover.opposite(),
+            _ => side_to_move(&self.board, &self.moves).unwrap_or(by_move_count),
+        };
+
+        let problem = if let (Some(mv), Some(None)) = (move_made, mover) {
+            Some(format!(
+                "The server says a move was made to ({}, {}) but there is no piece there",
+                mv.end_x, mv.end_y,
+            ))
+        } else if turn != by_move_count {
+            let problem = format!(
+                "After {} moves it should be {}'s turn, but the server's board says it is {}'s",
+                self.history.len() + self.ply_offset,
+                color_name(by_move_count),
+                color_name(turn),
+            );
+            // Count the moves from the server's board from now on, so that a missed move is
+            // only reported once.
+            self.ply_offset = ply_offset(self.history.len(), turn);
+            Some(problem)
+        } else {
+            None
+        };
+        if let Some(problem) = &problem {
+            log::warn!(target: logging::NETWORK, "Out of sync with the server: {problem}");
+        }
+        self.sync_problem = problem;
+        // The server's board is what counts.
+        self.current_turn = turn;
+        self.out_of_moves = !bridge::has_legal_moves(self);
     }
 
     fn set_board(&mut self, board: [[chess_network_protocol::Piece; 8]; 8]) {
-        self.board = board.map(|row| row.map(|piece| convert_piece(piece)));
+        self.board = board.map(|row| row.map(convert_piece));
+        self.server_board = self.board;
+    }
+
+    fn send_extension(&mut self, message: ExtensionMessage) {
+        self.send(&message);
+    }
+
+    /// Send `message` to the server. If that fails the connection is closed and the error
+    /// is logged, and nothing more is sent.
+    fn send<T: Serialize>(&mut self, message: &T) {
+        if self.socket.is_closed() {
+            return;
+        }
+        if let Err(err) = self.socket.write(message) {
+            log::warn!(target: logging::NETWORK, "Lost the connection to the server: {err}");
+        }
+    }
+
+    fn supports(&self, extension: &str) -> bool {
+        self.extensions.iter().any(|name| name == extension)
+    }
+
+    /// The server says the game ended by `decision`, with `board` as the final position.
+    fn end_by(&mut self, decision: Decision, board: [[ProtocolPiece; 8]; 8], moves: Vec<ProtocolMove>) {
+        self.pending_move = None;
+        self.unsent_promotion = None;
+        self.set_board(board);
+        self.moves = moves;
+        self.draw = DrawState::None;
+        self.decision = Some(decision);
+        self.update_turn(None);
+        log::info!(target: logging::NETWORK, "The game ended: {decision:?}");
+    }
+
+    fn handle_extension(&mut self, message: ExtensionMessage) {
+        match message {
+            ExtensionMessage::Hello { .. } => {
+                // Only sent by clients.
+            }
+            ExtensionMessage::TakebackRequest => {
+                // When both sides ask at the same time the server takes back both moves
+                // and answers with TakebackPerformed.
+                if self.takeback != TakebackState::Requested {
+                    self.takeback = TakebackState::Offered;
+                }
+            }
+            ExtensionMessage::TakebackResponse { .. } => {
+                // An accepted takeback is answered with TakebackPerformed instead.
+                self.takeback = TakebackState::None;
+            }
+            ExtensionMessage::PositionHash { plies, hash } => {
+                if self.pending_move.is_some() {
+                    // Our board shows a move the server hasn't answered yet.
+                    return;
+                }
+                let our_plies = self.history.len() + self.ply_offset;
+                let our_hash = extension::position_hash(&self.board, self.current_turn);
+                self.send_extension(ExtensionMessage::PositionHash { plies: our_plies, hash: our_hash });
+                if (plies, hash) != (our_plies, our_hash) && !self.resync_requested {
+                    log::warn!(target: logging::NETWORK, "Our position differs from the server's after {plies} half-moves, asking for a resync");
+                    self.sync_problem = Some(String::from("Our board differs from the server's. Waiting for the whole game to be sent again."));
+                    self.resync_requested = true;
+                    self.send_extension(ExtensionMessage::ResyncRequest);
+                }
+            }
+            ExtensionMessage::ResyncRequest => {
+                // Only sent by clients.
+            }
+            ExtensionMessage::Resync { board, moves, history } => {
+                self.set_board(board);
+                self.moves = moves;
+                match history.iter().map(|name| notation::parse_move(name)).collect::<Option<Vec<_>>>() {
+                    Some(history) => {
+                        self.history = history;
+                        self.ply_offset = 0;
+                    }
+                    None => log::warn!(target: logging::NETWORK, "Could not read the history in the resync, keeping ours"),
+                }
+                self.resync_requested = false;
+                self.update_turn(None);
+            }
+            ExtensionMessage::TakebackPerformed { plies, board, moves } => {
+                self.set_board(board);
+                self.moves = moves;
+                self.history.truncate(self.history.len().saturating_sub(plies));
+                self.update_turn(None);
+                self.takeback = TakebackState::None;
+                // Whatever ended the game has been taken back too.
+                self.joever = Joever::Ongoing;
+                self.draw = DrawState::None;
+                self.decision = None;
+            }
+            ExtensionMessage::Chat { text } => {
+                if let Some(text) = extension::clean_chat(&text) {
+                    self.chat.push(ChatMessage { ours: false, text });
+                }
+            }
+            ExtensionMessage::RematchOffer { swap_colors } => {
+                self.rematch = RematchState::Offered { swap_colors };
+            }
+            ExtensionMessage::RematchResponse { .. } => {
+                // An accepted rematch is answered with RematchStarted instead.
+                self.rematch = RematchState::None;
+            }
+            ExtensionMessage::RematchStarted { server_color, board, moves } => {
+                self.color = match server_color {
+                    ProtocolColor::White => Color::Black,
+                    ProtocolColor::Black => Color::White,
+                };
+                self.set_board(board);
+                self.moves = moves;
+                self.joever = Joever::Ongoing;
+                self.history.clear();
+                self.ply_offset = 0;
+                self.pending_move = None;
+                self.unsent_promotion = None;
+                self.sync_problem = None;
+                self.resync_requested = false;
+                self.takeback = TakebackState::None;
+                self.rematch = RematchState::None;
+                self.rematches += 1;
+                self.draw = DrawState::None;
+                self.decision = None;
+                self.update_turn(None);
+            }
+        }
     }
 }
 
@@ -59,121 +368,398 @@ impl ChessGame for ClientGame {
             self.set_board(handshake.board);
             self.joever = handshake.joever;
             self.moves = handshake.moves;
+            // We may be joining a game in progress. Count the moves from here on.
+            let turn = side_to_move(&self.board, &self.moves).unwrap_or(self.turn_from_move_count());
+            self.ply_offset = ply_offset(self.history.len(), turn);
+            self.extensions = extension::common_extensions(&handshake.features);
             self.server_features = handshake.features;
+            self.update_turn(None);
             self.handshaking = false;
 
+            if !self.extensions.is_empty() {
+                let hello = ExtensionMessage::Hello { extensions: self.extensions.clone() };
+                self.send_extension(hello);
+            }
+
             return;
         }
         let packet: ServerToClient = match self.socket.read() {
-            Some(packet) => packet,
+            Some(Incoming::Protocol(packet)) => packet,
+            Some(Incoming::Extension(message)) => {
+                self.handle_extension(message);
+                return;
+            }
             None => return,
         };
 
         match packet {
-            ServerToClient::State { board, moves, joever, .. } => {
+            ServerToClient::State { board, moves, joever, move_made } => {
+                let (Some(from), Some(to)) = (
+                    protocol_square(move_made.start_x, move_made.start_y),
+                    protocol_square(move_made.end_x, move_made.end_y),
+                ) else {
+                    log::warn!(target: logging::NETWORK, "Ignoring a state for a move off the board: {move_made:?}");
+                    return;
+                };
+                // The piece has already moved, so it is found where the move ends.
+                let moved = convert_piece(board[to.rank() as usize][to.file() as usize]);
+                let mv = parse_move(move_made, moved).unwrap_or(Move::Normal { from, to });
+                let promotion = promotion_piece(move_made.promotion);
+                // The same move can't be made twice in a row since the square it was made
+                // from is empty afterwards, so this is the same State sent again.
+                match self.history.last_mut() {
+                    Some(last) if last.mv == mv && promotion.is_some() && last.promotion != promotion => {
+                        // Sent again once the opponent chose what to promote to.
+                        last.promotion = promotion;
+                    }
+                    Some(last) if last.mv == mv => {
+                        log::warn!(target: logging::NETWORK, "Ignoring a repeated state for the move {move_made:?}");
+                    }
+                    _ => self.history.push(PlayedMove { mv, promotion }),
+                }
+                self.pending_move = None;
+                self.unsent_promotion = None;
                 self.set_board(board);
                 self.moves = moves;
                 self.joever = joever;
-                // If it was the server's turn and the server sent State it means the server
-                // has made its move. If it was our turn and we just made a move, State means
-                // that the move was accepted and its now the server's turn.
-                self.current_turn = self.current_turn.opposite();
+                self.draw = DrawState::None;
+                self.update_turn(Some(move_made));
             }
             ServerToClient::Error { board, moves, joever, message } => {
-                // The server rejected out move. This means we need to make a move again.
-                self.current_turn = Color::White;
+                // The server rejected our move. Going back to the server's board takes the
+                // move back, and it is up to whoever has pieces that can move on that board.
+                self.pending_move = None;
+                self.unsent_promotion = None;
                 self.set_board(board);
                 self.moves = moves;
                 self.joever = joever;
-                println!("error message = {}", message);
+                self.update_turn(None);
+                log::warn!(target: logging::NETWORK, "The server rejected our move: {message}");
+                self.error = Some(message);
+            }
+            ServerToClient::Resigned { board, joever } => {
+                let loser = match joever {
+                    Joever::White => Color::Black,
+                    Joever::Black => Color::White,
+                    _ => {
+                        log::warn!(target: logging::NETWORK, "Ignoring a resignation without a winner: {joever:?}");
+                        return;
+                    }
+                };
+                self.end_by(Decision::Resignation(loser), board, vec![]);
+                self.joever = joever;
+            }
+            ServerToClient::Draw { board, moves } => {
+                self.end_by(Decision::DrawAgreed, board, moves);
+                self.joever = Joever::Draw;
             }
-            ServerToClient::Resigned { .. } => {}
-            ServerToClient::Draw { .. } => {}
         }
     }
 
-    fn get_pieces(&self) -> [[Option<Piece>; 8]; 8] {
+    fn get_pieces(&self) -> Board {
         self.board
     }
 
     fn get_piece(&self, at: Square) -> Option<Piece> {
-        let file: i32 = at.file.into();
-        let rank: i32 = at.rank.into();
-        self.board[rank as usize][file as usize]
+        self.board[at.rank() as usize][at.file() as usize]
     }
 
     fn get_state(&self) -> GameState {
-        GameState::Normal
+        if self.is_check() {
+            GameState::Check
+        } else {
+            GameState::Normal
+        }
     }
 
     fn is_check(&self) -> bool {
-        false
+        // The server doesn't tell us about check, so look at its board ourselves. Our
+        // pending move isn't on that board, so it is still the turn the server said.
+        attacks::is_in_check(&self.server_board, self.current_turn)
     }
 
     fn current_turn(&self) -> Color {
-        self.current_turn
+        match self.pending_move {
+            // Until the server says otherwise we assume the move was fine.
+            Some(_) => self.current_turn.opposite(),
+            None => self.current_turn,
+        }
     }
 
-    fn promote(&mut self, _promotion_square: Square, _piece: PieceTypes) {
-
+    fn promote(&mut self, promotion_square: Square, piece: PieceType) {
+        let Some(mv) = self.unsent_promotion.take() else {
+            return;
+        };
+        let packet = ClientToServer::Move(ProtocolMove { promotion: convert_promotion(Some(piece), self.color), ..mv });
+        self.send(&packet);
+        self.board[promotion_square.rank() as usize][promotion_square.file() as usize] = Some(Piece { kind: piece, color: self.color });
     }
 
-    fn possible_moves(&mut self, at: Square) -> Result<(BoardMove, Vec<Move>), MoveError> {
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
         if !self.server_features.contains(&Features::PossibleMoveGeneration) {
-            let mut board_move = BoardMove { rows: Rows { squares: [Rows { squares: [None; 8] }; 8] } };
-            for file in 0..8 {
-                for rank in 0..8 {
-                    let square = (file, rank).try_into().unwrap();
-                    board_move[square] = Some(Move::Normal {
-                        from: at,
-                        to: square,
-                    });
-                }
+            // The server won't tell us, so ask our own rules engine instead.
+            if let Some(game) = self.hint_game() {
+                return game.possible_moves(at);
             }
-            return Ok((board_move, vec![]));
+            // Castling rights and en passant can't be seen on the board, so leave out
+            // castling rather than guess.
+            let mut moves = self.backend.seeded(&self.board, self.current_turn).possible_moves(at)?;
+            moves.retain(|mv| !matches!(mv, Move::Castle { .. }));
+            return Ok(moves);
         }
-        let mut board_move = BoardMove { rows: Rows { squares: [Rows { squares: [None; 8] }; 8] } };
-        for mv in &self.moves {
-            let from: Square = (mv.start_x as i32, mv.start_y as i32).try_into().unwrap();
-            if from == at {
-                let to: Square = (mv.end_x as i32, mv.end_y as i32).try_into().unwrap();
-                board_move[to] = Some(Move::Normal { from, to })
-            }
-        }
-
-        Ok((board_move, vec![]))
+        let moves = self.moves.iter()
+            .filter(|mv| protocol_square(mv.start_x, mv.start_y) == Some(at))
+            // Moves off the board can't be made, so leave them out.
+            .filter_map(|mv| protocol_square(mv.end_x, mv.end_y))
+            .map(|to| Move::Normal { from: at, to })
+            .collect();
+        Ok(moves)
     }
 
     fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
-        let packet = ClientToServer::Move(convert_move(mv));
-
-        serde_json::to_writer(self.socket.stream(), &packet).unwrap();
+        // Castling may have been chosen as the king's move from the server's list.
+        let mv = match mv {
+            Move::Normal { from, .. } => parse_move(convert_move(mv, self.color), self.get_piece(from)).unwrap_or(mv),
+            Move::Castle { .. } => mv,
+        };
+        // Our rules engine is only used for hints. The server may know rules it doesn't,
+        // so the server decides whether the move is legal.
+        let packet_move = convert_move(mv, self.color);
+        if bridge::is_promotion(&self.board, mv) {
+            self.unsent_promotion = Some(packet_move);
+        } else {
+            self.send(&ClientToServer::Move(packet_move));
+        }
+        self.board = self.board_after(mv);
+        self.pending_move = Some(mv);
         Ok(())
     }
 
     fn can_play_right_now(&self) -> bool {
-        self.current_turn == Color::White
+        self.pending_move.is_none() && self.current_turn == self.color
     }
 
     fn has_possible_moves(&self) -> bool {
-        self.server_features.contains(&Features::PossibleMoveGeneration)
+        // Either the server sends them or we generate them ourselves.
+        true
+    }
+
+    fn is_over(&self) -> bool {
+        if self.pending_move.is_some() {
+            // The server hasn't told us the moves the opponent can make yet.
+            return false;
+        }
+        !matches!(self.joever, Joever::Ongoing) || self.out_of_moves
+    }
+}
+
+impl GameSession for ClientGame {
+    fn moves_are_hints(&self) -> bool {
+        // Moves we generate ourselves may be wrong about the server's rules.
+        !self.server_features.contains(&Features::PossibleMoveGeneration)
+    }
+
+    fn player_color(&self) -> Option<Color> {
+        Some(self.color)
+    }
+
+    fn history(&self) -> &[PlayedMove] {
+        &self.history
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    fn sync_problem(&self) -> Option<&str> {
+        self.sync_problem.as_deref()
+    }
+
+    fn request_takeback(&mut self) {
+        if self.takeback != TakebackState::None || !self.supports(extension::TAKEBACK) {
+            return;
+        }
+        self.takeback = TakebackState::Requested;
+        self.send_extension(ExtensionMessage::TakebackRequest);
+    }
+
+    fn takeback_state(&self) -> TakebackState {
+        self.takeback
+    }
+
+    fn answer_takeback(&mut self, accept: bool) {
+        if self.takeback != TakebackState::Offered {
+            return;
+        }
+        // If accepted the server answers with the position after the takeback.
+        self.takeback = TakebackState::None;
+        self.send_extension(ExtensionMessage::TakebackResponse { accepted: accept });
+    }
+
+    fn supports_chat(&self) -> bool {
+        self.supports(extension::CHAT)
+    }
+
+    fn send_chat(&mut self, text: &str) {
+        let Some(text) = extension::clean_chat(text) else {
+            return;
+        };
+        if !self.supports_chat() {
+            return;
+        }
+        self.send_extension(ExtensionMessage::Chat { text: text.clone() });
+        self.chat.push(ChatMessage { ours: true, text });
+    }
+
+    fn chat_messages(&self) -> &[ChatMessage] {
+        &self.chat
+    }
+
+    fn offer_rematch(&mut self, swap_colors: bool) {
+        if self.rematch != RematchState::None || !self.supports(extension::REMATCH) {
+            return;
+        }
+        self.rematch = RematchState::Requested { swap_colors };
+        self.send_extension(ExtensionMessage::RematchOffer { swap_colors });
+    }
+
+    fn rematch_state(&self) -> RematchState {
+        self.rematch
+    }
+
+    fn answer_rematch(&mut self, accept: bool) {
+        if !matches!(self.rematch, RematchState::Offered { .. }) {
+            return;
+        }
+        // If accepted the server answers with the new game.
+        self.rematch = RematchState::None;
+        self.send_extension(ExtensionMessage::RematchResponse { accepted: accept });
+    }
+
+    fn rematches(&self) -> usize {
+        self.rematches
+    }
+
+    fn resign(&mut self) {
+        if self.is_over() {
+            return;
+        }
+        // The server answers with Resigned.
+        self.send(&ClientToServer::Resign);
+    }
+
+    fn offer_draw(&mut self) {
+        if self.draw != DrawState::None || self.is_over() {
+            return;
+        }
+        // The server answers with Draw if it has offered one too, or once it accepts.
+        self.draw = DrawState::Requested;
+        self.send(&ClientToServer::Draw);
+    }
+
+    fn draw_state(&self) -> DrawState {
+        self.draw
+    }
+
+    fn decision(&self) -> Option<Decision> {
+        self.decision
+    }
+
+    fn saved_session(&self) -> Option<SavedSession> {
+        Some(SavedSession::Client {
+            address: self.server_addr.ip().to_string(),
+            port: self.server_addr.port(),
+            tls_fingerprint: self.server_fingerprint(),
+        })
+    }
+}
+
+/// Parse an address typed by the user: an IPv4 or IPv6 address or a hostname, optionally
+/// followed by a port like `example.com:9000` or `[::1]:9000`. Without a port,
+/// `default_port` is used. Hostnames are looked up, which may give several addresses.
+pub fn parse_address(input: &str, default_port: u16) -> Result<Vec<SocketAddr>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(String::from("No address was given"));
+    }
+    let (host, port) = if let Ok(addr) = input.parse::<SocketAddr>() {
+        return Ok(vec![addr]);
+    } else if let Ok(ip) = input.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, default_port)]);
+    } else if let Some(ip) = input.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        let ip = ip.parse::<IpAddr>().map_err(|_| format!("Invalid IPv6 address: {ip}"))?;
+        return Ok(vec![SocketAddr::new(ip, default_port)]);
+    } else if let Some((host, port)) = input.rsplit_once(':') {
+        (host, port.parse().map_err(|_| format!("Invalid port: {port}"))?)
+    } else {
+        (input, default_port)
+    };
+
+    let addrs: Vec<SocketAddr> = (host, port).to_socket_addrs()
+        .map_err(|err| format!("Could not find {host}: {err}"))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Could not find {host}"));
+    }
+    Ok(addrs)
+}
+
+/// Connect to the first of the addresses that answers.
+fn open(address: impl ToSocketAddrs) -> Result<(TcpStream, SocketAddr), String> {
+    let addrs = address.to_socket_addrs().map_err(|err| format!("Invalid address: {err}"))?;
+    let mut last_error = String::from("No address was given");
+    for addr in addrs {
+        log::info!(target: logging::NETWORK, "Connecting to {addr}...");
+        match TcpStream::connect(addr) {
+            Ok(socket) => return Ok((socket, addr)),
+            Err(err) => {
+                log::info!(target: logging::NETWORK, "Could not connect to {addr}: {err}");
+                last_error = format!("Failed to connect to {addr}: {err}");
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// The color of the pieces that can make the moves in `moves`, or none if there are no moves.
+fn side_to_move(board: &Board, moves: &[ProtocolMove]) -> Option<Color> {
+    moves.iter()
+        .find_map(|mv| piece_at(board, mv.start_x, mv.start_y))
+        .map(|piece| piece.color)
+}
+
+/// The piece at `(x, y)` in a move from the network, or none if that is off the board.
+fn piece_at(board: &Board, x: usize, y: usize) -> Option<Piece> {
+    protocol_square(x, y).and_then(|square| board[square.rank() as usize][square.file() as usize])
+}
+
+/// The number of half-moves to add to `history_len` so that the count says it is `turn`'s
+/// turn, as the server's board does. Only whether it is odd matters.
+fn ply_offset(history_len: usize, turn: Color) -> usize {
+    usize::from(bridge::color_of_ply(history_len) != turn)
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
     }
 }
 
 fn convert_piece(protocol_piece: ProtocolPiece) -> Option<Piece> {
     Some(match protocol_piece {
-        ProtocolPiece::BlackPawn => Piece { piece: PieceTypes::Pawn(false), color: Color::Black },
-        ProtocolPiece::BlackKnight => Piece { piece: PieceTypes::Knight, color: Color::Black },
-        ProtocolPiece::BlackBishop => Piece { piece: PieceTypes::Bishop, color: Color::Black },
-        ProtocolPiece::BlackRook => Piece { piece: PieceTypes::Rook, color: Color::Black },
-        ProtocolPiece::BlackQueen => Piece { piece: PieceTypes::Queen, color: Color::Black },
-        ProtocolPiece::BlackKing => Piece { piece: PieceTypes::King, color: Color::Black },
-        ProtocolPiece::WhitePawn => Piece { piece: PieceTypes::Pawn(false), color: Color::White },
-        ProtocolPiece::WhiteKnight => Piece { piece: PieceTypes::Knight, color: Color::White },
-        ProtocolPiece::WhiteBishop => Piece { piece: PieceTypes::Bishop, color: Color::White },
-        ProtocolPiece::WhiteRook => Piece { piece: PieceTypes::Rook, color: Color::White },
-        ProtocolPiece::WhiteQueen => Piece { piece: PieceTypes::Queen, color: Color::White },
-        ProtocolPiece::WhiteKing => Piece { piece: PieceTypes::King, color: Color::White },
+        ProtocolPiece::BlackPawn => Piece { kind: PieceType::Pawn, color: Color::Black },
+        ProtocolPiece::BlackKnight => Piece { kind: PieceType::Knight, color: Color::Black },
+        ProtocolPiece::BlackBishop => Piece { kind: PieceType::Bishop, color: Color::Black },
+        ProtocolPiece::BlackRook => Piece { kind: PieceType::Rook, color: Color::Black },
+        ProtocolPiece::BlackQueen => Piece { kind: PieceType::Queen, color: Color::Black },
+        ProtocolPiece::BlackKing => Piece { kind: PieceType::King, color: Color::Black },
+        ProtocolPiece::WhitePawn => Piece { kind: PieceType::Pawn, color: Color::White },
+        ProtocolPiece::WhiteKnight => Piece { kind: PieceType::Knight, color: Color::White },
+        ProtocolPiece::WhiteBishop => Piece { kind: PieceType::Bishop, color: Color::White },
+        ProtocolPiece::WhiteRook => Piece { kind: PieceType::Rook, color: Color::White },
+        ProtocolPiece::WhiteQueen => Piece { kind: PieceType::Queen, color: Color::White },
+        ProtocolPiece::WhiteKing => Piece { kind: PieceType::King, color: Color::White },
         ProtocolPiece::None => return None,
     })
 }
\ No newline at end of file
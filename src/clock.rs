@@ -0,0 +1,33 @@
+use std::time::Duration;
+use crate::bridge::Color;
+use serde::{Deserialize, Serialize};
+
+/// How much time each player has spent thinking about their moves.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct Clocks {
+    pub white: Duration,
+    pub black: Duration,
+}
+
+impl Clocks {
+    /// Add `delta` to the clock of the player whose turn it is.
+    pub fn tick(&mut self, turn: Color, delta: Duration) {
+        match turn {
+            Color::White => self.white += delta,
+            Color::Black => self.black += delta,
+        }
+    }
+
+    pub fn get(&self, color: Color) -> Duration {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+}
+
+/// Format a duration as minutes and seconds, like `12:05`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -0,0 +1,145 @@
+//! Finding games on the local network.
+//!
+//! A server waiting for an opponent broadcasts a small JSON announcement over UDP every
+//! second. The join screen listens for announcements for a moment and lists the games it
+//! heard about. Announcements stop once someone has joined, so games that haven't been
+//! heard from in a while are forgotten.
+
+use std::env;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::logging;
+use crate::saved_game::SavedColor;
+
+/// The UDP port announcements are sent to.
+pub const DISCOVERY_PORT: u16 = 8385;
+/// Marks our announcements, so that anything else on the port is ignored.
+const APP: &str = "alvinw-chess-gui";
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// Games that haven't been announced for this long are forgotten.
+const FORGET_AFTER: Duration = Duration::from_secs(4);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    app: String,
+    /// Who is hosting.
+    pub name: String,
+    /// The TCP port the server listens on.
+    pub port: u16,
+    /// The color the player who joins gets.
+    pub color: SavedColor,
+    /// Whether only the opponent of a saved game may join.
+    pub reserved: bool,
+    pub tls: bool,
+}
+
+impl Announcement {
+    pub fn new(name: String, port: u16, color: SavedColor, reserved: bool, tls: bool) -> Self {
+        Self { app: APP.to_string(), name, port, color, reserved, tls }
+    }
+}
+
+/// Sends announcements for a server.
+pub struct Beacon {
+    socket: UdpSocket,
+    target: SocketAddr,
+    last_sent: Option<Instant>,
+}
+
+impl Beacon {
+    /// A beacon that broadcasts to the whole local network.
+    pub fn broadcast() -> io::Result<Self> {
+        Self::to((Ipv4Addr::BROADCAST, DISCOVERY_PORT).into())
+    }
+
+    /// A beacon that only sends to `target`.
+    pub fn to(target: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, target, last_sent: None })
+    }
+
+    /// Send `announcement` unless one was sent less than a second ago.
+    pub fn announce(&mut self, announcement: &Announcement) {
+        if self.last_sent.is_some_and(|last_sent| last_sent.elapsed() < ANNOUNCE_INTERVAL) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+        let json = serde_json::to_vec(announcement).unwrap();
+        if let Err(err) = self.socket.send_to(&json, self.target) {
+            log::debug!(target: logging::NETWORK, "Failed to announce the game to {}: {err}", self.target);
+        }
+    }
+}
+
+/// A game that has been announced on the network.
+#[derive(Debug, Clone)]
+pub struct DiscoveredGame {
+    pub announcement: Announcement,
+    /// Where to connect to join the game.
+    pub address: SocketAddr,
+    last_seen: Instant,
+}
+
+/// Listens for announcements.
+pub struct Browser {
+    socket: UdpSocket,
+    games: Vec<DiscoveredGame>,
+}
+
+impl Browser {
+    /// Listen for broadcast announcements.
+    pub fn new() -> io::Result<Self> {
+        Self::bind(DISCOVERY_PORT)
+    }
+
+    /// Listen for announcements on `port`, or any free port if it is 0.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, games: vec![] })
+    }
+
+    pub fn local_port(&self) -> io::Result<u16> {
+        Ok(self.socket.local_addr()?.port())
+    }
+
+    /// Handle the announcements that have arrived and forget games that have gone quiet.
+    pub fn poll(&mut self) {
+        let mut buf = [0; 1024];
+        while let Ok((size, from)) = self.socket.recv_from(&mut buf) {
+            let announcement = match serde_json::from_slice::<Announcement>(&buf[..size]) {
+                Ok(announcement) if announcement.app == APP => announcement,
+                _ => continue,
+            };
+            let address = SocketAddr::new(from.ip(), announcement.port);
+            match self.games.iter_mut().find(|game| game.address == address) {
+                Some(game) => {
+                    game.announcement = announcement;
+                    game.last_seen = Instant::now();
+                }
+                None => {
+                    log::debug!(target: logging::NETWORK, "Found {} at {address}", announcement.name);
+                    self.games.push(DiscoveredGame { announcement, address, last_seen: Instant::now() });
+                }
+            }
+        }
+        self.games.retain(|game| game.last_seen.elapsed() < FORGET_AFTER);
+    }
+
+    /// The games heard from recently, in the order they were first found.
+    pub fn games(&self) -> &[DiscoveredGame] {
+        &self.games
+    }
+}
+
+/// The name to announce games with when none is set, based on the user's login name.
+pub fn default_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .map_or_else(|_| String::from("Chess game"), |user| format!("{user}'s game"))
+}
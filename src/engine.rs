@@ -0,0 +1,197 @@
+//! A backend that plays by itself, so that a remote client can play against us.
+//!
+//! [`EngineGame`] wraps a game of another backend and suggests moves through
+//! [`ChessGame::engine_move`], which a [`ServerGame`](crate::server::ServerGame) hosting it
+//! plays for its own side. The engine looks two half-moves ahead and counts material, which
+//! is enough to take free pieces, avoid losing its own and mate in one. Among equally good
+//! moves it castles if it can, to get the king to safety.
+//!
+//! Games can't be cloned, so every position the engine looks at is made by replaying the
+//! game so far with the backend. That takes a while, so the search runs on a worker thread.
+
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::backend::Backend;
+use crate::bridge::{self, Board, ChessGame, Color, GameState, Move, MoveError, Piece, PieceType, PlayedMove, Square};
+use crate::logging;
+
+/// The score of being checkmated, worse than losing all the pieces.
+const MATE: i32 = 100_000;
+
+pub struct EngineGame {
+    game: Box<dyn ChessGame>,
+    /// Used to try out moves by replaying `history`.
+    backend: Backend,
+    history: Vec<PlayedMove>,
+    /// The search for a move in the position after the history it was started for.
+    search: Option<(Vec<PlayedMove>, Search)>,
+}
+
+enum Search {
+    Running(Receiver<Option<PlayedMove>>),
+    /// The move that was chosen, or none if there are no moves to make.
+    Done(Option<PlayedMove>),
+}
+
+impl EngineGame {
+    /// A game in the starting position, with the rules of `backend`.
+    pub fn new(backend: Backend) -> Self {
+        Self { game: backend.new_game(), backend, history: vec![], search: None }
+    }
+}
+
+impl ChessGame for EngineGame {
+    fn update(&mut self) {
+        self.game.update();
+    }
+
+    fn get_pieces(&self) -> Board {
+        self.game.get_pieces()
+    }
+
+    fn get_piece(&self, at: Square) -> Option<Piece> {
+        self.game.get_piece(at)
+    }
+
+    fn get_state(&self) -> GameState {
+        self.game.get_state()
+    }
+
+    fn is_check(&self) -> bool {
+        self.game.is_check()
+    }
+
+    fn current_turn(&self) -> Color {
+        self.game.current_turn()
+    }
+
+    fn promote(&mut self, promotion_square: Square, piece: PieceType) {
+        self.game.promote(promotion_square, piece);
+        if let Some(last) = self.history.last_mut() {
+            last.promotion = Some(piece);
+        }
+    }
+
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        self.game.possible_moves(at)
+    }
+
+    fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        self.game.perform_move(mv)?;
+        self.history.push(PlayedMove { mv, promotion: None });
+        Ok(())
+    }
+
+    fn can_play_right_now(&self) -> bool {
+        self.game.can_play_right_now()
+    }
+
+    fn has_possible_moves(&self) -> bool {
+        self.game.has_possible_moves()
+    }
+
+    /// Starts looking for a move in the current position and returns none until one has
+    /// been found.
+    fn engine_move(&mut self) -> Option<PlayedMove> {
+        if !self.search.as_ref().is_some_and(|(searched, _)| *searched == self.history) {
+            let (sender, receiver) = mpsc::channel();
+            let (backend, history) = (self.backend, self.history.clone());
+            thread::spawn(move || {
+                let _ = sender.send(choose_move(backend, &history));
+            });
+            self.search = Some((self.history.clone(), Search::Running(receiver)));
+        }
+        let (_, search) = self.search.as_mut()?;
+        if let Search::Running(receiver) = search {
+            match receiver.try_recv() {
+                Ok(chosen) => *search = Search::Done(chosen),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    log::warn!(target: logging::APP, "The engine stopped without choosing a move");
+                    *search = Search::Done(None);
+                }
+            }
+        }
+        match search {
+            Search::Done(chosen) => *chosen,
+            Search::Running(_) => None,
+        }
+    }
+
+    fn is_engine(&self) -> bool {
+        true
+    }
+
+    fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+}
+
+/// The best move for the side to move after `history`, or none if it has no moves.
+fn choose_move(backend: Backend, history: &[PlayedMove]) -> Option<PlayedMove> {
+    let mut game = backend.replay(history);
+    let board = game.get_pieces();
+    let mut best: Option<((i32, bool), PlayedMove)> = None;
+    for mv in bridge::all_moves(game.as_mut()) {
+        let played = PlayedMove { mv, promotion: promotion(&board, mv) };
+        let line = [history, &[played]].concat();
+        let Some(mut position) = try_line(backend, &line) else {
+            continue;
+        };
+        let score = (-best_reply_score(backend, &line, position.as_mut()), matches!(mv, Move::Castle { .. }));
+        if best.is_none_or(|(best_score, _)| score > best_score) {
+            best = Some((score, played));
+        }
+    }
+    best.map(|(_, played)| played)
+}
+
+/// The best material balance the side to move in `game`, where `history` has been played,
+/// can get with one move, from its point of view.
+fn best_reply_score(backend: Backend, history: &[PlayedMove], game: &mut dyn ChessGame) -> i32 {
+    let board = game.get_pieces();
+    let turn = game.current_turn();
+    let scores = bridge::all_moves(game).into_iter()
+        .filter_map(|mv| try_line(backend, &[history, &[PlayedMove { mv, promotion: promotion(&board, mv) }]].concat()))
+        .map(|position| material(&position.get_pieces(), turn));
+    match scores.max() {
+        Some(score) => score,
+        None if game.is_check() => -MATE,
+        None => 0,
+    }
+}
+
+/// The position after `line` is played from the start, or none if `backend` doesn't
+/// allow its last move. The moves before it have been played already.
+fn try_line(backend: Backend, line: &[PlayedMove]) -> Option<Box<dyn ChessGame>> {
+    let (last, before) = line.split_last()?;
+    let mut position = backend.replay(before);
+    position.perform_move(last.mv).ok()?;
+    if let (Move::Normal { to, .. }, Some(piece)) = (last.mv, last.promotion) {
+        position.promote(to, piece);
+    }
+    Some(position)
+}
+
+/// The engine always promotes to a queen.
+fn promotion(board: &Board, mv: Move) -> Option<PieceType> {
+    bridge::is_promotion(board, mv).then_some(PieceType::Queen)
+}
+
+/// The value of `color`'s pieces minus the value of the opponent's.
+fn material(board: &Board, color: Color) -> i32 {
+    board.iter().flatten().flatten()
+        .map(|piece| if piece.color == color { value(piece.kind) } else { -value(piece.kind) })
+        .sum()
+}
+
+fn value(kind: PieceType) -> i32 {
+    match kind {
+        PieceType::Pawn => 100,
+        PieceType::Knight | PieceType::Bishop => 300,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 0,
+    }
+}
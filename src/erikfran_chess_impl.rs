@@ -1,11 +1,170 @@
-use erikfran_chess::{Piece, util::{Square, BoardMove}, Color, PieceTypes, Move, MoveError};
+//! The backend for erikfran's chess crate, and conversions between its types and the
+//! bridge's.
 
-use crate::bridge::{self, GameState};
+use erikfran_chess::util::Square as ErikfranSquare;
 
-impl bridge::ChessGame for erikfran_chess::Game {
-    fn get_pieces(&self) -> [[Option<Piece>; 8]; 8] {
+use crate::attacks;
+use crate::bridge::{self, Board, CastlingSide, Color, GameState, Move, MoveError, Piece, PieceType, Square};
+
+/// A game of erikfran's chess crate.
+pub struct ErikfranGame {
+    game: erikfran_chess::Game,
+    /// Whether the side to move has no moves. The crate can only tell by asking every
+    /// square for its moves, so this is worked out once after each move.
+    over: bool,
+}
+
+impl ErikfranGame {
+    /// A game in the starting position.
+    pub fn new() -> Self {
+        Self { game: erikfran_chess::Game::new(), over: false }
+    }
+
+    /// Create a game with the pieces on `board` where it is `turn`'s turn to move.
+    ///
+    /// This is used to get a rules engine for positions we only know the board of. Things
+    /// that can't be seen on the board, like castling rights, are left as they are in a new
+    /// game.
+    pub fn seeded(board: &Board, turn: Color) -> Self {
+        let mut game = erikfran_chess::Game::new();
+        for (rank_index, row) in board.iter().enumerate() {
+            for (file_index, piece) in row.iter().enumerate() {
+                let square: Square = (file_index as i32, rank_index as i32).try_into().unwrap();
+                game.board[square.into()] = piece.map(Into::into);
+            }
+        }
+        game.turn = turn.into();
+        game.check = attacks::is_in_check(board, turn);
+        let mut game = Self { game, over: false };
+        game.update_over();
+        game
+    }
+
+    fn update_over(&mut self) {
+        self.over = !bridge::has_legal_moves(self);
+    }
+}
+
+impl Default for ErikfranGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<erikfran_chess::Color> for Color {
+    fn from(color: erikfran_chess::Color) -> Self {
+        match color {
+            erikfran_chess::Color::White => Color::White,
+            erikfran_chess::Color::Black => Color::Black,
+        }
+    }
+}
+
+impl From<Color> for erikfran_chess::Color {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::White => erikfran_chess::Color::White,
+            Color::Black => erikfran_chess::Color::Black,
+        }
+    }
+}
+
+impl From<erikfran_chess::PieceTypes> for PieceType {
+    fn from(piece: erikfran_chess::PieceTypes) -> Self {
+        match piece {
+            erikfran_chess::PieceTypes::Pawn(_) => PieceType::Pawn,
+            erikfran_chess::PieceTypes::Knight => PieceType::Knight,
+            erikfran_chess::PieceTypes::Bishop => PieceType::Bishop,
+            erikfran_chess::PieceTypes::Rook => PieceType::Rook,
+            erikfran_chess::PieceTypes::Queen => PieceType::Queen,
+            erikfran_chess::PieceTypes::King => PieceType::King,
+        }
+    }
+}
+
+impl From<PieceType> for erikfran_chess::PieceTypes {
+    fn from(piece: PieceType) -> Self {
+        match piece {
+            PieceType::Pawn => erikfran_chess::PieceTypes::Pawn(false),
+            PieceType::Knight => erikfran_chess::PieceTypes::Knight,
+            PieceType::Bishop => erikfran_chess::PieceTypes::Bishop,
+            PieceType::Rook => erikfran_chess::PieceTypes::Rook,
+            PieceType::Queen => erikfran_chess::PieceTypes::Queen,
+            PieceType::King => erikfran_chess::PieceTypes::King,
+        }
+    }
+}
+
+impl From<erikfran_chess::Piece> for Piece {
+    fn from(piece: erikfran_chess::Piece) -> Self {
+        Piece { kind: piece.piece.into(), color: piece.color.into() }
+    }
+}
+
+impl From<Piece> for erikfran_chess::Piece {
+    fn from(piece: Piece) -> Self {
+        erikfran_chess::Piece { piece: piece.kind.into(), color: piece.color.into() }
+    }
+}
+
+impl From<ErikfranSquare> for Square {
+    fn from(square: ErikfranSquare) -> Self {
+        (i32::from(square.file), i32::from(square.rank)).try_into().unwrap()
+    }
+}
+
+impl From<Square> for ErikfranSquare {
+    fn from(square: Square) -> Self {
+        (square.file(), square.rank()).try_into().unwrap()
+    }
+}
+
+impl From<erikfran_chess::CastlingSide> for CastlingSide {
+    fn from(side: erikfran_chess::CastlingSide) -> Self {
+        match side {
+            erikfran_chess::CastlingSide::KingSide => CastlingSide::KingSide,
+            erikfran_chess::CastlingSide::QueenSide => CastlingSide::QueenSide,
+        }
+    }
+}
+
+impl From<CastlingSide> for erikfran_chess::CastlingSide {
+    fn from(side: CastlingSide) -> Self {
+        match side {
+            CastlingSide::KingSide => erikfran_chess::CastlingSide::KingSide,
+            CastlingSide::QueenSide => erikfran_chess::CastlingSide::QueenSide,
+        }
+    }
+}
+
+impl From<erikfran_chess::Move> for Move {
+    fn from(mv: erikfran_chess::Move) -> Self {
+        match mv {
+            erikfran_chess::Move::Normal { from, to } => Move::Normal { from: from.into(), to: to.into() },
+            erikfran_chess::Move::Castle { side } => Move::Castle { side: side.into() },
+        }
+    }
+}
+
+impl From<Move> for erikfran_chess::Move {
+    fn from(mv: Move) -> Self {
+        match mv {
+            Move::Normal { from, to } => erikfran_chess::Move::Normal { from: from.into(), to: to.into() },
+            Move::Castle { side } => erikfran_chess::Move::Castle { side: side.into() },
+        }
+    }
+}
+
+impl From<erikfran_chess::MoveError> for MoveError {
+    fn from(err: erikfran_chess::MoveError) -> Self {
+        MoveError::new(err.to_string())
+    }
+}
+
+impl bridge::ChessGame for ErikfranGame {
+    fn get_pieces(&self) -> Board {
         let mut ret = [[None; 8]; 8];
-        for (row_index, row) in self.board.rows.squares.iter().enumerate() {
+        for (row_index, row) in self.game.board.rows.squares.iter().enumerate() {
             for (piece_index, piece) in row.squares.iter().enumerate() {
                 ret[row_index][piece_index] = piece.map(|piece| piece.into());
             }
@@ -14,11 +173,11 @@ impl bridge::ChessGame for erikfran_chess::Game {
     }
 
     fn get_piece(&self, at: Square) -> Option<Piece> {
-        self.board[at]
+        self.game.board[at.into()].map(Into::into)
     }
 
     fn get_state(&self) -> GameState {
-        if self.check {
+        if self.game.check {
             GameState::Check
         } else {
             GameState::Normal
@@ -26,30 +185,39 @@ impl bridge::ChessGame for erikfran_chess::Game {
     }
 
     fn is_check(&self) -> bool {
-        self.check
+        self.game.check
     }
 
     fn current_turn(&self) -> Color {
-        self.turn.into()
+        self.game.turn.into()
     }
 
-    fn promote(&mut self, promotion_square: Square, piece_type: PieceTypes) {
+    fn promote(&mut self, promotion_square: Square, piece_type: PieceType) {
         // This backend does not implement promotion. So we implement it manually here
         // instead.
 
-        let piece = self.board[promotion_square].expect("Expected piece to promote");
-        self.board[promotion_square] = Some(erikfran_chess::Piece {
+        let square = promotion_square.into();
+        let piece = self.game.board[square].expect("Expected piece to promote");
+        self.game.board[square] = Some(erikfran_chess::Piece {
             color: piece.color,
-            piece: piece_type,
+            piece: piece_type.into(),
         });
+        // The new piece may give check or take away the opponent's last moves.
+        self.update_over();
     }
 
-    fn possible_moves(&mut self, at: Square) -> Result<(BoardMove, Vec<Move>), MoveError> {
-        erikfran_chess::Game::possible_moves(self, at.into(), true)
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        let (board_move, castle_moves) = self.game.possible_moves(at.into(), true)?;
+        let normal_moves = board_move.rows.squares.into_iter()
+            .flat_map(|row| row.squares)
+            .flatten();
+        Ok(normal_moves.chain(castle_moves).map(Into::into).collect())
     }
 
     fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
-        self.try_move(mv)
+        self.game.try_move(mv.into())?;
+        self.update_over();
+        Ok(())
     }
 
     fn update(&mut self) {
@@ -64,4 +232,8 @@ impl bridge::ChessGame for erikfran_chess::Game {
     fn has_possible_moves(&self) -> bool {
         true
     }
-}
\ No newline at end of file
+
+    fn is_over(&self) -> bool {
+        self.over
+    }
+}
@@ -0,0 +1,143 @@
+//! Messages that are not part of chess-network-protocol.
+//!
+//! The server lists the extensions it understands in its handshake using `Features::Other`.
+//! A client that understands some of them answers with [`ExtensionMessage::Hello`], after
+//! which both sides may send the messages of the extensions they have in common. Peers that
+//! don't know about any extensions will never be sent one of these messages.
+
+use chess_network_protocol::{Color as ProtocolColor, Features, Piece as ProtocolPiece, Move as ProtocolMove};
+use serde::{Deserialize, Serialize};
+
+use crate::bridge::{Board, Color, Piece, PieceType};
+
+pub const TAKEBACK: &str = "takeback";
+/// The server sends a hash of the position after every move so that the client can
+/// notice when its board differs, and ask for the whole game again.
+pub const POSITION_HASH: &str = "position-hash";
+/// Text messages between the players.
+pub const CHAT: &str = "chat";
+/// Starting a new game on the same connection.
+pub const REMATCH: &str = "rematch";
+
+/// The extensions this implementation understands.
+pub const SUPPORTED: &[&str] = &[TAKEBACK, POSITION_HASH, CHAT, REMATCH];
+/// The longest chat message that is sent or shown, in characters.
+pub const MAX_CHAT_LENGTH: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ExtensionMessage {
+    /// Sent by the client after the handshake to tell the server which of the advertised
+    /// extensions it understands.
+    Hello { extensions: Vec<String> },
+    /// Ask the opponent to take back the sender's last move. When both sides send this at
+    /// the same time, the server takes back both of their last moves as if each had
+    /// accepted the other's request.
+    TakebackRequest,
+    /// Answer to a `TakebackRequest`. An accepted request is answered by the server with
+    /// `TakebackPerformed` instead.
+    TakebackResponse { accepted: bool },
+    /// Sent by the server after it has taken back `plies` half-moves.
+    TakebackPerformed {
+        plies: usize,
+        board: [[ProtocolPiece; 8]; 8],
+        moves: Vec<ProtocolMove>,
+    },
+    /// The hash of the position after `plies` half-moves, see [`position_hash`]. Sent by
+    /// the server after every `State`, and answered by the client with its own hash.
+    PositionHash { plies: usize, hash: u64 },
+    /// Sent by the client when its position hash differs from the server's.
+    ResyncRequest,
+    /// The whole game, sent by the server as the answer to `ResyncRequest`. `history`
+    /// is the moves played so far in coordinate notation, like `e2e4` or `O-O`.
+    Resync {
+        board: [[ProtocolPiece; 8]; 8],
+        moves: Vec<ProtocolMove>,
+        history: Vec<String>,
+    },
+    /// A chat message to the other player, at most [`MAX_CHAT_LENGTH`] characters.
+    Chat { text: String },
+    /// Offer the opponent a new game, with the colors swapped if `swap_colors` is set.
+    RematchOffer { swap_colors: bool },
+    /// Answer to a `RematchOffer`. An accepted offer is answered by the server with
+    /// `RematchStarted` instead.
+    RematchResponse { accepted: bool },
+    /// Sent by the server when the new game has started. `server_color` is what the server
+    /// plays in it, and the board and moves are the same as in a handshake.
+    RematchStarted {
+        server_color: ProtocolColor,
+        board: [[ProtocolPiece; 8]; 8],
+        moves: Vec<ProtocolMove>,
+    },
+}
+
+/// A message that is either from chess-network-protocol or one of our extensions.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Incoming<T> {
+    Protocol(T),
+    Extension(ExtensionMessage),
+}
+
+/// Hash the board and the color to move for the `position-hash` extension.
+///
+/// This is 64-bit FNV-1a over 65 bytes: the squares a1, b1, ..., h1, a2, ..., h8, then the
+/// color to move. An empty square is 0, white pieces are 1 to 6 and black pieces 7 to 12, in
+/// the order pawn, knight, bishop, rook, queen, king. White to move is 0 and black is 1.
+pub fn position_hash(board: &Board, turn: Color) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let square_byte = |piece: &Option<Piece>| -> u8 {
+        let Some(piece) = piece else {
+            return 0;
+        };
+        let kind = match piece.kind {
+            PieceType::Pawn => 1,
+            PieceType::Knight => 2,
+            PieceType::Bishop => 3,
+            PieceType::Rook => 4,
+            PieceType::Queen => 5,
+            PieceType::King => 6,
+        };
+        match piece.color {
+            Color::White => kind,
+            Color::Black => kind + 6,
+        }
+    };
+    let turn_byte = match turn {
+        Color::White => 0,
+        Color::Black => 1,
+    };
+
+    board.iter()
+        .flatten()
+        .map(square_byte)
+        .chain([turn_byte])
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+}
+
+/// Clean up a chat message before it is sent or shown: trim whitespace, replace control
+/// characters and cut it to [`MAX_CHAT_LENGTH`]. Returns none if nothing is left.
+pub fn clean_chat(text: &str) -> Option<String> {
+    let text: String = text.trim()
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_CHAT_LENGTH)
+        .collect();
+    (!text.is_empty()).then_some(text)
+}
+
+/// The features the server should advertise in its handshake.
+pub fn features() -> Vec<Features> {
+    SUPPORTED.iter().map(|name| Features::Other(name.to_string())).collect()
+}
+
+/// The extensions in `features` that this implementation also understands.
+pub fn common_extensions(features: &[Features]) -> Vec<String> {
+    features.iter()
+        .filter_map(|feature| match feature {
+            Features::Other(name) if SUPPORTED.contains(&name.as_str()) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,44 +1,110 @@
-use std::io::Read;
+use std::io::{self, Read, Write};
 use std::net::TcpStream;
 
-/// A non-blocking stream that reads JSON objects using serde.
+use crate::logging;
+use crate::tls::Transport;
+use crate::traffic::{self, Direction};
+
+/// A non-blocking stream that reads and writes JSON objects using serde.
+///
+/// Every message is recorded in the [traffic log](crate::traffic). The connection may be
+/// [encrypted](crate::tls).
 pub struct JsonTcpStream {
-    stream: TcpStream,
+    stream: Transport,
     buffer: Vec<u8>,
+    /// The address of the other side, for the traffic log.
+    peer: String,
+    /// Set when the other side has closed the connection or it has failed.
+    closed: bool,
 }
 
 impl JsonTcpStream {
     pub fn new(stream: TcpStream) -> Self {
-        stream.set_nonblocking(true).expect("set stream non blocking");
-        Self { stream, buffer: vec![] }
+        Self::with_transport(Transport::Plain(stream))
+    }
+
+    pub fn with_transport(stream: Transport) -> Self {
+        stream.tcp().set_nonblocking(true).expect("set stream non blocking");
+        let peer = stream.tcp().peer_addr().map_or_else(|_| String::from("unknown"), |addr| addr.to_string());
+        Self { stream, buffer: vec![], peer, closed: false }
+    }
+
+    /// The fingerprint of the server's certificate, if this is an encrypted connection to
+    /// a server.
+    pub fn server_fingerprint(&self) -> Option<String> {
+        self.stream.server_fingerprint()
+    }
+
+    /// Whether the connection has been closed. This is noticed when reading, or when
+    /// writing fails.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Send `value`. If that fails, for example because the other side has gone away, the
+    /// connection is closed since part of the message may have been sent.
+    pub fn write<T: serde::Serialize>(&mut self, value: &T) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "The connection is closed"));
+        }
+        let json = serde_json::to_string(value)?;
+        traffic::record(Direction::Sent, &self.peer, &json);
+        let result = self.stream.write_all(json.as_bytes()).and_then(|_| self.stream.flush());
+        if result.is_err() {
+            self.closed = true;
+        }
+        result
     }
 
+    /// Read the next message, or none if a whole message hasn't arrived yet.
+    ///
+    /// Messages don't have to arrive in one piece, and several may arrive at once. The
+    /// ones not returned are kept for the next call. Messages that aren't a `T` are
+    /// skipped, since the other side may well send things we don't know about.
     pub fn read<T: serde::de::DeserializeOwned>(&mut self) -> Option<T> {
         let mut read_buf = [0; 1024];
-        let size = self.stream.read(&mut read_buf).ok()?;
-        if size == 0 {
-            return None;
-        }
-        let read_bytes = &read_buf[..size];
-        self.buffer.extend_from_slice(read_bytes);
-
-        match serde_json::from_slice(self.buffer.as_slice()) {
-            Ok(result) => {
-                // Clear the buffer since the data has been read successfully.
-                self.buffer.clear();
-                result
-            }
-            Err(_err) => {
-                // println!("Partial data: {:?} err = {:?}", String::from_utf8(self.buffer.clone()), err);
-                // If this fails to read we assume that is because we received a partial part of
-                // the data. Since it was added to the buffer we can continue trying next time
-                // this method is called.
-                return None;
+        loop {
+            match self.stream.read(&mut read_buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => break,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(size) => self.buffer.extend_from_slice(&read_buf[..size]),
             }
         }
-    }
 
-    pub fn stream(&mut self) -> &mut TcpStream {
-        &mut self.stream
+        loop {
+            let mut values = serde_json::Deserializer::from_slice(&self.buffer).into_iter::<serde_json::Value>();
+            let value = match values.next() {
+                Some(Ok(value)) => value,
+                // We received a partial part of the data. Since it is kept in the buffer we
+                // can continue trying next time this method is called.
+                Some(Err(err)) if err.is_eof() => return None,
+                Some(Err(err)) => {
+                    log::warn!(target: logging::NETWORK, "Discarding invalid data from {}: {err}", self.peer);
+                    self.buffer.clear();
+                    return None;
+                }
+                None => {
+                    // Only whitespace is left.
+                    self.buffer.clear();
+                    return None;
+                }
+            };
+            let end = values.byte_offset();
+            let json = String::from_utf8_lossy(&self.buffer[..end]).trim().to_string();
+            self.buffer.drain(..end);
+            traffic::record(Direction::Received, &self.peer, &json);
+
+            match serde_json::from_value(value) {
+                Ok(message) => return Some(message),
+                Err(err) => log::warn!(target: logging::NETWORK, "Ignoring unexpected message {json}: {err}"),
+            }
+        }
     }
-}
\ No newline at end of file
+}
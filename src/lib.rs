@@ -0,0 +1,28 @@
+//! The game logic and views of the chess GUI. The binary in `main.rs` asks how to play and
+//! opens the window, while the integration tests use the network code directly.
+
+pub mod view;
+pub mod attacks;
+pub mod backend;
+pub mod bridge;
+pub mod builtin_rules;
+pub mod clock;
+pub mod discovery;
+pub mod engine;
+pub mod erikfran_chess_impl;
+pub mod extension;
+pub mod json_tcp_stream;
+pub mod local_game;
+pub mod logging;
+pub mod notation;
+pub mod pgn;
+pub mod replay_game;
+pub mod saved_game;
+pub mod server;
+pub mod session;
+pub mod client;
+pub mod settings;
+pub mod theme;
+pub mod tls;
+pub mod tournament;
+pub mod traffic;
@@ -0,0 +1,135 @@
+use crate::backend::Backend;
+use crate::bridge::{self, Board, ChessGame, Color, GameState, Move, MoveError, Piece, PieceType, PlayedMove, Square};
+use crate::saved_game::SavedSession;
+use crate::session::GameSession;
+
+/// A game where both players sit at the same computer.
+///
+/// Keeps the moves that have been played so that they can be taken back.
+pub struct LocalGame {
+    backend: Backend,
+    game: Box<dyn ChessGame>,
+    history: Vec<PlayedMove>,
+    rematches: usize,
+}
+
+impl LocalGame {
+    pub fn new() -> Self {
+        Self::from_history(vec![])
+    }
+
+    /// Continue a game where `history` has been played.
+    pub fn from_history(history: Vec<PlayedMove>) -> Self {
+        let backend = Backend::default();
+        Self {
+            backend,
+            game: backend.replay(&history),
+            history,
+            rematches: 0,
+        }
+    }
+
+    /// Play with the rules of `backend` instead of the default one.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self.game = backend.replay(&self.history);
+        self
+    }
+
+    /// Take back the last move. Returns false if there was nothing to take back.
+    pub fn undo(&mut self) -> bool {
+        if self.history.pop().is_none() {
+            return false;
+        }
+        self.game = self.backend.replay(&self.history);
+        true
+    }
+}
+
+impl Default for LocalGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl bridge::ChessGame for LocalGame {
+    fn update(&mut self) {
+
+    }
+
+    fn get_pieces(&self) -> Board {
+        self.game.get_pieces()
+    }
+
+    fn get_piece(&self, at: Square) -> Option<Piece> {
+        self.game.get_piece(at)
+    }
+
+    fn get_state(&self) -> GameState {
+        self.game.get_state()
+    }
+
+    fn is_check(&self) -> bool {
+        self.game.is_check()
+    }
+
+    fn current_turn(&self) -> Color {
+        self.game.current_turn()
+    }
+
+    fn promote(&mut self, promotion_square: Square, piece: PieceType) {
+        self.game.promote(promotion_square, piece);
+        if let Some(last) = self.history.last_mut() {
+            last.promotion = Some(piece);
+        }
+    }
+
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        self.game.possible_moves(at)
+    }
+
+    fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        self.game.perform_move(mv)?;
+        self.history.push(PlayedMove { mv, promotion: None });
+        Ok(())
+    }
+
+    fn can_play_right_now(&self) -> bool {
+        // Local games control both sides
+        true
+    }
+
+    fn has_possible_moves(&self) -> bool {
+        true
+    }
+
+    fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+}
+
+impl GameSession for LocalGame {
+    fn history(&self) -> &[PlayedMove] {
+        &self.history
+    }
+
+    fn request_takeback(&mut self) {
+        // Both players are at the same computer, so there is no one to ask.
+        self.undo();
+    }
+
+    fn offer_rematch(&mut self, _swap_colors: bool) {
+        // Both players are at the same computer, so there are no colors to swap either.
+        self.game = self.backend.new_game();
+        self.history.clear();
+        self.rematches += 1;
+    }
+
+    fn rematches(&self) -> usize {
+        self.rematches
+    }
+
+    fn saved_session(&self) -> Option<SavedSession> {
+        Some(SavedSession::Local)
+    }
+}
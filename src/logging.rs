@@ -0,0 +1,97 @@
+//! A small logger for the `log` crate that can be filtered per module.
+//!
+//! Messages are logged with one of the targets below. The filter is written like
+//! `info,network=debug,rendering=off`: a bare level applies to everything that isn't
+//! mentioned, and `target=level` sets the level of one target. Targets match by prefix,
+//! so `wgpu=warn` also covers `wgpu_core`.
+//!
+//! The filter is read from the `--log` command line flag, or else the `CHESS_LOG`
+//! environment variable.
+
+use std::env;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Connections, handshakes and messages from the other player.
+pub const NETWORK: &str = "network";
+/// Clicks and key presses.
+pub const INPUT: &str = "input";
+/// Drawing, themes and frame rate.
+pub const RENDERING: &str = "rendering";
+/// Settings, saved games and everything else.
+pub const APP: &str = "app";
+
+const ENV_VAR: &str = "CHESS_LOG";
+const FLAG: &str = "--log";
+/// The graphics libraries are chatty at the info level.
+const DEFAULT_FILTER: &str = "info,wgpu=warn,naga=warn";
+
+struct Logger {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Logger {
+    fn parse(filter: &str) -> Result<Self, String> {
+        let mut logger = Logger { default: LevelFilter::Info, targets: vec![] };
+        for part in filter.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let parse_level = |level: &str| level.parse::<LevelFilter>()
+                .map_err(|_| format!("Invalid log level: {level}"));
+            match part.split_once('=') {
+                Some((target, level)) => logger.targets.push((target.to_string(), parse_level(level)?)),
+                None => logger.default = parse_level(part)?,
+            }
+        }
+        Ok(logger)
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        // The longest matching prefix is the most specific.
+        self.targets.iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error | Level::Warn => eprintln!("[{} {}] {}", record.level(), record.target(), record.args()),
+            _ => println!("[{} {}] {}", record.level(), record.target(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Set up logging, taking the `--log` flag out of `args` so that the rest can be read as
+/// settings.
+pub fn init(args: &mut Vec<String>) -> Result<(), String> {
+    let flag = match args.iter().position(|arg| arg == FLAG) {
+        Some(index) if index + 1 < args.len() => {
+            let value = args.remove(index + 1);
+            args.remove(index);
+            Some(value)
+        }
+        Some(_) => return Err(format!("Missing value for {FLAG}")),
+        None => None,
+    };
+    let filter = flag
+        .or_else(|| env::var(ENV_VAR).ok())
+        .map_or_else(|| DEFAULT_FILTER.to_string(), |filter| format!("{DEFAULT_FILTER},{filter}"));
+
+    let logger = Logger::parse(&filter)?;
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(logger)).map_err(|err| err.to_string())
+}
@@ -1,26 +1,50 @@
-use std::{env, thread};
+use std::{env, fs, thread};
 use std::io::stdin;
-use std::net::Ipv4Addr;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use ggez::conf::{WindowMode, WindowSetup};
 use ggez::{ContextBuilder, event};
-use crate::view::board_view::BoardView;
-use crate::view::MainState;
 use local_ip_address::local_ip;
-use crate::client::ClientGame;
-use crate::server::{ProtocolState, ServerGame};
-
-mod view;
-mod bridge;
-mod erikfran_chess_impl;
-mod json_tcp_stream;
-mod server;
-mod client;
-
-const PORT: u16 = 8384;
+use alvinw_chess_gui::{discovery, logging, pgn, tls, traffic};
+use alvinw_chess_gui::client::{self, ClientGame};
+use alvinw_chess_gui::bridge::ChessGame;
+use alvinw_chess_gui::discovery::{Browser, DiscoveredGame};
+use alvinw_chess_gui::engine::EngineGame;
+use alvinw_chess_gui::local_game::LocalGame;
+use alvinw_chess_gui::saved_game::{SavedColor, SavedGame, SavedSession};
+use alvinw_chess_gui::server::{ProtocolState, ServerGame};
+use alvinw_chess_gui::settings::SettingsStore;
+use alvinw_chess_gui::tournament::{Format, TournamentHost};
+use alvinw_chess_gui::view::MainState;
+use alvinw_chess_gui::view::board_view::BoardView;
+use alvinw_chess_gui::view::replay_view::ReplayView;
+use alvinw_chess_gui::view::tournament_view::TournamentView;
 
 fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = logging::init(&mut args) {
+        println!("{err}");
+        return;
+    }
+
+    let settings = match SettingsStore::load(args) {
+        Ok(settings) => settings.into_shared(),
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let port = settings.borrow().get().port;
+    let use_tls = settings.borrow().get().tls;
+    let backend = settings.borrow().get().backend;
+    let name = settings.borrow().get().name.clone().unwrap_or_else(discovery::default_name);
+    if settings.borrow().get().traffic_log {
+        if let Some(path) = traffic::default_path() {
+            traffic::open_file(&path, traffic::MAX_FILE_SIZE);
+        }
+    }
 
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = PathBuf::from(manifest_dir);
@@ -40,69 +64,373 @@ fn main() {
     let (mut ctx, event_loop) = builder.build().expect("Failed to start ggez.");
 
 
-    println!("Do you want to be a server or client? (server, client)");
+    let saved_game = SavedGame::load();
+    if saved_game.is_some() {
+        println!("Do you want to be a server or client, let a client play our engine, play locally, resume the saved game, watch a replay or host a tournament? (server, client, engine, local, resume, replay, tournament)");
+    } else {
+        println!("Do you want to be a server or client, let a client play our engine, play locally, watch a replay or host a tournament? (server, client, engine, local, replay, tournament)");
+    }
     let mut buf = String::new();
     stdin().read_line(&mut buf).unwrap();
 
-    match &buf.trim()[..] {
-        "server" => {
+    match buf.trim() {
+        option @ ("server" | "engine") => {
+            let engine = option == "engine";
             let my_local_ip = local_ip().unwrap();
-            println!("I am the server. Please tell people to join the ip: {}", my_local_ip);
-
-            let game = erikfran_chess::Game::new();
-            let mut server_game = ServerGame::new(game, 8384);
-
-            loop {
-                let state = server_game.get_protocol_state();
-
-                match state {
-                    ProtocolState::NotConnected => {
-                        println!("Waiting for client to connect...");
-                        server_game.try_accept_client();
-                    },
-                    ProtocolState::Handshake => {
-                        println!("Waiting for handshake.");
-                        server_game.try_handshake();
-                    },
-                    ProtocolState::Play => {
-                        break;
-                    }
-                }
-
-                thread::sleep(Duration::from_millis(1000));
+            if engine {
+                println!("The engine is the server. Please tell people to join the ip: {}", my_local_ip);
+            } else {
+                println!("I am the server. Please tell people to join the ip: {}", my_local_ip);
             }
+            println!("The game is also shown to players on the local network as \"{name}\".");
+
+            let server_game = if engine {
+                ServerGame::new(move || Box::new(EngineGame::new(backend)) as Box<dyn ChessGame>, port)
+            } else {
+                ServerGame::new(move || backend.new_game(), port)
+            };
+            let server_game = server_game.with_announcement(name.clone());
+            let mut server_game = match require_tls(server_game, use_tls) {
+                Ok(server_game) => server_game,
+                Err(err) => {
+                    println!("{err}");
+                    return;
+                }
+            };
+            wait_for_client(&mut server_game);
 
             // Ready to play.
 
-            println!("Ready to play!");
+            log::info!(target: logging::NETWORK, "Ready to play!");
 
             let main_state = MainState::new();
 
-            let board_view = BoardView::new(&mut ctx, server_game).unwrap();
+            let board_view = BoardView::new(&mut ctx, server_game).unwrap()
+                .with_settings(&ctx, settings.clone())
+                .with_autosave();
+            // We only watch while the engine plays.
+            let board_view = if engine { board_view.with_input_locked() } else { board_view };
 
             main_state.set_view(board_view);
 
             event::run(ctx, event_loop, main_state);
         }
         "client" => {
-            println!("Enter the ip to join");
+            let games = find_games();
+            if games.is_empty() {
+                println!("No games were found on the local network.");
+            } else {
+                println!("Games on the local network:");
+                for (index, game) in games.iter().enumerate() {
+                    println!("  {}) {}", index + 1, describe_game(game));
+                }
+            }
+            let last_ip = settings.borrow().get().last_ip.clone();
+            let choice = if games.is_empty() { "" } else { "the number of a game or " };
+            match &last_ip {
+                Some(last_ip) => println!("Enter {choice}the address to join, with or without a port (leave empty for {last_ip})"),
+                None => println!("Enter {choice}the address to join, with or without a port"),
+            }
             let mut buf2 = String::new();
             stdin().read_line(&mut buf2).unwrap();
-            let ip: Ipv4Addr = buf2.trim().parse().unwrap();
-            println!("Attempting to connect to {}", ip);
+            let input = match (buf2.trim(), last_ip) {
+                ("", Some(last_ip)) => last_ip,
+                (input, _) => input.to_string(),
+            };
+            let discovered = input.parse::<usize>().ok()
+                .and_then(|number| games.get(number.wrapping_sub(1)));
+            let (addrs, use_tls) = if let Some(game) = discovered {
+                (vec![game.address], game.announcement.tls)
+            } else {
+                let addrs = match client::parse_address(&input, port) {
+                    Ok(addrs) => addrs,
+                    Err(err) => {
+                        println!("{err}");
+                        return;
+                    }
+                };
+                settings.borrow_mut().change(|settings| settings.last_ip = Some(input.clone()));
+                (addrs, use_tls)
+            };
+            log::info!(target: logging::NETWORK, "Attempting to connect to {}", addrs[0]);
 
-            let client_game = ClientGame::connect(ip, PORT);
+            let fingerprint = settings.borrow().get().fingerprint.clone();
+            let client_game = match join(&addrs, use_tls, fingerprint) {
+                Ok(client_game) => client_game.with_backend(backend),
+                Err(err) => {
+                    println!("{err}");
+                    return;
+                }
+            };
 
             let main_state = MainState::new();
 
-            let board_view = BoardView::new(&mut ctx, client_game).unwrap();
+            let board_view = BoardView::new(&mut ctx, client_game).unwrap()
+                .with_settings(&ctx, settings.clone())
+                .with_autosave();
 
             main_state.set_view(board_view);
 
             event::run(ctx, event_loop, main_state);
         }
+        "local" => {
+            let main_state = MainState::new();
+
+            let board_view = BoardView::new(&mut ctx, LocalGame::new().with_backend(backend)).unwrap()
+                .with_settings(&ctx, settings.clone())
+                .with_autosave();
+
+            main_state.set_view(board_view);
+
+            event::run(ctx, event_loop, main_state);
+        }
+        "resume" if saved_game.is_some() => {
+            let saved_game = saved_game.unwrap();
+            let history = match saved_game.history() {
+                Ok(history) => history,
+                Err(err) => {
+                    println!("{err}");
+                    return;
+                }
+            };
+            let main_state = MainState::new();
+
+            match saved_game.session {
+                SavedSession::Local => {
+                    let board_view = BoardView::new(&mut ctx, LocalGame::from_history(history).with_backend(backend)).unwrap()
+                        .with_settings(&ctx, settings.clone())
+                        .with_clocks(saved_game.clocks)
+                        .with_autosave();
+                    main_state.set_view(board_view);
+                }
+                SavedSession::Server { port, color, peer, tls, engine } => {
+                    let peer = peer.and_then(|peer| peer.parse().ok());
+                    match peer {
+                        Some(peer) => println!("Resuming the game. Waiting for {} to reconnect.", peer),
+                        None => println!("Resuming the game. Please tell people to join the ip: {}", local_ip().unwrap()),
+                    }
+                    let server_game = if engine {
+                        ServerGame::resume(move || Box::new(EngineGame::new(backend)) as Box<dyn ChessGame>, history, port, color.into(), peer)
+                    } else {
+                        ServerGame::resume(move || backend.new_game(), history, port, color.into(), peer)
+                    };
+                    let server_game = server_game.with_announcement(name.clone());
+                    let mut server_game = match require_tls(server_game, tls) {
+                        Ok(server_game) => server_game,
+                        Err(err) => {
+                            println!("{err}");
+                            return;
+                        }
+                    };
+                    wait_for_client(&mut server_game);
+
+                    log::info!(target: logging::NETWORK, "Ready to play!");
+                    let board_view = BoardView::new(&mut ctx, server_game).unwrap()
+                        .with_settings(&ctx, settings.clone())
+                        .with_clocks(saved_game.clocks)
+                        .with_autosave();
+                    // We only watch while the engine plays.
+                    let board_view = if engine { board_view.with_input_locked() } else { board_view };
+                    main_state.set_view(board_view);
+                }
+                SavedSession::Client { address, port, tls_fingerprint } => {
+                    log::info!(target: logging::NETWORK, "Attempting to reconnect to {}", address);
+                    let client_game = match client::parse_address(&address, port)
+                        .and_then(|addrs| join(&addrs, tls_fingerprint.is_some(), tls_fingerprint))
+                    {
+                        Ok(client_game) => client_game.with_backend(backend).with_history(history),
+                        Err(err) => {
+                            println!("{err}");
+                            return;
+                        }
+                    };
+                    let board_view = BoardView::new(&mut ctx, client_game).unwrap()
+                        .with_settings(&ctx, settings.clone())
+                        .with_clocks(saved_game.clocks)
+                        .with_autosave();
+                    main_state.set_view(board_view);
+                }
+            }
+
+            event::run(ctx, event_loop, main_state);
+        }
+        "replay" => {
+            println!("Enter the path of a PGN or saved game file (leave empty for the last saved game)");
+            let mut buf2 = String::new();
+            stdin().read_line(&mut buf2).unwrap();
+            let path = match buf2.trim() {
+                "" => SavedGame::default_path(),
+                input => Some(PathBuf::from(input)),
+            };
+            let Some(path) = path else {
+                println!("There is no saved game.");
+                return;
+            };
+            let moves = if path.extension().is_some_and(|extension| extension == "pgn") {
+                fs::read_to_string(&path)
+                    .map_err(|err| format!("Failed to read {}: {err}", path.display()))
+                    .and_then(|text| pgn::parse(&text, backend))
+            } else {
+                SavedGame::load_from(&path).and_then(|saved| saved.history())
+            };
+            let moves = match moves {
+                Ok(moves) => moves,
+                Err(err) => {
+                    println!("{err}");
+                    return;
+                }
+            };
+
+            let main_state = MainState::new();
+            let replay_view = ReplayView::new(&mut ctx, moves, backend).unwrap()
+                .with_settings(&ctx, settings.clone());
+            main_state.set_view(replay_view);
+
+            event::run(ctx, event_loop, main_state);
+        }
+        "tournament" => {
+            println!("Should everyone play everyone, or should players with the same score meet for a number of rounds? (round-robin, swiss)");
+            let mut buf2 = String::new();
+            stdin().read_line(&mut buf2).unwrap();
+            let format = match buf2.trim() {
+                "round-robin" => Format::RoundRobin,
+                "swiss" => {
+                    println!("How many rounds?");
+                    let mut buf3 = String::new();
+                    stdin().read_line(&mut buf3).unwrap();
+                    match buf3.trim().parse() {
+                        Ok(rounds) if rounds > 0 => Format::Swiss { rounds },
+                        _ => {
+                            println!("Invalid number of rounds.");
+                            return;
+                        }
+                    }
+                }
+                _ => {
+                    println!("Invalid format.");
+                    return;
+                }
+            };
+            println!("Hosting a tournament. Please tell the participants to join the ip: {}", local_ip().unwrap());
+            println!("Every game is started with the rematch extension, so only clients that support it can play. This includes this program's client.");
+
+            let mut host = TournamentHost::new(port, format).with_backend(backend);
+            match tls_config(use_tls) {
+                Ok(Some(config)) => host = host.with_tls(config),
+                Ok(None) => {}
+                Err(err) => {
+                    println!("{err}");
+                    return;
+                }
+            }
+
+            let main_state = MainState::new();
+            main_state.set_view(TournamentView::new(host));
+
+            event::run(ctx, event_loop, main_state);
+        }
         _ => {
             panic!("Invalid option!");
         }
     }
+}
+
+/// Require clients to use TLS if `use_tls` is set, and show the fingerprint that they
+/// should see.
+fn require_tls(server_game: ServerGame, use_tls: bool) -> Result<ServerGame, String> {
+    Ok(match tls_config(use_tls)? {
+        Some(config) => server_game.with_tls(config),
+        None => server_game,
+    })
+}
+
+/// The TLS configuration for servers if `use_tls` is set, after showing the fingerprint
+/// that clients should see.
+fn tls_config(use_tls: bool) -> Result<Option<Arc<rustls::ServerConfig>>, String> {
+    if !use_tls {
+        return Ok(None);
+    }
+    let identity = tls::Identity::load_or_create()?;
+    println!("Connections are encrypted. Tell the other players to check that they see this fingerprint:");
+    println!("{}", identity.fingerprint());
+    Ok(Some(identity.server_config()?))
+}
+
+/// Connect to the server at the first of `addrs` that answers, with TLS if `use_tls` is
+/// set. Unless we know which fingerprint to expect, the user is asked to compare it with
+/// the one the server shows.
+fn join(addrs: &[SocketAddr], use_tls: bool, fingerprint: Option<String>) -> Result<ClientGame, String> {
+    if !use_tls {
+        return ClientGame::connect(addrs);
+    }
+    let client_game = ClientGame::connect_tls(addrs, tls::client_config(fingerprint.clone()))?;
+    if fingerprint.is_none() {
+        println!("The connection is encrypted. The server's fingerprint is:");
+        println!("{}", client_game.server_fingerprint().unwrap_or_default());
+        println!("Is this the fingerprint the server shows? (yes, no)");
+        let mut buf = String::new();
+        stdin().read_line(&mut buf).unwrap();
+        if !matches!(buf.trim(), "y" | "yes") {
+            return Err(String::from("The server's fingerprint was not accepted."));
+        }
+    }
+    Ok(client_game)
+}
+
+/// Listen for games announced on the local network for a moment.
+fn find_games() -> Vec<DiscoveredGame> {
+    let mut browser = match Browser::new() {
+        Ok(browser) => browser,
+        Err(err) => {
+            log::warn!(target: logging::NETWORK, "Can't look for games on the local network: {err}");
+            return vec![];
+        }
+    };
+    println!("Looking for games on the local network...");
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(1500) {
+        browser.poll();
+        thread::sleep(Duration::from_millis(100));
+    }
+    browser.games().to_vec()
+}
+
+fn describe_game(game: &DiscoveredGame) -> String {
+    let announcement = &game.announcement;
+    let color = match announcement.color {
+        SavedColor::White => "white",
+        SavedColor::Black => "black",
+    };
+    let mut description = format!("{} at {} (you play {color}", announcement.name, game.address);
+    if announcement.tls {
+        description += ", encrypted";
+    }
+    if announcement.reserved {
+        description += ", only for the opponent of a saved game";
+    }
+    description + ")"
+}
+
+/// Block until a client has connected and finished the handshake.
+fn wait_for_client(server_game: &mut ServerGame) {
+    log::info!(target: logging::NETWORK, "Waiting for client to connect...");
+    loop {
+        let state = server_game.get_protocol_state();
+
+        match state {
+            ProtocolState::NotConnected => {
+                log::debug!(target: logging::NETWORK, "Still waiting for client to connect");
+                server_game.try_accept_client();
+            },
+            ProtocolState::Handshake => {
+                log::debug!(target: logging::NETWORK, "Waiting for handshake");
+                server_game.try_handshake();
+            },
+            ProtocolState::Play => {
+                break;
+            }
+        }
+
+        thread::sleep(Duration::from_millis(1000));
+    }
 }
\ No newline at end of file
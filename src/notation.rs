@@ -0,0 +1,60 @@
+//! Turning moves into text.
+
+use crate::bridge::{CastlingSide, Move, PieceType, PlayedMove, Square};
+
+/// The name of a square, like `e4`.
+pub fn square_name(square: Square) -> String {
+    let file = (b'a' + square.file() as u8) as char;
+    let rank = square.rank() + 1;
+    format!("{file}{rank}")
+}
+
+/// A move in coordinate notation, like `e2e4` or `e7e8q`.
+pub fn move_name(played: &PlayedMove) -> String {
+    match played.mv {
+        Move::Normal { from, to } => {
+            let promotion = played.promotion.map_or("", |piece| match piece {
+                PieceType::Queen => "q",
+                PieceType::Rook => "r",
+                PieceType::Bishop => "b",
+                PieceType::Knight => "n",
+                PieceType::King | PieceType::Pawn => "",
+            });
+            format!("{}{}{promotion}", square_name(from), square_name(to))
+        }
+        Move::Castle { side: CastlingSide::KingSide } => String::from("O-O"),
+        Move::Castle { side: CastlingSide::QueenSide } => String::from("O-O-O"),
+    }
+}
+
+/// Read a square name written by [`square_name`].
+pub fn parse_square(name: &str) -> Option<Square> {
+    let mut chars = name.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?.to_digit(10)?;
+    if chars.next().is_some() || !('a'..='h').contains(&file) {
+        return None;
+    }
+    (file as i32 - 'a' as i32, rank as i32 - 1).try_into().ok()
+}
+
+/// Read a move written by [`move_name`].
+pub fn parse_move(name: &str) -> Option<PlayedMove> {
+    let castle = |side| Some(PlayedMove { mv: Move::Castle { side }, promotion: None });
+    match name {
+        "O-O" => return castle(CastlingSide::KingSide),
+        "O-O-O" => return castle(CastlingSide::QueenSide),
+        _ => {}
+    }
+    let from = parse_square(name.get(0..2)?)?;
+    let to = parse_square(name.get(2..4)?)?;
+    let promotion = match name.get(4..)? {
+        "" => None,
+        "q" => Some(PieceType::Queen),
+        "r" => Some(PieceType::Rook),
+        "b" => Some(PieceType::Bishop),
+        "n" => Some(PieceType::Knight),
+        _ => return None,
+    };
+    Some(PlayedMove { mv: Move::Normal { from, to }, promotion })
+}
@@ -0,0 +1,288 @@
+//! Reading and writing the moves of a game in PGN (portable game notation).
+//!
+//! Tags, comments, variations and annotations are skipped, only the main line is read.
+//! Moves are in standard algebraic notation, like `Nf3` or `exd8=Q+`, which only names the
+//! destination of a move, so the position is played along to find the piece that moved.
+
+use crate::backend::Backend;
+use crate::bridge::{CastlingSide, ChessGame, Move, PieceType, PlayedMove, Square};
+use crate::notation;
+
+/// Read the moves of the first game in `text`, following the rules of `backend`.
+pub fn parse(text: &str, backend: Backend) -> Result<Vec<PlayedMove>, String> {
+    let mut game = backend.new_game();
+    let mut history = vec![];
+    for token in tokens(text)? {
+        let played = parse_san(game.as_mut(), &token)?;
+        game.perform_move(played.mv)
+            .map_err(|err| format!("Illegal move {token}: {err}"))?;
+        if let (Move::Normal { to, .. }, Some(piece)) = (played.mv, played.promotion) {
+            game.promote(to, piece);
+        }
+        history.push(played);
+    }
+    Ok(history)
+}
+
+/// Write a game with the tags in `tags`, which should include the seven tag roster
+/// (`Event`, `Site`, `Date`, `Round`, `White`, `Black` and `Result`) in that order. The
+/// movetext ends with the value of the `Result` tag. `backend` is used to work out how to
+/// write the moves.
+///
+/// # Panics
+/// This function panics if a move in `history` is illegal.
+pub fn write(tags: &[(&str, String)], history: &[PlayedMove], backend: Backend) -> String {
+    let mut text = String::new();
+    for (name, value) in tags {
+        text += &format!("[{name} \"{}\"]\n", value.replace('\\', "\\\\").replace('"', "\\\""));
+    }
+    text.push('\n');
+
+    let mut game = backend.new_game();
+    let mut words = vec![];
+    for (index, played) in history.iter().enumerate() {
+        if index % 2 == 0 {
+            words.push(format!("{}.", index / 2 + 1));
+        }
+        words.push(write_san(game.as_mut(), played));
+    }
+    let result = tags.iter().find(|(name, _)| *name == "Result").map_or("*", |(_, value)| value.as_str());
+    words.push(result.to_string());
+
+    // Lines in export format are at most 80 characters long.
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > 80 {
+            text += &line;
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &word;
+    }
+    text += &line;
+    text.push('\n');
+    text
+}
+
+/// Split the movetext into moves, dropping everything else.
+fn tokens(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    // Comments can't be nested, unlike variations.
+    let mut in_comment = false;
+    let mut variation_depth = 0_usize;
+    let mut in_tag = false;
+    let mut in_line_comment = false;
+
+    let mut finish = |current: &mut String| {
+        // Move numbers like `12.` or `12...` may be written right before the move.
+        let token = current.rsplit('.').next().unwrap_or_default();
+        let is_result = matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*");
+        if !token.is_empty() && !token.starts_with('$') && !is_result {
+            tokens.push(token.to_string());
+        }
+        current.clear();
+    };
+
+    for c in text.chars() {
+        if in_line_comment {
+            in_line_comment = c != '\n';
+            continue;
+        }
+        match c {
+            '}' if in_comment => in_comment = false,
+            _ if in_comment => {}
+            '[' => in_tag = true,
+            ']' => in_tag = false,
+            _ if in_tag => {}
+            '{' => in_comment = true,
+            '}' => return Err(String::from("A comment is closed with } but was never opened")),
+            '(' => variation_depth += 1,
+            ')' => {
+                variation_depth = variation_depth.checked_sub(1)
+                    .ok_or_else(|| String::from("A variation is closed with ) but was never opened"))?;
+            }
+            _ if variation_depth > 0 => {}
+            ';' => in_line_comment = true,
+            c if c.is_whitespace() => finish(&mut current),
+            c => current.push(c),
+        }
+    }
+    if in_comment {
+        return Err(String::from("A comment opened with { is never closed"));
+    }
+    finish(&mut current);
+    Ok(tokens)
+}
+
+/// Find the move in `game` that `san` describes.
+fn parse_san(game: &mut dyn ChessGame, san: &str) -> Result<PlayedMove, String> {
+    let invalid = || format!("Invalid move: {san}");
+    let stripped = san.trim_end_matches(['+', '#', '!', '?']);
+
+    match stripped {
+        "O-O" | "0-0" => return Ok(PlayedMove { mv: Move::Castle { side: CastlingSide::KingSide }, promotion: None }),
+        "O-O-O" | "0-0-0" => return Ok(PlayedMove { mv: Move::Castle { side: CastlingSide::QueenSide }, promotion: None }),
+        _ => {}
+    }
+
+    let (stripped, promotion) = match stripped.split_once('=') {
+        Some((rest, piece)) => (rest, Some(piece_type(piece.chars().next().ok_or_else(invalid)?).ok_or_else(invalid)?)),
+        // Promotions are sometimes written without the `=`, like `e8Q`. Pawn moves are the
+        // only ones starting with a lowercase file.
+        None => match stripped.chars().last().and_then(piece_type) {
+            Some(piece) if stripped.starts_with(|c: char| c.is_ascii_lowercase()) => (&stripped[..stripped.len() - 1], Some(piece)),
+            _ => (stripped, None),
+        },
+    };
+
+    let mut chars = stripped.chars().peekable();
+    let piece = match chars.peek().copied().and_then(piece_type) {
+        Some(piece) => {
+            chars.next();
+            piece
+        }
+        None => PieceType::Pawn,
+    };
+    let rest: String = chars.filter(|c| *c != 'x').collect();
+    if rest.len() < 2 {
+        return Err(invalid());
+    }
+    let (disambiguation, destination) = rest.split_at(rest.len() - 2);
+    let to = notation::parse_square(destination).ok_or_else(invalid)?;
+
+    let mut candidates: Vec<Square> = vec![];
+    for from in own_squares(game) {
+        let name = notation::square_name(from);
+        if !disambiguation.chars().all(|c| name.contains(c)) {
+            continue;
+        }
+        if !game.get_piece(from).is_some_and(|on_square| on_square.kind == piece) {
+            continue;
+        }
+        if can_move_to(game, from, to) {
+            candidates.push(from);
+        }
+    }
+
+    match candidates[..] {
+        [from] => Ok(PlayedMove { mv: Move::Normal { from, to }, promotion }),
+        [] => Err(format!("No piece can make the move {san}")),
+        _ => Err(format!("Ambiguous move: {san}")),
+    }
+}
+
+/// Write `played` in standard algebraic notation and play it in `game`.
+fn write_san(game: &mut dyn ChessGame, played: &PlayedMove) -> String {
+    let mut san = match played.mv {
+        Move::Castle { side: CastlingSide::KingSide } => String::from("O-O"),
+        Move::Castle { side: CastlingSide::QueenSide } => String::from("O-O-O"),
+        Move::Normal { from, to } => {
+            let piece = game.get_piece(from).map_or(PieceType::Pawn, |on_square| on_square.kind);
+            let from_name = notation::square_name(from);
+            let is_pawn = matches!(piece, PieceType::Pawn);
+            // Pawns capturing en passant land on an empty square, but change file like
+            // any other pawn capture.
+            let is_capture = game.get_piece(to).is_some() || (is_pawn && from.file() != to.file());
+
+            let mut san = String::new();
+            if is_pawn {
+                if is_capture {
+                    san.push_str(&from_name[..1]);
+                }
+            } else {
+                san.push(piece_letter(piece));
+                san += &disambiguation(game, piece, from, to);
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san += &notation::square_name(to);
+            if let Some(promotion) = played.promotion {
+                san.push('=');
+                san.push(piece_letter(promotion));
+            }
+            san
+        }
+    };
+
+    game.perform_move(played.mv)
+        .unwrap_or_else(|err| panic!("Written move {san} was illegal: {err}"));
+    if let (Move::Normal { to, .. }, Some(piece)) = (played.mv, played.promotion) {
+        game.promote(to, piece);
+    }
+    if game.is_check() {
+        san.push(if game.is_over() { '#' } else { '+' });
+    }
+    san
+}
+
+/// What has to be added after the piece letter to tell the move of the `piece` on `from`
+/// to `to` apart from the moves of the other pieces of the same type that can go there.
+fn disambiguation(game: &mut dyn ChessGame, piece: PieceType, from: Square, to: Square) -> String {
+    let mut others: Vec<Square> = vec![];
+    for square in own_squares(game) {
+        if square == from || !game.get_piece(square).is_some_and(|on_square| on_square.kind == piece) {
+            continue;
+        }
+        if can_move_to(game, square, to) {
+            others.push(square);
+        }
+    }
+    let name = notation::square_name(from);
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|other| other.file() != from.file()) {
+        name[..1].to_string()
+    } else if others.iter().all(|other| other.rank() != from.rank()) {
+        name[1..].to_string()
+    } else {
+        name
+    }
+}
+
+/// Whether the piece on `from` can move to `to`.
+fn can_move_to(game: &mut dyn ChessGame, from: Square, to: Square) -> bool {
+    game.possible_moves(from).is_ok_and(|moves| {
+        moves.iter().any(|mv| matches!(mv, Move::Normal { to: target, .. } if *target == to))
+    })
+}
+
+/// The squares with a piece of the color to move.
+fn own_squares(game: &dyn ChessGame) -> Vec<Square> {
+    let mut squares = vec![];
+    for rank in 0..8 {
+        for file in 0..8 {
+            let square: Square = (file, rank).try_into().unwrap();
+            if game.get_piece(square).is_some_and(|piece| piece.color == game.current_turn()) {
+                squares.push(square);
+            }
+        }
+    }
+    squares
+}
+
+fn piece_type(letter: char) -> Option<PieceType> {
+    match letter {
+        'K' => Some(PieceType::King),
+        'Q' => Some(PieceType::Queen),
+        'R' => Some(PieceType::Rook),
+        'B' => Some(PieceType::Bishop),
+        'N' => Some(PieceType::Knight),
+        _ => None,
+    }
+}
+
+fn piece_letter(piece: PieceType) -> char {
+    match piece {
+        PieceType::King => 'K',
+        PieceType::Queen => 'Q',
+        PieceType::Rook => 'R',
+        PieceType::Bishop => 'B',
+        PieceType::Knight => 'N',
+        PieceType::Pawn => 'P',
+    }
+}
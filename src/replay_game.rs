@@ -0,0 +1,150 @@
+use crate::backend::Backend;
+use crate::bridge::{self, Board, ChessGame, Color, GameState, Move, MoveError, Piece, PieceType, PlayedMove, Square};
+use crate::logging;
+use crate::notation;
+use crate::session::GameSession;
+
+/// A finished game that is looked through one move at a time. The moves can't be changed.
+pub struct ReplayGame {
+    backend: Backend,
+    game: Box<dyn ChessGame>,
+    moves: Vec<PlayedMove>,
+    /// How many of the moves have been played on the board.
+    position: usize,
+    /// Why the last move couldn't be played, until the view has shown it.
+    error: Option<String>,
+}
+
+impl ReplayGame {
+    pub fn new(moves: Vec<PlayedMove>) -> Self {
+        let backend = Backend::default();
+        Self {
+            backend,
+            game: backend.new_game(),
+            moves,
+            position: 0,
+            error: None,
+        }
+    }
+
+    /// Replay the moves with the rules of `backend` instead of the default one.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self.game = backend.replay(&self.moves[..self.position]);
+        self
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.moves.len()
+    }
+
+    /// Play the next move. Returns false if there are no more moves or the next one is
+    /// illegal, which is reported through [`take_error`](GameSession::take_error).
+    pub fn step_forward(&mut self) -> bool {
+        let Some(played) = self.moves.get(self.position).copied() else {
+            return false;
+        };
+        // Saved games can be edited or come from somewhere else, so the moves may not be
+        // legal after all.
+        if let Err(err) = self.game.perform_move(played.mv) {
+            let name = notation::move_name(&played);
+            log::warn!(target: logging::APP, "Move {} of the replay, {name}, is illegal: {err}", self.position + 1);
+            self.error = Some(format!("Move {} ({name}) is illegal, so the replay stops here: {err}", self.position + 1));
+            return false;
+        }
+        if let (Move::Normal { to, .. }, Some(piece)) = (played.mv, played.promotion) {
+            self.game.promote(to, piece);
+        }
+        self.position += 1;
+        true
+    }
+
+    /// Take back the last move played on the board. Returns false if at the start.
+    pub fn step_back(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.go_to(self.position - 1);
+        true
+    }
+
+    /// Show the position after `position` moves.
+    pub fn go_to(&mut self, position: usize) {
+        let position = position.min(self.moves.len());
+        if position >= self.position {
+            while self.position < position && self.step_forward() {}
+        } else {
+            self.game = self.backend.replay(&self.moves[..position]);
+            self.position = position;
+        }
+    }
+}
+
+impl bridge::ChessGame for ReplayGame {
+    fn update(&mut self) {
+
+    }
+
+    fn get_pieces(&self) -> Board {
+        self.game.get_pieces()
+    }
+
+    fn get_piece(&self, at: Square) -> Option<Piece> {
+        self.game.get_piece(at)
+    }
+
+    fn get_state(&self) -> GameState {
+        self.game.get_state()
+    }
+
+    fn is_check(&self) -> bool {
+        self.game.is_check()
+    }
+
+    fn current_turn(&self) -> Color {
+        self.game.current_turn()
+    }
+
+    fn promote(&mut self, _promotion_square: Square, _piece: PieceType) {
+        // The replay can't be changed.
+    }
+
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        self.game.possible_moves(at)
+    }
+
+    fn perform_move(&mut self, _mv: Move) -> Result<(), MoveError> {
+        // The replay can't be changed.
+        Ok(())
+    }
+
+    fn can_play_right_now(&self) -> bool {
+        false
+    }
+
+    fn has_possible_moves(&self) -> bool {
+        true
+    }
+
+    fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+}
+
+impl GameSession for ReplayGame {
+    fn history(&self) -> &[PlayedMove] {
+        &self.moves[..self.position]
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+}
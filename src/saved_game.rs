@@ -0,0 +1,153 @@
+//! Saving games in progress so that they can be resumed after the app is closed.
+//!
+//! Only the moves are stored. The position is restored by replaying them, the same way
+//! takebacks work.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::bridge::{Color, PlayedMove};
+use crate::clock::Clocks;
+use crate::logging;
+use crate::notation;
+use crate::settings::config_dir;
+
+const SAVED_GAME_FILE: &str = "saved_game.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SavedColor {
+    White,
+    Black,
+}
+
+impl From<Color> for SavedColor {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::White => SavedColor::White,
+            Color::Black => SavedColor::Black,
+        }
+    }
+}
+
+impl From<SavedColor> for Color {
+    fn from(color: SavedColor) -> Self {
+        match color {
+            SavedColor::White => Color::White,
+            SavedColor::Black => Color::Black,
+        }
+    }
+}
+
+/// How the saved game was being played.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SavedSession {
+    Local,
+    /// We were hosting. `peer` is the address of the opponent, who is the only one allowed
+    /// to connect when the game is resumed. If `engine` is set, our engine was playing.
+    Server {
+        port: u16,
+        color: SavedColor,
+        peer: Option<String>,
+        #[serde(default)]
+        tls: bool,
+        #[serde(default)]
+        engine: bool,
+    },
+    /// We had joined the server at `address`. If the connection was encrypted,
+    /// `tls_fingerprint` is the server's fingerprint and only that server is accepted when
+    /// rejoining.
+    Client {
+        address: String,
+        port: u16,
+        #[serde(default)]
+        tls_fingerprint: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedGame {
+    pub session: SavedSession,
+    /// The moves in coordinate notation, like `e2e4`.
+    pub moves: Vec<String>,
+    pub clocks: Clocks,
+}
+
+impl SavedGame {
+    pub fn new(session: SavedSession, history: &[PlayedMove], clocks: Clocks) -> Self {
+        Self {
+            session,
+            moves: history.iter().map(notation::move_name).collect(),
+            clocks,
+        }
+    }
+
+    pub fn history(&self) -> Result<Vec<PlayedMove>, String> {
+        self.moves.iter()
+            .map(|name| notation::parse_move(name).ok_or_else(|| format!("Invalid move in saved game: {name}")))
+            .collect()
+    }
+
+    /// Load the saved game, if there is one.
+    pub fn load() -> Option<SavedGame> {
+        let path = path()?;
+        if !path.exists() {
+            return None;
+        }
+        match Self::load_from(&path) {
+            Ok(saved) => Some(saved),
+            Err(err) => {
+                log::warn!(target: logging::APP, "Ignoring broken saved game: {err}");
+                None
+            }
+        }
+    }
+
+    /// Load a game saved to `path`.
+    pub fn load_from(path: &Path) -> Result<SavedGame, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+        serde_json::from_str(&contents).map_err(|err| format!("Failed to read {}: {err}", path.display()))
+    }
+
+    /// The path the game is saved to automatically.
+    pub fn default_path() -> Option<PathBuf> {
+        path()
+    }
+
+    /// Write the game to disk, replacing any game saved earlier.
+    ///
+    /// The game is written to a temporary file that is then renamed, so that closing the
+    /// app while saving leaves the earlier save rather than half of the new one.
+    pub fn save(&self) {
+        let Some(path) = path() else {
+            return;
+        };
+        let temp_path = path.with_extension("json.tmp");
+        let result = path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&temp_path, serde_json::to_string_pretty(self).unwrap()))
+            .and_then(|_| fs::rename(&temp_path, &path));
+        if let Err(err) = result {
+            log::warn!(target: logging::APP, "Failed to save game to {}: {err}", path.display());
+        }
+    }
+
+    /// Remove the saved game, for when it is over and there is nothing left to resume.
+    pub fn clear() {
+        let Some(path) = path() else {
+            return;
+        };
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!(target: logging::APP, "Failed to remove the saved game {}: {err}", path.display()),
+        }
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(SAVED_GAME_FILE))
+}
@@ -1,19 +1,59 @@
-use std::net::TcpListener;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
 
 use chess_network_protocol::{ServerToClient, Joever, ClientToServerHandshake, ServerToClientHandshake, Piece as ProtocolPiece, Move as ProtocolMove, Color as ProtocolColor, Features, ClientToServer};
-use erikfran_chess::{Color, Move, MoveError, Piece, PieceTypes};
-use erikfran_chess::util::{BoardMove, Square};
+use serde::Serialize;
 
-use crate::bridge::{self, ChessGame};
+use crate::bridge::{self, Board, CastlingSide, ChessGame, Color, Move, MoveError, Piece, PieceType, PlayedMove, Square};
+use crate::discovery::{Announcement, Beacon};
+use crate::extension::{self, ExtensionMessage, Incoming};
 use crate::json_tcp_stream::JsonTcpStream;
-
-pub struct ServerGame {
-    game: erikfran_chess::Game,
-    listener: TcpListener,
+use crate::logging;
+use crate::notation;
+use crate::saved_game::SavedSession;
+use crate::session::{self, ChatMessage, Decision, DrawState, GameSession, RematchState, TakebackState};
+use crate::tls::Transport;
+
+/// Hosts a game for a client to join. The host's side of the game is played with `G`,
+/// which also referees the client's moves.
+pub struct ServerGame<G: ChessGame = Box<dyn ChessGame>> {
+    game: G,
+    /// Creates `G` in the starting position, for rematches and for replaying the game
+    /// after a takeback.
+    new_game: Box<dyn Fn() -> G>,
+    /// Usually one listener for IPv6 and one for IPv4.
+    listeners: Vec<TcpListener>,
+    port: u16,
     client: Option<JsonTcpStream>,
+    /// The address of the connected client.
+    peer: Option<SocketAddr>,
+    /// When resuming a game, only the opponent from before may connect.
+    expected_peer: Option<IpAddr>,
     protocol_state: ProtocolState,
     last_move_made: Option<ProtocolMove>,
     server_color: Color,
+    history: Vec<PlayedMove>,
+    takeback: TakebackState,
+    /// The extensions the client has said it understands.
+    client_extensions: Vec<String>,
+    /// Set when clients must connect with TLS.
+    tls: Option<Arc<rustls::ServerConfig>>,
+    /// Connections whose TLS handshake has finished on a worker thread, so that a slow
+    /// client doesn't block the UI while connecting.
+    handshaken: Receiver<(Transport, SocketAddr)>,
+    handshaken_sender: Sender<(Transport, SocketAddr)>,
+    chat: Vec<ChatMessage>,
+    rematch: RematchState,
+    rematches: usize,
+    /// The color that has offered a draw since the last move.
+    draw_offer: Option<Color>,
+    /// Set once the game has been resigned or drawn by agreement.
+    decision: Option<Decision>,
+    /// Announces the game on the local network while waiting for a client, together with
+    /// the name to announce.
+    beacon: Option<(Beacon, String)>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -23,33 +63,133 @@ pub enum ProtocolState {
     Play,
 }
 
-impl ServerGame {
-    pub fn new(game: erikfran_chess::Game, port: u16) -> Self {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).unwrap();
-        listener.set_nonblocking(true).unwrap();
+impl<G: ChessGame> ServerGame<G> {
+    /// Wait for a client on `port`. `new_game` creates the game in the starting position.
+    pub fn new(new_game: impl Fn() -> G + 'static, port: u16) -> Self {
+        let (handshaken_sender, handshaken) = mpsc::channel();
         Self {
-            game,
-            listener,
+            game: new_game(),
+            new_game: Box::new(new_game),
+            listeners: listen(port),
+            port,
             client: None,
+            peer: None,
+            expected_peer: None,
             protocol_state: ProtocolState::NotConnected,
             last_move_made: None,
             server_color: Color::Black,
+            history: vec![],
+            takeback: TakebackState::None,
+            client_extensions: vec![],
+            tls: None,
+            handshaken,
+            handshaken_sender,
+            chat: vec![],
+            rematch: RematchState::None,
+            rematches: 0,
+            draw_offer: None,
+            decision: None,
+            beacon: None,
+        }
+    }
+
+    /// Announce the game on the local network as `name` until a client connects.
+    pub fn with_announcement(self, name: String) -> Self {
+        match Beacon::broadcast() {
+            Ok(beacon) => self.with_beacon(beacon, name),
+            Err(err) => {
+                log::warn!(target: logging::NETWORK, "Can't announce the game on the local network: {err}");
+                self
+            }
         }
     }
 
+    /// Announce the game as `name` with `beacon` until a client connects.
+    pub fn with_beacon(mut self, beacon: Beacon, name: String) -> Self {
+        self.beacon = Some((beacon, name));
+        self
+    }
+
+    /// Require clients to connect with TLS.
+    pub fn with_tls(mut self, config: Arc<rustls::ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Continue a game where `history` has been played, waiting for the opponent at
+    /// `peer` to connect again.
+    pub fn resume(new_game: impl Fn() -> G + 'static, history: Vec<PlayedMove>, port: u16, server_color: Color, peer: Option<IpAddr>) -> Self {
+        let mut server_game = Self::new(new_game, port);
+        bridge::replay_onto(&mut server_game.game, &history);
+        server_game.last_move_made = last_move(&history);
+        server_game.history = history;
+        server_game.server_color = server_color;
+        server_game.expected_peer = peer.map(|peer| peer.to_canonical());
+        server_game
+    }
+
     pub fn get_protocol_state(&self) -> ProtocolState { self.protocol_state }
 
+    /// The port clients connect to. This is useful when the server was created with port 0
+    /// to get any free port.
+    pub fn local_port(&self) -> u16 {
+        self.listeners.first()
+            .and_then(|listener| listener.local_addr().ok())
+            .map_or(self.port, |addr| addr.port())
+    }
+
     pub fn try_accept_client(&mut self) {
-        let res = self.listener.accept();
-        if let Ok((stream, addr)) = res {
-            self.client = Some(JsonTcpStream::new(stream));
-            self.protocol_state = ProtocolState::Handshake;
-            println!("{} connected", addr);
-        } else {
-            // println!("{:?}", res);
+        self.announce();
+        let res = self.listeners.iter().find_map(|listener| listener.accept().ok());
+        if let Some((stream, addr)) = res {
+            // IPv4 clients connecting to the IPv6 listener show up as IPv4-mapped IPv6
+            // addresses, which wouldn't match the address of a saved game.
+            let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+            if self.expected_peer.is_some_and(|expected| expected != addr.ip()) {
+                log::warn!(target: logging::NETWORK, "Refusing {addr}, waiting for the opponent of the saved game to reconnect");
+                return;
+            }
+            match &self.tls {
+                None => self.connect(Transport::Plain(stream), addr),
+                Some(config) => {
+                    let config = config.clone();
+                    let handshaken = self.handshaken_sender.clone();
+                    thread::spawn(move || match Transport::accept(stream, config) {
+                        Ok(transport) => {
+                            let _ = handshaken.send((transport, addr));
+                        }
+                        Err(err) => log::warn!(target: logging::NETWORK, "TLS handshake with {addr} failed: {err}"),
+                    });
+                }
+            }
+        }
+        if let Ok((transport, addr)) = self.handshaken.try_recv() {
+            self.connect(transport, addr);
         }
     }
 
+    fn connect(&mut self, transport: Transport, addr: SocketAddr) {
+        self.client = Some(JsonTcpStream::with_transport(transport));
+        self.peer = Some(addr);
+        self.protocol_state = ProtocolState::Handshake;
+        self.beacon = None;
+        log::info!(target: logging::NETWORK, "{} connected", addr);
+    }
+
+    fn announce(&mut self) {
+        let port = self.local_port();
+        let Some((beacon, name)) = &mut self.beacon else {
+            return;
+        };
+        beacon.announce(&Announcement::new(
+            name.clone(),
+            port,
+            self.server_color.opposite().into(),
+            self.expected_peer.is_some(),
+            self.tls.is_some(),
+        ));
+    }
+
     /// Attempt to handle the client handshake if it has been received.
     /// 
     /// # Panics
@@ -62,128 +202,360 @@ impl ServerGame {
             None => return,
         };
 
-        self.server_color = match handshake.server_color {
+        let server_color = match handshake.server_color {
             ProtocolColor::White => Color::White,
             ProtocolColor::Black => Color::Black,
         };
+        if !self.history.is_empty() && server_color != self.server_color {
+            log::warn!(target: logging::NETWORK, "The client asked for the other color than in the saved game, so the colors are swapped.");
+        }
+        self.server_color = server_color;
 
-        println!("Got handshake {:?}", handshake);
+        let moves = self.get_moves();
         let server_handshake = ServerToClientHandshake {
             board: convert_board(self.get_pieces()),
-            moves: self.get_moves(),
-            joever: Joever::Ongoing,
-            features: vec![
-                Features::PossibleMoveGeneration,
-            ],
+            joever: self.joever(&moves),
+            moves,
+            features: [
+                vec![Features::PossibleMoveGeneration],
+                extension::features(),
+            ].concat(),
         };
-        let stream = self.client.as_mut().unwrap().stream();
-        serde_json::to_writer(stream, &server_handshake).unwrap();
-        println!("sent {:?}", server_handshake);
+        self.send(&server_handshake);
         self.protocol_state = ProtocolState::Play;
 
     }
 
     pub fn get_moves(&mut self) -> Vec<ProtocolMove> {
-        let mut moves = vec![];
-        for file in 0..8 {
-            for rank in 0..8 {
-                let square = (file, rank).try_into().unwrap();
-                if let Ok((board_move, _castle_moves)) = self.game.possible_moves(square, true) {
-                    for row in board_move.rows.squares {
-                        for piece in row.squares {
-                            if let Some(mv) = piece {
-                                moves.push(convert_move(mv));
-                            }
-                        }
-                    }
-                }
-            }
+        legal_moves(&mut self.game)
+    }
+
+    /// How the game has ended, given the `moves` the side to move can make.
+    fn joever(&self, moves: &[ProtocolMove]) -> Joever {
+        let winner = match self.decision {
+            Some(decision) => decision.winner(),
+            None if !moves.is_empty() => return Joever::Ongoing,
+            None if self.game.is_check() => Some(self.game.current_turn().opposite()),
+            None => None,
+        };
+        match winner {
+            Some(Color::White) => Joever::White,
+            Some(Color::Black) => Joever::Black,
+            None => Joever::Draw,
         }
-        moves
     }
 
     pub fn send_state(&mut self) {
         let board = convert_board(self.get_pieces());
+        let moves = self.get_moves();
         let state = ServerToClient::State {
             board,
-            moves: self.get_moves(),
+            joever: self.joever(&moves),
+            moves,
             move_made: self.last_move_made.expect("Cannot call send_state when no last move."),
-            joever: Joever::Ongoing,
         };
-        if let Some(ref mut stream) = &mut self.client {
-            serde_json::to_writer(stream.stream(), &state).unwrap();
+        self.send(&state);
+        self.send_position_hash();
+    }
+
+    fn send_position_hash(&mut self) {
+        if !self.client_supports(extension::POSITION_HASH) {
+            return;
+        }
+        let hash = extension::position_hash(&self.get_pieces(), self.game.current_turn());
+        self.send_extension(ExtensionMessage::PositionHash { plies: self.history.len(), hash });
+    }
+
+    fn send_extension(&mut self, message: ExtensionMessage) {
+        self.send(&message);
+    }
+
+    /// Send `message` to the client. If that fails the connection is closed and the error
+    /// is logged, and nothing more is sent.
+    fn send<T: Serialize>(&mut self, message: &T) {
+        let Some(stream) = &mut self.client else {
+            return;
+        };
+        if stream.is_closed() {
+            return;
+        }
+        if let Err(err) = stream.write(message) {
+            log::warn!(target: logging::NETWORK, "Lost the connection to the client: {err}");
+        }
+    }
+
+    fn client_supports(&self, extension: &str) -> bool {
+        self.client_extensions.iter().any(|name| name == extension)
+    }
+
+    /// Take back the last `plies` half-moves and tell the client about the new position.
+    fn perform_takeback(&mut self, plies: usize) {
+        self.history.truncate(self.history.len() - plies);
+        self.game = (self.new_game)();
+        bridge::replay_onto(&mut self.game, &self.history);
+        self.last_move_made = last_move(&self.history);
+        self.takeback = TakebackState::None;
+        self.draw_offer = None;
+        self.decision = None;
+
+        let performed = ExtensionMessage::TakebackPerformed {
+            plies,
+            board: convert_board(self.get_pieces()),
+            moves: self.get_moves(),
+        };
+        self.send_extension(performed);
+        self.send_position_hash();
+    }
+
+    /// Start a new game with the same client and tell it about the new position.
+    fn start_rematch(&mut self, swap_colors: bool) {
+        self.game = (self.new_game)();
+        self.history.clear();
+        self.last_move_made = None;
+        self.takeback = TakebackState::None;
+        self.rematch = RematchState::None;
+        self.rematches += 1;
+        self.draw_offer = None;
+        self.decision = None;
+        if swap_colors {
+            self.server_color = self.server_color.opposite();
+        }
+        log::info!(target: logging::NETWORK, "Starting a rematch{}", if swap_colors { " with the colors swapped" } else { "" });
+
+        let started = ExtensionMessage::RematchStarted {
+            server_color: match self.server_color {
+                Color::White => ProtocolColor::White,
+                Color::Black => ProtocolColor::Black,
+            },
+            board: convert_board(self.get_pieces()),
+            moves: self.get_moves(),
+        };
+        self.send_extension(started);
+        self.send_position_hash();
+    }
+
+    /// Make the move of a backend that plays by itself, once the client is there to see it.
+    fn play_engine_move(&mut self) {
+        if !matches!(self.protocol_state, ProtocolState::Play) || self.game.current_turn() != self.server_color || self.decision.is_some() {
+            return;
+        }
+        let Some(played) = self.game.engine_move() else {
+            return;
+        };
+        let Err(err) = self.play(played) else {
+            return;
+        };
+        // Asking again would give the same move, so play any move the game allows instead.
+        log::warn!(target: logging::NETWORK, "The engine chose an illegal move, playing another one: {err}");
+        let board = self.get_pieces();
+        for mv in bridge::all_moves(&mut self.game) {
+            let promotion = bridge::is_promotion(&board, mv).then_some(PieceType::Queen);
+            if self.play(PlayedMove { mv, promotion }).is_ok() {
+                return;
+            }
+        }
+    }
+
+    /// Make a move, including the promotion if it is already known, and tell the client
+    /// about it.
+    fn play(&mut self, played: PlayedMove) -> Result<(), MoveError> {
+        if self.decision.is_some() {
+            return Err(MoveError::new("The game is over."));
+        }
+        let color = self.game.current_turn();
+        self.game.perform_move(played.mv)?;
+        if let (Move::Normal { to, .. }, Some(piece)) = (played.mv, played.promotion) {
+            self.game.promote(to, piece);
+        }
+        self.history.push(played);
+        self.draw_offer = None;
+        self.last_move_made = Some(ProtocolMove {
+            promotion: convert_promotion(played.promotion, color),
+            ..convert_move(played.mv, color)
+        });
+        self.send_state();
+        Ok(())
+    }
+
+    /// End the game by resignation or agreement and tell the client.
+    fn end_by(&mut self, decision: Decision) {
+        if self.decision.is_some() {
+            return;
+        }
+        self.decision = Some(decision);
+        self.draw_offer = None;
+        log::info!(target: logging::NETWORK, "The game ended: {decision:?}");
+        let board = convert_board(self.get_pieces());
+        let packet = match decision {
+            Decision::Resignation(_) => ServerToClient::Resigned { board, joever: self.joever(&[]) },
+            Decision::DrawAgreed => ServerToClient::Draw { board, moves: self.get_moves() },
+        };
+        self.send(&packet);
+    }
+
+    fn handle_extension(&mut self, message: ExtensionMessage) {
+        match message {
+            ExtensionMessage::Hello { extensions } => {
+                self.client_extensions = extensions;
+            }
+            ExtensionMessage::TakebackRequest => {
+                if self.takeback == TakebackState::Requested {
+                    // Both sides asked at the same time, so both of them agree to take
+                    // back their last moves.
+                    let plies = session::takeback_plies(self.history.len(), self.server_color)
+                        .max(session::takeback_plies(self.history.len(), self.server_color.opposite()));
+                    self.perform_takeback(plies);
+                } else {
+                    self.takeback = TakebackState::Offered;
+                }
+            }
+            ExtensionMessage::TakebackResponse { accepted } => {
+                if self.takeback != TakebackState::Requested {
+                    return;
+                }
+                if accepted {
+                    self.perform_takeback(session::takeback_plies(self.history.len(), self.server_color));
+                } else {
+                    self.takeback = TakebackState::None;
+                }
+            }
+            ExtensionMessage::TakebackPerformed { .. } => {
+                // Only the server performs takebacks.
+            }
+            ExtensionMessage::PositionHash { plies, hash } => {
+                let our_hash = extension::position_hash(&self.get_pieces(), self.game.current_turn());
+                if plies != self.history.len() || hash != our_hash {
+                    // The client will ask for a resync if it notices too.
+                    log::warn!(target: logging::NETWORK, "The client's position differs after {plies} half-moves, ours is after {}", self.history.len());
+                }
+            }
+            ExtensionMessage::ResyncRequest => {
+                log::info!(target: logging::NETWORK, "Sending the whole game to the client to resync");
+                let resync = ExtensionMessage::Resync {
+                    board: convert_board(self.get_pieces()),
+                    moves: self.get_moves(),
+                    history: self.history.iter().map(notation::move_name).collect(),
+                };
+                self.send_extension(resync);
+            }
+            ExtensionMessage::Resync { .. } => {
+                // Only the server's game counts.
+            }
+            ExtensionMessage::Chat { text } => {
+                if let Some(text) = extension::clean_chat(&text) {
+                    self.chat.push(ChatMessage { ours: false, text });
+                }
+            }
+            ExtensionMessage::RematchOffer { swap_colors } => {
+                self.rematch = RematchState::Offered { swap_colors };
+            }
+            ExtensionMessage::RematchResponse { accepted } => {
+                let RematchState::Requested { swap_colors } = self.rematch else {
+                    return;
+                };
+                if accepted {
+                    self.start_rematch(swap_colors);
+                } else {
+                    self.rematch = RematchState::None;
+                }
+            }
+            ExtensionMessage::RematchStarted { .. } => {
+                // Only the server starts new games.
+            }
         }
     }
 }
 
-impl bridge::ChessGame for ServerGame {
+impl<G: ChessGame> bridge::ChessGame for ServerGame<G> {
     fn update(&mut self) {
+        self.play_engine_move();
+
         let client = match &mut self.client {
             Some(client) => client,
             None => return,
         };
 
         let packet: ClientToServer = match client.read() {
-            Some(packet) => packet,
+            Some(Incoming::Protocol(packet)) => packet,
+            Some(Incoming::Extension(message)) => {
+                self.handle_extension(message);
+                return;
+            }
             None => return,
         };
 
         match packet {
             ClientToServer::Move(mv) => {
-                let from: Square = (mv.start_x as i32, mv.start_y as i32).try_into().unwrap();
-                let to: Square = (mv.end_x as i32, mv.end_y as i32).try_into().unwrap();
-                let erikfran_move = Move::Normal { from, to };
-
-                match self.game.perform_move(erikfran_move) {
-                    Ok(_) => {
+                // We decide what is legal, and the game itself would let the client move our
+                // pieces too.
+                let result = if self.game.current_turn() == self.server_color {
+                    Err(MoveError::new("It is not your turn."))
+                } else if let Some(played) = parse_played_move(mv, &self.get_pieces()) {
+                    self.play(played)
+                } else {
+                    Err(MoveError::new("Invalid square"))
+                };
+                match result {
+                    Ok(()) => {
                         // Client move accepted.
-                        self.last_move_made = Some(mv);
-                        self.send_state();
                     }
                     Err(err) => {
+                        let moves = self.get_moves();
                         let error_packet = ServerToClient::Error {
                             board: convert_board(self.get_pieces()),
-                            moves: self.get_moves(),
-                            joever: Joever::Ongoing,
+                            joever: self.joever(&moves),
+                            moves,
                             message: format!("{}", err),
                         };
-                        serde_json::to_writer(self.client.as_mut().unwrap().stream(), &error_packet).unwrap();
+                        self.send(&error_packet);
                     }
                 }
             }
-            ClientToServer::Resign => {}
-            ClientToServer::Draw => {}
+            ClientToServer::Resign => self.end_by(Decision::Resignation(self.server_color.opposite())),
+            ClientToServer::Draw => {
+                if self.decision.is_some() {
+                    return;
+                }
+                if self.draw_offer == Some(self.server_color) {
+                    self.end_by(Decision::DrawAgreed);
+                } else {
+                    self.draw_offer = Some(self.server_color.opposite());
+                }
+            }
         }
     }
 
-    fn perform_move(&mut self, mv: erikfran_chess::Move) -> Result<(), MoveError> {
-        // Perform the move
-        let res = self.game.perform_move(mv);
-        self.last_move_made = Some(convert_move(mv));
-        if res.is_ok() {
-            // If the move actually happened, notify the client.
-            self.send_state();
-        }
-        res
+    fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        // The piece to promote to is chosen afterwards, see promote.
+        self.play(PlayedMove { mv, promotion: None })
     }
 
     fn promote(
         &mut self,
         promotion_square: Square,
-        piece: PieceTypes,
+        piece: PieceType,
     ) {
-        self.game.promote(promotion_square, piece)
+        let color = self.game.current_turn().opposite();
+        self.game.promote(promotion_square, piece);
+        if let Some(last) = self.history.last_mut() {
+            last.promotion = Some(piece);
+        }
 
-        // TODO networking
+        // The client was sent the move before the piece was chosen, so send the position
+        // again with the promoted piece.
+        if let Some(last_move) = &mut self.last_move_made {
+            last_move.promotion = convert_promotion(Some(piece), color);
+            self.send_state();
+        }
     }
 
     fn can_play_right_now(&self) -> bool {
-        self.game.turn == self.server_color
+        self.game.current_turn() == self.server_color
     }
 
-    // Delegate informational methods to the erikfran chess backend.
+    // Delegate informational methods to the backend.
 
-    fn get_pieces(&self) -> [[Option<Piece>; 8]; 8] {
+    fn get_pieces(&self) -> Board {
         self.game.get_pieces()
     }
 
@@ -191,8 +563,8 @@ impl bridge::ChessGame for ServerGame {
         self.game.get_piece(at)
     }
 
-    fn possible_moves( &mut self, at: Square) -> Result<(BoardMove, Vec<Move>), MoveError> {
-        bridge::ChessGame::possible_moves(&mut self.game, at)
+    fn possible_moves( &mut self, at: Square) -> Result<Vec<Move>, MoveError> {
+        self.game.possible_moves(at)
     }
 
     fn get_state(&self) -> bridge::GameState {
@@ -203,57 +575,288 @@ impl bridge::ChessGame for ServerGame {
         self.game.is_check()
     }
 
-    fn current_turn(&self) -> erikfran_chess::Color {
+    fn current_turn(&self) -> Color {
         self.game.current_turn()
     }
 
     fn has_possible_moves(&self) -> bool {
         true
     }
+
+    fn is_over(&self) -> bool {
+        self.decision.is_some() || self.game.is_over()
+    }
+}
+
+impl<G: ChessGame> GameSession for ServerGame<G> {
+    fn player_color(&self) -> Option<Color> {
+        Some(self.server_color)
+    }
+
+    fn history(&self) -> &[PlayedMove] {
+        &self.history
+    }
+
+    fn request_takeback(&mut self) {
+        if self.takeback != TakebackState::None || !self.client_supports(extension::TAKEBACK) {
+            return;
+        }
+        if session::takeback_plies(self.history.len(), self.server_color) == 0 {
+            return;
+        }
+        self.takeback = TakebackState::Requested;
+        self.send_extension(ExtensionMessage::TakebackRequest);
+    }
+
+    fn takeback_state(&self) -> TakebackState {
+        self.takeback
+    }
+
+    fn answer_takeback(&mut self, accept: bool) {
+        if self.takeback != TakebackState::Offered {
+            return;
+        }
+        if accept {
+            self.perform_takeback(session::takeback_plies(self.history.len(), self.server_color.opposite()));
+        } else {
+            self.takeback = TakebackState::None;
+            self.send_extension(ExtensionMessage::TakebackResponse { accepted: false });
+        }
+    }
+
+    fn supports_chat(&self) -> bool {
+        self.client_supports(extension::CHAT)
+    }
+
+    fn send_chat(&mut self, text: &str) {
+        let Some(text) = extension::clean_chat(text) else {
+            return;
+        };
+        if !self.supports_chat() {
+            return;
+        }
+        self.send_extension(ExtensionMessage::Chat { text: text.clone() });
+        self.chat.push(ChatMessage { ours: true, text });
+    }
+
+    fn chat_messages(&self) -> &[ChatMessage] {
+        &self.chat
+    }
+
+    fn offer_rematch(&mut self, swap_colors: bool) {
+        if self.rematch != RematchState::None || !self.client_supports(extension::REMATCH) {
+            return;
+        }
+        self.rematch = RematchState::Requested { swap_colors };
+        self.send_extension(ExtensionMessage::RematchOffer { swap_colors });
+    }
+
+    fn rematch_state(&self) -> RematchState {
+        self.rematch
+    }
+
+    fn answer_rematch(&mut self, accept: bool) {
+        let RematchState::Offered { swap_colors } = self.rematch else {
+            return;
+        };
+        if accept {
+            self.start_rematch(swap_colors);
+        } else {
+            self.rematch = RematchState::None;
+            self.send_extension(ExtensionMessage::RematchResponse { accepted: false });
+        }
+    }
+
+    fn rematches(&self) -> usize {
+        self.rematches
+    }
+
+    fn resign(&mut self) {
+        self.end_by(Decision::Resignation(self.server_color));
+    }
+
+    fn offer_draw(&mut self) {
+        if self.decision.is_some() || self.draw_offer == Some(self.server_color) {
+            return;
+        }
+        if self.draw_offer == Some(self.server_color.opposite()) {
+            self.end_by(Decision::DrawAgreed);
+            return;
+        }
+        self.draw_offer = Some(self.server_color);
+        // The protocol only lets clients offer draws, so tell the client in the chat.
+        self.send_chat("I offer a draw. Offer one back to accept it.");
+    }
+
+    fn draw_state(&self) -> DrawState {
+        match self.draw_offer {
+            None => DrawState::None,
+            Some(color) if color == self.server_color => DrawState::Requested,
+            Some(_) => DrawState::Offered,
+        }
+    }
+
+    fn decision(&self) -> Option<Decision> {
+        self.decision
+    }
+
+    fn saved_session(&self) -> Option<SavedSession> {
+        Some(SavedSession::Server {
+            port: self.port,
+            color: self.server_color.into(),
+            peer: self.peer.map(|peer| peer.ip().to_string()),
+            tls: self.tls.is_some(),
+            engine: self.game.is_engine(),
+        })
+    }
+}
+
+/// Listen on `port` on all IPv6 and IPv4 addresses. Some systems also accept IPv4
+/// connections on the IPv6 listener, and then the IPv4 listener isn't needed and can't be
+/// made.
+///
+/// # Panics
+/// This function panics if it can't listen at all, for example because the port is taken.
+pub(crate) fn listen(port: u16) -> Vec<TcpListener> {
+    let mut listeners = vec![];
+    match TcpListener::bind((Ipv6Addr::UNSPECIFIED, port)) {
+        Ok(listener) => listeners.push(listener),
+        Err(err) => log::info!(target: logging::NETWORK, "Not listening for IPv6 connections: {err}"),
+    }
+    // When any free port was asked for, IPv4 should use the one IPv6 got.
+    let port = listeners.first()
+        .and_then(|listener| listener.local_addr().ok())
+        .map_or(port, |addr| addr.port());
+    match TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)) {
+        Ok(listener) => listeners.push(listener),
+        Err(err) if !listeners.is_empty() => {
+            log::debug!(target: logging::NETWORK, "IPv4 connections are handled by the IPv6 listener ({err})");
+        }
+        Err(err) => panic!("Failed to listen on port {port}: {err}"),
+    }
+    for listener in &listeners {
+        listener.set_nonblocking(true).unwrap();
+    }
+    listeners
+}
+
+/// The last move of `history` as sent to clients.
+fn last_move(history: &[PlayedMove]) -> Option<ProtocolMove> {
+    let index = history.len().checked_sub(1)?;
+    let color = bridge::color_of_ply(index);
+    Some(ProtocolMove {
+        promotion: convert_promotion(history[index].promotion, color),
+        ..convert_move(history[index].mv, color)
+    })
+}
+
+/// The moves the side to move can make in `game`, as sent to clients. Castling is sent as
+/// the king's move.
+pub fn legal_moves(game: &mut (impl ChessGame + ?Sized)) -> Vec<ProtocolMove> {
+    let color = game.current_turn();
+    let mut moves = vec![];
+    for file in 0..8 {
+        for rank in 0..8 {
+            let square = (file, rank).try_into().unwrap();
+            if let Ok(possible_moves) = game.possible_moves(square) {
+                moves.extend(possible_moves.into_iter().map(|mv| convert_move(mv, color)));
+            }
+        }
+    }
+    moves
 }
 
-fn convert_piece(erikfran_piece: Option<Piece>) -> ProtocolPiece {
-    match erikfran_piece {
+fn convert_piece(piece: Option<Piece>) -> ProtocolPiece {
+    match piece {
         None => ProtocolPiece::None,
-        Some(erikfran_piece) => match erikfran_piece.color {
-            erikfran_chess::Color::White => match erikfran_piece.piece {
-                PieceTypes::Pawn(_) => ProtocolPiece::WhitePawn,
-                PieceTypes::Bishop => ProtocolPiece::WhiteBishop,
-                PieceTypes::Knight => ProtocolPiece::WhiteKnight,
-                PieceTypes::Rook => ProtocolPiece::WhiteRook,
-                PieceTypes::Queen => ProtocolPiece::WhiteQueen,
-                PieceTypes::King => ProtocolPiece::WhiteKing,
+        Some(piece) => match piece.color {
+            Color::White => match piece.kind {
+                PieceType::Pawn => ProtocolPiece::WhitePawn,
+                PieceType::Bishop => ProtocolPiece::WhiteBishop,
+                PieceType::Knight => ProtocolPiece::WhiteKnight,
+                PieceType::Rook => ProtocolPiece::WhiteRook,
+                PieceType::Queen => ProtocolPiece::WhiteQueen,
+                PieceType::King => ProtocolPiece::WhiteKing,
             }
-            erikfran_chess::Color::Black => match erikfran_piece.piece {
-                PieceTypes::Pawn(_) => ProtocolPiece::BlackPawn,
-                PieceTypes::Bishop => ProtocolPiece::BlackBishop,
-                PieceTypes::Knight => ProtocolPiece::BlackKnight,
-                PieceTypes::Rook => ProtocolPiece::BlackRook,
-                PieceTypes::Queen => ProtocolPiece::BlackQueen,
-                PieceTypes::King => ProtocolPiece::BlackKing,
+            Color::Black => match piece.kind {
+                PieceType::Pawn => ProtocolPiece::BlackPawn,
+                PieceType::Bishop => ProtocolPiece::BlackBishop,
+                PieceType::Knight => ProtocolPiece::BlackKnight,
+                PieceType::Rook => ProtocolPiece::BlackRook,
+                PieceType::Queen => ProtocolPiece::BlackQueen,
+                PieceType::King => ProtocolPiece::BlackKing,
             }
         }
     }
 }
 
-fn convert_board(erikfran_board: [[Option<Piece>; 8]; 8]) -> [[ProtocolPiece; 8]; 8] {
-    erikfran_board.map(
-        |row| row.map(
-            |piece| convert_piece(piece)
-        )
+pub fn convert_board(board: Board) -> [[ProtocolPiece; 8]; 8] {
+    board.map(
+        |row| row.map(convert_piece)
     )
 }
 
-pub fn convert_move(mv: Move) -> ProtocolMove {
+/// The square at `(x, y)` in a move from the network, or none if it is off the board.
+pub fn protocol_square(x: usize, y: usize) -> Option<Square> {
+    if x >= 8 || y >= 8 {
+        return None;
+    }
+    (x as i32, y as i32).try_into().ok()
+}
+
+/// The move `mv` from the network, where `piece` is the piece that moves. Castling is sent
+/// as the king's move, so a king moving two files is castling. Returns none if a square is
+/// off the board.
+pub fn parse_move(mv: ProtocolMove, piece: Option<Piece>) -> Option<Move> {
+    let from = protocol_square(mv.start_x, mv.start_y)?;
+    let to = protocol_square(mv.end_x, mv.end_y)?;
+    let is_king = piece.is_some_and(|piece| piece.kind == PieceType::King);
+    if is_king && from.rank() == to.rank() && (to.file() - from.file()).abs() == 2 {
+        let side = if to.file() > from.file() { CastlingSide::KingSide } else { CastlingSide::QueenSide };
+        return Some(Move::Castle { side });
+    }
+    Some(Move::Normal { from, to })
+}
+
+/// The move `mv` a player sent, to be made on `board`. A pawn reaching the last rank
+/// becomes a queen if the player didn't say what to promote it to.
+pub fn parse_played_move(mv: ProtocolMove, board: &Board) -> Option<PlayedMove> {
+    let from = protocol_square(mv.start_x, mv.start_y)?;
+    let bridge_move = parse_move(mv, board[from.rank() as usize][from.file() as usize])?;
+    let promotion = match promotion_piece(mv.promotion) {
+        Some(piece) => Some(piece),
+        None => bridge::is_promotion(board, bridge_move).then_some(PieceType::Queen),
+    };
+    Some(PlayedMove { mv: bridge_move, promotion })
+}
+
+/// The piece a pawn is promoted to in a move from the network, if any.
+pub fn promotion_piece(piece: ProtocolPiece) -> Option<PieceType> {
+    match piece {
+        ProtocolPiece::WhiteQueen | ProtocolPiece::BlackQueen => Some(PieceType::Queen),
+        ProtocolPiece::WhiteRook | ProtocolPiece::BlackRook => Some(PieceType::Rook),
+        ProtocolPiece::WhiteBishop | ProtocolPiece::BlackBishop => Some(PieceType::Bishop),
+        ProtocolPiece::WhiteKnight | ProtocolPiece::BlackKnight => Some(PieceType::Knight),
+        _ => None,
+    }
+}
+
+/// The piece a pawn of `color` is promoted to, as sent in moves.
+pub fn convert_promotion(piece: Option<PieceType>, color: Color) -> ProtocolPiece {
+    convert_piece(piece.map(|kind| Piece { kind, color }))
+}
+
+/// The protocol's version of `mv`, made by `color`. Castling is sent as the king's move.
+pub fn convert_move(mv: Move, color: Color) -> ProtocolMove {
     let (from, to) = match mv {
         Move::Normal { from, to } => (from, to),
-        Move::Castle { .. } => panic!("Castling is unsupported."),
+        Move::Castle { side } => side.king_squares(color),
     };
     ProtocolMove {
-        start_x: i32::from(from.file) as usize,
-        start_y: i32::from(from.rank) as usize,
-        end_x: i32::from(to.file) as usize,
-        end_y: i32::from(to.rank) as usize,
+        start_x: from.file() as usize,
+        start_y: from.rank() as usize,
+        end_x: to.file() as usize,
+        end_y: to.rank() as usize,
         promotion: ProtocolPiece::None,
     }
 }
\ No newline at end of file
@@ -0,0 +1,162 @@
+//! What a game is besides the rules: who is playing it, the moves so far, and what the
+//! players negotiate over the board, like takebacks, rematches and draws.
+//!
+//! [`ChessGame`] is all a rules engine has to implement. The games the views show implement
+//! [`GameSession`] on top of it, and the network games do most of their work here.
+
+use crate::bridge::{self, ChessGame, Color, PlayedMove};
+use crate::saved_game::SavedSession;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TakebackState {
+    /// No takeback is being negotiated.
+    None,
+    /// We asked the opponent to take back our last move and are waiting for an answer.
+    Requested,
+    /// The opponent asked us to let them take back their last move.
+    Offered,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RematchState {
+    /// No rematch is being negotiated.
+    None,
+    /// We offered the opponent a new game and are waiting for an answer.
+    Requested { swap_colors: bool },
+    /// The opponent offered us a new game.
+    Offered { swap_colors: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawState {
+    /// No draw has been offered since the last move.
+    None,
+    /// We offered the opponent a draw and are waiting for them to offer one back.
+    Requested,
+    /// The opponent offered us a draw.
+    Offered,
+}
+
+/// How a game ended other than on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The player of this color resigned.
+    Resignation(Color),
+    /// Both players agreed to a draw.
+    DrawAgreed,
+}
+
+impl Decision {
+    /// The color that won, or none for a draw.
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            Decision::Resignation(color) => Some(color.opposite()),
+            Decision::DrawAgreed => None,
+        }
+    }
+}
+
+/// A chat message in a network game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// Whether we sent the message, rather than the opponent.
+    pub ours: bool,
+    pub text: String,
+}
+
+pub trait GameSession: ChessGame {
+    /// Whether [`possible_moves`](ChessGame::possible_moves) only suggests moves, and
+    /// others may be tried too since someone else decides what is legal.
+    fn moves_are_hints(&self) -> bool {
+        false
+    }
+
+    /// The color this side of the game plays as, or none if it plays both colors.
+    fn player_color(&self) -> Option<Color> {
+        None
+    }
+
+    /// The moves that have been played so far, oldest first.
+    fn history(&self) -> &[PlayedMove];
+
+    /// Take back the last move. Local games do this immediately while network games
+    /// ask the opponent first.
+    fn request_takeback(&mut self) {}
+
+    fn takeback_state(&self) -> TakebackState {
+        TakebackState::None
+    }
+
+    /// Answer a takeback the opponent has asked for.
+    fn answer_takeback(&mut self, _accept: bool) {}
+
+    /// An error reported by the other side since the last call, like the server
+    /// rejecting our move.
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+
+    /// A description of how we disagree with the other side about the game, if we
+    /// have noticed that we do.
+    fn sync_problem(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether messages can be sent to the opponent. This is false for local games and
+    /// when the opponent doesn't support chat.
+    fn supports_chat(&self) -> bool {
+        false
+    }
+
+    fn send_chat(&mut self, _text: &str) {}
+
+    /// The chat messages of this game, oldest first.
+    fn chat_messages(&self) -> &[ChatMessage] {
+        &[]
+    }
+
+    /// Start a new game, with the colors swapped if `swap_colors` is set. Local games do
+    /// this immediately while network games ask the opponent first.
+    fn offer_rematch(&mut self, _swap_colors: bool) {}
+
+    fn rematch_state(&self) -> RematchState {
+        RematchState::None
+    }
+
+    /// Answer a rematch the opponent has offered.
+    fn answer_rematch(&mut self, _accept: bool) {}
+
+    /// How many new games have been started since this one was created, so that views
+    /// can notice when the board starts over.
+    fn rematches(&self) -> usize {
+        0
+    }
+
+    /// Give up the game. Only network games can be resigned.
+    fn resign(&mut self) {}
+
+    /// Offer the opponent a draw, or accept the draw they have offered.
+    fn offer_draw(&mut self) {}
+
+    fn draw_state(&self) -> DrawState {
+        DrawState::None
+    }
+
+    /// How the game ended if it was resigned or drawn by agreement.
+    fn decision(&self) -> Option<Decision> {
+        None
+    }
+
+    /// How to get back to this game if it is saved, or none if it can't be resumed.
+    fn saved_session(&self) -> Option<SavedSession> {
+        None
+    }
+}
+
+/// The number of half-moves that have to be taken back to undo the last move made by
+/// `requester`. This is zero if they haven't made a move yet.
+pub fn takeback_plies(history_len: usize, requester: Color) -> usize {
+    (0..history_len).rev()
+        .find(|index| bridge::color_of_ply(*index) == requester)
+        .map_or(0, |index| history_len - index)
+}
@@ -0,0 +1,213 @@
+//! User settings that are remembered between runs.
+//!
+//! Settings are stored as JSON in the user's config directory. Every setting can also be
+//! overridden for a single run from the command line, for example `--port 9000`. Overrides
+//! are not written to the settings file.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::bridge::Color;
+use crate::logging;
+
+const APP_NAME: &str = "alvinw-chess-gui";
+const SETTINGS_FILE: &str = "settings.json";
+
+pub type SharedSettings = Rc<RefCell<SettingsStore>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// Our own pieces at the bottom. White is at the bottom in local games.
+    Auto,
+    White,
+    Black,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 3] = [Orientation::Auto, Orientation::White, Orientation::Black];
+
+    /// The color at the bottom of the board, given the color we play as.
+    pub fn bottom_color(self, player_color: Option<Color>) -> Color {
+        match self {
+            Orientation::Auto => player_color.unwrap_or(Color::White),
+            Orientation::White => Color::White,
+            Orientation::Black => Color::Black,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Orientation::Auto => "auto",
+            Orientation::White => "white",
+            Orientation::Black => "black",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|orientation| orientation.name() == value)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Settings {
+    pub port: u16,
+    /// The address that was joined last time, offered as the default when joining.
+    pub last_ip: Option<String>,
+    /// The name of the theme, or none for the first theme in the theme file.
+    pub theme: Option<String>,
+    pub orientation: Orientation,
+    /// How long a piece takes to slide to its new square, in milliseconds.
+    pub animation_ms: u64,
+    /// Whether network messages are written to the traffic log file.
+    pub traffic_log: bool,
+    /// Whether connections to the other player are encrypted. Both players need the same
+    /// setting.
+    pub tls: bool,
+    /// The fingerprint the server's certificate must have when joining with TLS. This is
+    /// only given on the command line, since it belongs to one particular server.
+    #[serde(skip)]
+    pub fingerprint: Option<String>,
+    /// The name hosted games are shown with to players on the local network.
+    pub name: Option<String>,
+    /// The rules engine local games are played with and hosted games are refereed with.
+    pub backend: Backend,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            port: 8384,
+            last_ip: None,
+            theme: None,
+            orientation: Orientation::Auto,
+            animation_ms: 200,
+            traffic_log: false,
+            tls: false,
+            fingerprint: None,
+            name: None,
+            backend: Backend::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn animation_duration(&self) -> Duration {
+        Duration::from_millis(self.animation_ms)
+    }
+
+    /// Apply a single command line override such as `--port 9000`.
+    fn apply_override(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "--port" => self.port = parse_port(value)?,
+            "--ip" => self.last_ip = Some(value.to_string()),
+            "--theme" => self.theme = Some(value.to_string()),
+            "--orientation" => {
+                self.orientation = Orientation::parse(value)
+                    .ok_or_else(|| format!("Invalid orientation: {value} (expected auto, white or black)"))?;
+            }
+            "--animation-ms" => {
+                self.animation_ms = value.parse().map_err(|_| format!("Invalid animation time: {value}"))?;
+            }
+            "--traffic-log" => {
+                self.traffic_log = value.parse().map_err(|_| format!("Invalid value for --traffic-log: {value} (expected true or false)"))?;
+            }
+            "--tls" => {
+                self.tls = value.parse().map_err(|_| format!("Invalid value for --tls: {value} (expected true or false)"))?;
+            }
+            "--fingerprint" => self.fingerprint = Some(value.to_string()),
+            "--name" => self.name = Some(value.to_string()),
+            "--backend" => {
+                let names: Vec<&str> = Backend::ALL.iter().map(|backend| backend.name()).collect();
+                self.backend = Backend::parse(value)
+                    .ok_or_else(|| format!("Invalid backend: {value} (expected {})", names.join(" or ")))?;
+            }
+            _ => return Err(format!("Unknown option: {option}")),
+        }
+        Ok(())
+    }
+}
+
+/// The settings from the settings file together with the overrides for this run.
+pub struct SettingsStore {
+    /// What is in the settings file.
+    saved: Settings,
+    /// What is in the settings file with the command line overrides applied.
+    current: Settings,
+    path: Option<PathBuf>,
+}
+
+impl SettingsStore {
+    /// Load the settings file and apply the command line overrides in `args`, which should
+    /// not include the program name.
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let path = config_dir().map(|dir| dir.join(SETTINGS_FILE));
+        let saved = path.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| serde_json::from_str(&contents).unwrap_or_else(|err| {
+                log::warn!(target: logging::APP, "Ignoring broken settings file: {err}");
+                Settings::default()
+            }))
+            .unwrap_or_default();
+
+        let mut current = saved.clone();
+        let mut args = args.into_iter();
+        while let Some(option) = args.next() {
+            let value = args.next().ok_or_else(|| format!("Missing value for {option}"))?;
+            current.apply_override(&option, &value)?;
+        }
+
+        Ok(Self { saved, current, path })
+    }
+
+    pub fn into_shared(self) -> SharedSettings {
+        Rc::new(RefCell::new(self))
+    }
+
+    pub fn get(&self) -> &Settings {
+        &self.current
+    }
+
+    /// Change a setting and write it to the settings file.
+    pub fn change(&mut self, change: impl Fn(&mut Settings)) {
+        change(&mut self.saved);
+        change(&mut self.current);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, serde_json::to_string_pretty(&self.saved).unwrap()));
+        if let Err(err) = result {
+            log::warn!(target: logging::APP, "Failed to save settings to {}: {err}", path.display());
+        }
+    }
+}
+
+/// Parse a port to host games on. Port 0 isn't allowed, since it would pick a different free
+/// port every time.
+pub fn parse_port(value: &str) -> Result<u16, String> {
+    value.trim().parse().ok()
+        .filter(|port| *port != 0)
+        .ok_or_else(|| format!("Invalid port: {value} (expected 1 to 65535)"))
+}
+
+/// The directory where this application stores its files, if one could be determined.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join(APP_NAME))
+}
@@ -0,0 +1,71 @@
+//! Board colors and piece images that can be switched while the game is running.
+//!
+//! Themes are described in `themes.json` in the resources directory, which is a list of
+//! objects like this one:
+//!
+//! ```json
+//! { "name": "Classic", "light_squares": [220, 190, 30], "dark_squares": [120, 100, 80], "pieces": null }
+//! ```
+//!
+//! `pieces` is the name of a subdirectory of the resources directory containing a piece
+//! set, that is `white_king.png`, `black_pawn.png` and so on. When it is null the images
+//! directly in the resources directory are used.
+
+use std::io::Read;
+use ggez::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::logging;
+
+const THEMES_PATH: &str = "/themes.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub light_squares: [u8; 3],
+    pub dark_squares: [u8; 3],
+    #[serde(default)]
+    pub pieces: Option<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: String::from("Classic"),
+            light_squares: [220, 190, 30],
+            dark_squares: [120, 100, 80],
+            pieces: None,
+        }
+    }
+}
+
+impl Theme {
+    /// The path of the image of a piece in this theme's piece set, where `name` is
+    /// something like `white_king`.
+    pub fn piece_path(&self, name: &str) -> String {
+        match &self.pieces {
+            Some(directory) => format!("/{directory}/{name}.png"),
+            None => format!("/{name}.png"),
+        }
+    }
+}
+
+/// Read the available themes from the resources directory. The default theme is used if
+/// the file is missing or broken so that there is always at least one theme.
+pub fn load_themes(ctx: &Context) -> Vec<Theme> {
+    let themes = ctx.fs.open(THEMES_PATH)
+        .map_err(|err| err.to_string())
+        .and_then(|mut file| {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).map_err(|err| err.to_string())?;
+            serde_json::from_str::<Vec<Theme>>(&contents).map_err(|err| err.to_string())
+        });
+    match themes {
+        Ok(themes) if !themes.is_empty() => themes,
+        Ok(_) => vec![Theme::default()],
+        Err(err) => {
+            log::warn!(target: logging::RENDERING, "Failed to load {THEMES_PATH}: {err}");
+            vec![Theme::default()]
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Optional encryption of the connection between server and client.
+//!
+//! The server uses a self-signed certificate that is generated the first time it is
+//! needed and kept in the config directory. Since there is no certificate authority to
+//! vouch for it, the client accepts any certificate and both sides show its fingerprint
+//! instead, so that the players can compare them. A client that already knows the
+//! fingerprint can be told to only accept that one.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, ClientConnection, PrivateKey, ServerConfig, ServerConnection, ServerName, StreamOwned};
+use sha2::{Digest, Sha256};
+
+use crate::logging;
+use crate::settings::config_dir;
+
+const CERTIFICATE_FILE: &str = "tls_certificate.der";
+const KEY_FILE: &str = "tls_key.der";
+/// The name in the certificate. The client doesn't check it since it checks fingerprints.
+const SERVER_NAME: &str = "alvinw-chess-gui";
+/// How long to wait for the other side during the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The server's certificate and private key.
+pub struct Identity {
+    certificate: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl Identity {
+    /// Load the certificate from the config directory, or generate one if there is none.
+    pub fn load_or_create() -> Result<Self, String> {
+        let paths = config_dir().map(|dir| (dir.join(CERTIFICATE_FILE), dir.join(KEY_FILE)));
+        if let Some((certificate_path, key_path)) = &paths {
+            if let (Ok(certificate), Ok(key)) = (fs::read(certificate_path), fs::read(key_path)) {
+                return Ok(Self { certificate, key });
+            }
+        }
+
+        log::info!(target: logging::NETWORK, "Generating a TLS certificate");
+        let identity = Self::generate()?;
+        if let Some((certificate_path, key_path)) = &paths {
+            if let Err(err) = identity.save(certificate_path, key_path) {
+                log::warn!(target: logging::NETWORK, "Failed to save the TLS certificate, a new one will be made next time: {err}");
+            }
+        }
+        Ok(identity)
+    }
+
+    /// Generate a new certificate without saving it.
+    pub fn generate() -> Result<Self, String> {
+        let generated = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+            .map_err(|err| format!("Failed to generate a certificate: {err}"))?;
+        Ok(Self {
+            certificate: generated.serialize_der().map_err(|err| format!("Failed to generate a certificate: {err}"))?,
+            key: generated.serialize_private_key_der(),
+        })
+    }
+
+    fn save(&self, certificate_path: &Path, key_path: &Path) -> io::Result<()> {
+        if let Some(dir) = certificate_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(certificate_path, &self.certificate)?;
+        write_private(key_path, &self.key)
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.certificate)
+    }
+
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, String> {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(vec![Certificate(self.certificate.clone())], PrivateKey(self.key.clone()))
+            .map_err(|err| format!("Invalid TLS certificate: {err}"))?;
+        Ok(Arc::new(config))
+    }
+}
+
+/// Write `contents` to a file at `path` that only the user can read.
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // The mode is only used for new files.
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents)
+}
+
+/// Write `contents` to a file at `path`. The file is private as long as the config
+/// directory is, which it is by default on Windows.
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    fs::write(path, contents)
+}
+
+/// The SHA-256 hash of a certificate as colon separated hex, like `AB:12:...`.
+pub fn fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Accepts the server's certificate if it has the expected fingerprint, or any certificate
+/// if no fingerprint is expected.
+struct FingerprintVerifier {
+    expected: Option<String>,
+}
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let Some(expected) = &self.expected else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        let actual = fingerprint(&end_entity.0);
+        if normalize(&actual) == normalize(expected) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!("The server's fingerprint is {actual}, expected {expected}")))
+        }
+    }
+}
+
+/// Fingerprints may be written with or without colons and in any case.
+fn normalize(fingerprint: &str) -> String {
+    fingerprint.chars().filter(char::is_ascii_hexdigit).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+pub fn client_config(expected_fingerprint: Option<String>) -> Arc<ClientConfig> {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(FingerprintVerifier { expected: expected_fingerprint }))
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+/// A connection that may or may not be encrypted.
+pub enum Transport {
+    Plain(TcpStream),
+    Server(Box<StreamOwned<ServerConnection, TcpStream>>),
+    Client(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Transport {
+    /// Do the server side of the TLS handshake on `stream`.
+    pub fn accept(mut stream: TcpStream, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let mut connection = ServerConnection::new(config).map_err(io::Error::other)?;
+        handshake(&mut stream, &mut connection)?;
+        Ok(Transport::Server(Box::new(StreamOwned::new(connection, stream))))
+    }
+
+    /// Do the client side of the TLS handshake on `stream`.
+    pub fn connect(mut stream: TcpStream, config: Arc<ClientConfig>) -> io::Result<Self> {
+        let server_name = ServerName::try_from(SERVER_NAME).map_err(io::Error::other)?;
+        let mut connection = ClientConnection::new(config, server_name).map_err(io::Error::other)?;
+        handshake(&mut stream, &mut connection)?;
+        Ok(Transport::Client(Box::new(StreamOwned::new(connection, stream))))
+    }
+
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            Transport::Plain(stream) => stream,
+            Transport::Server(stream) => &stream.sock,
+            Transport::Client(stream) => &stream.sock,
+        }
+    }
+
+    /// The fingerprint of the server's certificate, as seen by an encrypted client.
+    pub fn server_fingerprint(&self) -> Option<String> {
+        let Transport::Client(stream) = self else {
+            return None;
+        };
+        stream.conn.peer_certificates()?.first().map(|certificate| fingerprint(&certificate.0))
+    }
+}
+
+/// Run the TLS handshake to completion with a blocking socket, since the rest of the code
+/// expects whole messages to go through once the connection is up. The socket is left
+/// blocking, the caller decides what it wants.
+fn handshake<Data>(stream: &mut TcpStream, connection: &mut rustls::ConnectionCommon<Data>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    while connection.is_handshaking() {
+        connection.complete_io(stream)?;
+    }
+    stream.set_read_timeout(None)
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Transport::Plain(stream) => stream.read(buf),
+            Transport::Server(stream) => stream.read(buf),
+            Transport::Client(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Plain(stream) => stream.write(buf),
+            Transport::Server(stream) => stream.write(buf),
+            Transport::Client(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Transport::Plain(stream) => stream.flush(),
+            Transport::Server(stream) => stream.flush(),
+            Transport::Client(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,930 @@
+//! Hosting a tournament between many clients, for example to let bots play each other.
+//!
+//! Participants connect the same way as to a [`ServerGame`](crate::server::ServerGame), but
+//! the host doesn't play itself. It pairs the participants round by round and referees every
+//! game with its own rules engine, relaying each move to the opponent as if the host had
+//! made it. Games are started with the rematch extension's `RematchStarted`, so only
+//! clients that support it can take part.
+//!
+//! The bookkeeping is done by [`Tournament`], which knows nothing about the network, while
+//! [`TournamentHost`] runs the games.
+
+use std::net::{SocketAddr, TcpListener};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use chess_network_protocol::{ClientToServer, ClientToServerHandshake, Color as ProtocolColor, Features, Joever, Move as ProtocolMove, ServerToClient, ServerToClientHandshake};
+use serde::Serialize;
+
+use crate::backend::Backend;
+use crate::bridge::{ChessGame, Color, Move, PlayedMove};
+use crate::extension::{self, ExtensionMessage, Incoming};
+use crate::json_tcp_stream::JsonTcpStream;
+use crate::logging;
+use crate::pgn;
+use crate::server::{convert_board, convert_move, convert_promotion, legal_moves, listen, parse_played_move};
+use crate::tls::Transport;
+
+/// The most half-moves a game may last. Longer games are drawn, so that two bots moving
+/// their pieces back and forth can't hold up the tournament.
+pub const MAX_PLIES: usize = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Everyone plays everyone else once.
+    RoundRobin,
+    /// A fixed number of rounds where players with the same score meet. Nobody meets the
+    /// same opponent twice unless the pairing can't be made otherwise.
+    Swiss { rounds: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl Outcome {
+    pub fn win_for(color: Color) -> Self {
+        match color {
+            Color::White => Outcome::WhiteWins,
+            Color::Black => Outcome::BlackWins,
+        }
+    }
+
+    /// The result as written in PGN, like `1-0`.
+    pub fn pgn(self) -> &'static str {
+        match self {
+            Outcome::WhiteWins => "1-0",
+            Outcome::BlackWins => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+
+    fn joever(self) -> Joever {
+        match self {
+            Outcome::WhiteWins => Joever::White,
+            Outcome::BlackWins => Joever::Black,
+            Outcome::Draw => Joever::Draw,
+        }
+    }
+}
+
+/// How a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Resignation,
+    /// Both players offered a draw.
+    Agreement,
+    /// The game reached [`MAX_PLIES`].
+    MoveLimit,
+    /// A player left the tournament.
+    Forfeit,
+}
+
+impl Termination {
+    pub fn description(self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Resignation => "resignation",
+            Termination::Agreement => "agreed draw",
+            Termination::MoveLimit => "move limit",
+            Termination::Forfeit => "forfeit",
+        }
+    }
+
+    /// The value of the PGN `Termination` tag.
+    fn pgn(self) -> &'static str {
+        match self {
+            Termination::MoveLimit => "adjudication",
+            Termination::Forfeit => "abandoned",
+            _ => "normal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameResult {
+    pub outcome: Outcome,
+    pub termination: Termination,
+}
+
+/// A game between two players, given as their indices in [`Tournament::players`].
+#[derive(Clone)]
+pub struct Pairing {
+    pub white: usize,
+    pub black: usize,
+    /// Set when the game is over.
+    pub result: Option<GameResult>,
+    pub moves: Vec<PlayedMove>,
+}
+
+impl Pairing {
+    fn new(white: usize, black: usize) -> Self {
+        Self { white, black, result: None, moves: vec![] }
+    }
+
+    pub fn has_player(&self, player: usize) -> bool {
+        self.white == player || self.black == player
+    }
+
+    fn opponent(&self, player: usize) -> usize {
+        if self.white == player { self.black } else { self.white }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Round {
+    pub pairings: Vec<Pairing>,
+    /// The player who sits out this round, which counts as a win.
+    pub bye: Option<usize>,
+}
+
+impl Round {
+    pub fn is_finished(&self) -> bool {
+        self.pairings.iter().all(|pairing| pairing.result.is_some())
+    }
+}
+
+/// How a player has done so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    pub player: usize,
+    /// One for every win and bye and a half for every draw.
+    pub points: f32,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub byes: usize,
+}
+
+impl Standing {
+    fn new(player: usize) -> Self {
+        Self { player, points: 0.0, wins: 0, draws: 0, losses: 0, byes: 0 }
+    }
+
+    pub fn played(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+}
+
+/// The pairings and results of a tournament.
+pub struct Tournament {
+    players: Vec<String>,
+    format: Format,
+    rounds: Vec<Round>,
+}
+
+impl Tournament {
+    pub fn new(players: Vec<String>, format: Format) -> Self {
+        Self { players, format, rounds: vec![] }
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// The rounds that have been paired so far, the last of which may still be played.
+    pub fn rounds(&self) -> &[Round] {
+        &self.rounds
+    }
+
+    /// How many rounds the whole tournament has.
+    pub fn round_count(&self) -> usize {
+        let players = self.players.len();
+        if players < 2 {
+            return 0;
+        }
+        match self.format {
+            // With an odd number of players everyone also needs a round to sit out.
+            Format::RoundRobin => players - 1 + players % 2,
+            Format::Swiss { rounds } => rounds,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rounds.len() >= self.round_count() && self.rounds.last().is_none_or(Round::is_finished)
+    }
+
+    /// Pair the next round and return its index, or none if the current round is still
+    /// being played or all rounds have been played.
+    pub fn start_round(&mut self) -> Option<usize> {
+        if self.is_finished() || !self.rounds.last().is_none_or(Round::is_finished) {
+            return None;
+        }
+        let round = match self.format {
+            Format::RoundRobin => round_robin(self.players.len(), self.rounds.len()),
+            Format::Swiss { .. } => self.swiss_round(),
+        };
+        self.rounds.push(round);
+        Some(self.rounds.len() - 1)
+    }
+
+    /// Record how the game `pairing` in round `round` ended.
+    pub fn record(&mut self, round: usize, pairing: usize, result: GameResult, moves: Vec<PlayedMove>) {
+        let pairing = &mut self.rounds[round].pairings[pairing];
+        pairing.result = Some(result);
+        pairing.moves = moves;
+    }
+
+    /// The standings of all players, best first. Players with as many points are ordered by
+    /// their number of wins.
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = (0..self.players.len()).map(Standing::new).collect();
+        for round in &self.rounds {
+            if let Some(bye) = round.bye {
+                standings[bye].byes += 1;
+                standings[bye].points += 1.0;
+            }
+            for pairing in &round.pairings {
+                let Some(result) = pairing.result else {
+                    continue;
+                };
+                let (winner, loser) = match result.outcome {
+                    Outcome::WhiteWins => (pairing.white, pairing.black),
+                    Outcome::BlackWins => (pairing.black, pairing.white),
+                    Outcome::Draw => {
+                        for player in [pairing.white, pairing.black] {
+                            standings[player].draws += 1;
+                            standings[player].points += 0.5;
+                        }
+                        continue;
+                    }
+                };
+                standings[winner].wins += 1;
+                standings[winner].points += 1.0;
+                standings[loser].losses += 1;
+            }
+        }
+        standings.sort_by(|a, b| {
+            b.points.total_cmp(&a.points)
+                .then(b.wins.cmp(&a.wins))
+                .then(a.player.cmp(&b.player))
+        });
+        standings
+    }
+
+    /// The standings as CSV with a header row.
+    pub fn standings_csv(&self) -> String {
+        let mut csv = String::from("rank,name,points,played,wins,draws,losses,byes\n");
+        for (index, standing) in self.standings().iter().enumerate() {
+            csv += &format!(
+                "{},{},{},{},{},{},{},{}\n",
+                index + 1,
+                csv_field(&self.players[standing.player]),
+                standing.points,
+                standing.played(),
+                standing.wins,
+                standing.draws,
+                standing.losses,
+                standing.byes,
+            );
+        }
+        csv
+    }
+
+    /// All finished games as PGN, with `event` as the name of the tournament. The moves
+    /// are written with the rules of `backend`.
+    pub fn pgn(&self, event: &str, backend: Backend) -> String {
+        let mut games = vec![];
+        for (round_index, round) in self.rounds.iter().enumerate() {
+            for pairing in &round.pairings {
+                let Some(result) = pairing.result else {
+                    continue;
+                };
+                let tags = [
+                    ("Event", event.to_string()),
+                    ("Site", String::from("?")),
+                    ("Date", String::from("????.??.??")),
+                    ("Round", (round_index + 1).to_string()),
+                    ("White", self.players[pairing.white].clone()),
+                    ("Black", self.players[pairing.black].clone()),
+                    ("Result", result.outcome.pgn().to_string()),
+                    ("Termination", result.termination.pgn().to_string()),
+                ];
+                games.push(pgn::write(&tags, &pairing.moves, backend));
+            }
+        }
+        games.join("\n")
+    }
+
+    /// Pair the next Swiss round. Players are ranked by their standing, and from the top
+    /// each player is paired with the highest ranked player left that they haven't met, as
+    /// long as the players below can still be paired. If there is no way to avoid players
+    /// meeting again, neighbours in the ranking are paired.
+    fn swiss_round(&self) -> Round {
+        let standings = self.standings();
+        let mut ranked: Vec<usize> = standings.iter().map(|standing| standing.player).collect();
+        let mut round = Round::default();
+        if ranked.len() % 2 == 1 {
+            // The lowest ranked player who hasn't sat out yet sits out.
+            let index = (0..ranked.len()).rev()
+                .find(|index| standings[*index].byes == 0)
+                .unwrap_or(ranked.len() - 1);
+            round.bye = Some(ranked.remove(index));
+        }
+        let pairs = self.pair_unmet(&ranked)
+            .unwrap_or_else(|| ranked.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+        for (player, opponent) in pairs {
+            // The player who has had white less often gets it.
+            let pairing = if self.white_balance(player) <= self.white_balance(opponent) {
+                Pairing::new(player, opponent)
+            } else {
+                Pairing::new(opponent, player)
+            };
+            round.pairings.push(pairing);
+        }
+        round
+    }
+
+    /// Pair all of `ranked` so that nobody meets someone they have met before, or none if
+    /// that can't be done.
+    fn pair_unmet(&self, ranked: &[usize]) -> Option<Vec<(usize, usize)>> {
+        let Some((&player, rest)) = ranked.split_first() else {
+            return Some(vec![]);
+        };
+        for (index, &opponent) in rest.iter().enumerate() {
+            if self.have_met(player, opponent) {
+                continue;
+            }
+            let mut remaining = rest.to_vec();
+            remaining.remove(index);
+            if let Some(mut pairs) = self.pair_unmet(&remaining) {
+                pairs.insert(0, (player, opponent));
+                return Some(pairs);
+            }
+        }
+        None
+    }
+
+    fn have_met(&self, a: usize, b: usize) -> bool {
+        self.rounds.iter()
+            .flat_map(|round| &round.pairings)
+            .any(|pairing| pairing.has_player(a) && pairing.opponent(a) == b)
+    }
+
+    /// How many more games `player` has played as white than as black.
+    fn white_balance(&self, player: usize) -> isize {
+        self.rounds.iter()
+            .flat_map(|round| &round.pairings)
+            .map(|pairing| match player {
+                _ if pairing.white == player => 1,
+                _ if pairing.black == player => -1,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// Round `round` of a round robin between `players` players, paired with the circle method:
+/// the players sit at a table where the first one stays put and the others move one seat
+/// each round, and players sitting opposite each other meet.
+fn round_robin(players: usize, round: usize) -> Round {
+    // With an odd number of players, the one who would meet the missing player sits out.
+    let seats = players + players % 2;
+    let moving = seats - 1;
+    let player_at = |seat: usize| if seat == 0 { 0 } else { (seat - 1 + round) % moving + 1 };
+
+    let mut result = Round::default();
+    for board in 0..seats / 2 {
+        let (a, b) = (player_at(board), player_at(seats - 1 - board));
+        if b == players {
+            result.bye = Some(a);
+            continue;
+        }
+        if a == players {
+            result.bye = Some(b);
+            continue;
+        }
+        // Alternate the colors so that nobody gets the same color all the time.
+        let pairing = if (board + round).is_multiple_of(2) { Pairing::new(a, b) } else { Pairing::new(b, a) };
+        result.pairings.push(pairing);
+    }
+    result
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Someone who has connected to the tournament.
+pub struct Participant {
+    /// Cleared when the participant disconnects.
+    stream: Option<JsonTcpStream>,
+    pub address: SocketAddr,
+    pub name: String,
+    /// Whether the participant has sent its handshake.
+    greeted: bool,
+    /// The extensions the participant has said it understands.
+    extensions: Vec<String>,
+}
+
+impl Participant {
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Whether the participant can be given games.
+    pub fn can_play(&self) -> bool {
+        self.is_connected() && self.greeted && self.supports(extension::REMATCH)
+    }
+
+    /// What the participant is doing before the tournament starts, for showing in a list.
+    pub fn status(&self) -> &'static str {
+        if !self.is_connected() {
+            "disconnected"
+        } else if !self.greeted {
+            "connecting"
+        } else if !self.supports(extension::REMATCH) {
+            "can't play, doesn't support the rematch extension"
+        } else {
+            "ready"
+        }
+    }
+
+    fn supports(&self, extension: &str) -> bool {
+        self.extensions.iter().any(|name| name == extension)
+    }
+
+    /// Send `message`, and treat the participant as disconnected if that fails.
+    fn send<T: Serialize>(&mut self, message: &T) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        if let Err(err) = stream.write(message) {
+            log::warn!(target: logging::NETWORK, "Lost the connection to {}: {err}", self.name);
+            self.stream = None;
+        }
+    }
+
+    /// Tell a participant that supports chat about what is going on.
+    fn notify(&mut self, text: String) {
+        if self.supports(extension::CHAT) {
+            self.send(&ExtensionMessage::Chat { text });
+        }
+    }
+}
+
+/// A game of the current round that is being played.
+struct LiveGame {
+    /// The index of the game in the current round.
+    pairing: usize,
+    white: usize,
+    black: usize,
+    referee: Box<dyn ChessGame>,
+    history: Vec<PlayedMove>,
+    /// The color that has offered a draw since the last move.
+    draw_offer: Option<Color>,
+}
+
+impl LiveGame {
+    fn color_of(&self, player: usize) -> Color {
+        if self.white == player { Color::White } else { Color::Black }
+    }
+
+    fn error(&mut self, message: &str) -> ServerToClient {
+        ServerToClient::Error {
+            board: convert_board(self.referee.get_pieces()),
+            moves: legal_moves(&mut self.referee),
+            joever: Joever::Ongoing,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Accepts participants, runs the games of each round and keeps the [`Tournament`] up to
+/// date. Participants can join until the tournament is started.
+pub struct TournamentHost {
+    /// Usually one listener for IPv6 and one for IPv4.
+    listeners: Vec<TcpListener>,
+    port: u16,
+    /// Set when participants must connect with TLS.
+    tls: Option<Arc<rustls::ServerConfig>>,
+    /// Connections whose TLS handshake has finished on a worker thread, so that a slow
+    /// participant doesn't block the host while connecting.
+    handshaken: Receiver<(Transport, SocketAddr)>,
+    handshaken_sender: Sender<(Transport, SocketAddr)>,
+    /// The rules engine the games are refereed with.
+    backend: Backend,
+    format: Format,
+    participants: Vec<Participant>,
+    /// Set once the tournament has started.
+    tournament: Option<Tournament>,
+    games: Vec<LiveGame>,
+}
+
+impl TournamentHost {
+    pub fn new(port: u16, format: Format) -> Self {
+        let (handshaken_sender, handshaken) = mpsc::channel();
+        Self {
+            listeners: listen(port),
+            port,
+            tls: None,
+            handshaken,
+            handshaken_sender,
+            backend: Backend::default(),
+            format,
+            participants: vec![],
+            tournament: None,
+            games: vec![],
+        }
+    }
+
+    /// Require participants to connect with TLS.
+    pub fn with_tls(mut self, config: Arc<rustls::ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Referee the games with `backend`.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// The port participants connect to.
+    pub fn local_port(&self) -> u16 {
+        self.listeners.first()
+            .and_then(|listener| listener.local_addr().ok())
+            .map_or(self.port, |addr| addr.port())
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn participants(&self) -> &[Participant] {
+        &self.participants
+    }
+
+    /// The tournament, once it has started.
+    pub fn tournament(&self) -> Option<&Tournament> {
+        self.tournament.as_ref()
+    }
+
+    /// How many half-moves have been played in the game `pairing` of the current round, or
+    /// none if it isn't being played.
+    pub fn live_plies(&self, pairing: usize) -> Option<usize> {
+        self.games.iter()
+            .find(|game| game.pairing == pairing)
+            .map(|game| game.history.len())
+    }
+
+    /// Start the tournament with the participants that can play. The others are
+    /// disconnected, and nobody else can join.
+    pub fn start(&mut self) -> Result<(), String> {
+        if self.tournament.is_some() {
+            return Err(String::from("The tournament has already started."));
+        }
+        if self.participants.iter().filter(|participant| participant.can_play()).count() < 2 {
+            return Err(String::from("At least two participants that can play are needed."));
+        }
+        self.participants.retain(|participant| {
+            if !participant.can_play() {
+                log::warn!(target: logging::NETWORK, "Leaving out {}: {}", participant.name, participant.status());
+            }
+            participant.can_play()
+        });
+        let names = self.participants.iter().map(|participant| participant.name.clone()).collect();
+        self.tournament = Some(Tournament::new(names, self.format));
+        // Nobody else may join.
+        self.listeners.clear();
+        log::info!(target: logging::NETWORK, "Starting the tournament with {} participants", self.participants.len());
+        self.start_rounds();
+        Ok(())
+    }
+
+    /// Accept new participants, handle what they have sent and start the next round once
+    /// the current one is over.
+    pub fn update(&mut self) {
+        self.try_accept();
+        for index in 0..self.participants.len() {
+            self.read_from(index);
+        }
+        self.forfeit_disconnected();
+        self.start_rounds();
+    }
+
+    fn try_accept(&mut self) {
+        if let Some((stream, addr)) = self.listeners.iter().find_map(|listener| listener.accept().ok()) {
+            let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+            match &self.tls {
+                None => self.add_participant(Transport::Plain(stream), addr),
+                Some(config) => {
+                    let config = config.clone();
+                    let handshaken = self.handshaken_sender.clone();
+                    thread::spawn(move || match Transport::accept(stream, config) {
+                        Ok(transport) => {
+                            let _ = handshaken.send((transport, addr));
+                        }
+                        Err(err) => log::warn!(target: logging::NETWORK, "TLS handshake with {addr} failed: {err}"),
+                    });
+                }
+            }
+        }
+        while let Ok((transport, addr)) = self.handshaken.try_recv() {
+            self.add_participant(transport, addr);
+        }
+    }
+
+    fn add_participant(&mut self, transport: Transport, addr: SocketAddr) {
+        let name = format!("Player {} ({})", self.participants.len() + 1, addr.ip());
+        log::info!(target: logging::NETWORK, "{name} connected from {addr}");
+        self.participants.push(Participant {
+            stream: Some(JsonTcpStream::with_transport(transport)),
+            address: addr,
+            name,
+            greeted: false,
+            extensions: vec![],
+        });
+    }
+
+    fn read_from(&mut self, index: usize) {
+        loop {
+            let participant = &mut self.participants[index];
+            let Some(stream) = &mut participant.stream else {
+                return;
+            };
+            if !participant.greeted {
+                if stream.read::<ClientToServerHandshake>().is_none() {
+                    break;
+                }
+                self.greet(index);
+                continue;
+            }
+            match stream.read::<Incoming<ClientToServer>>() {
+                Some(Incoming::Protocol(packet)) => self.handle_packet(index, packet),
+                Some(Incoming::Extension(message)) => self.handle_extension(index, message),
+                None => break,
+            }
+        }
+        let participant = &mut self.participants[index];
+        if participant.stream.as_ref().is_some_and(JsonTcpStream::is_closed) {
+            log::info!(target: logging::NETWORK, "{} disconnected", participant.name);
+            participant.stream = None;
+        }
+    }
+
+    /// Answer the handshake of a participant. The colors the participant asked for don't
+    /// matter, since every game is started with `RematchStarted`. Until then there are no
+    /// moves to make.
+    fn greet(&mut self, index: usize) {
+        let participant = &mut self.participants[index];
+        participant.greeted = true;
+        let handshake = ServerToClientHandshake {
+            board: convert_board(self.backend.new_game().get_pieces()),
+            moves: vec![],
+            joever: Joever::Ongoing,
+            features: vec![
+                Features::PossibleMoveGeneration,
+                Features::Other(extension::REMATCH.to_string()),
+                Features::Other(extension::CHAT.to_string()),
+            ],
+        };
+        participant.send(&handshake);
+    }
+
+    /// The game in the current round that `player` is playing.
+    fn game_of(&self, player: usize) -> Option<usize> {
+        self.games.iter().position(|game| game.white == player || game.black == player)
+    }
+
+    fn handle_packet(&mut self, player: usize, packet: ClientToServer) {
+        let Some(game_index) = self.game_of(player) else {
+            if let ClientToServer::Move(_) = packet {
+                let error = ServerToClient::Error {
+                    board: convert_board(self.backend.new_game().get_pieces()),
+                    moves: vec![],
+                    joever: Joever::Ongoing,
+                    message: String::from("Your next game hasn't started yet."),
+                };
+                self.participants[player].send(&error);
+            }
+            return;
+        };
+        let game = &mut self.games[game_index];
+        let color = game.color_of(player);
+
+        match packet {
+            ClientToServer::Move(mv) => {
+                if game.referee.current_turn() != color {
+                    let error = game.error("It is not your turn.");
+                    self.participants[player].send(&error);
+                    return;
+                }
+                let Some(played) = parse_played_move(mv, &game.referee.get_pieces()) else {
+                    let error = game.error("Invalid square");
+                    self.participants[player].send(&error);
+                    return;
+                };
+                if let Err(err) = game.referee.perform_move(played.mv) {
+                    let error = game.error(&err.to_string());
+                    self.participants[player].send(&error);
+                    return;
+                }
+                if let (Move::Normal { to, .. }, Some(piece)) = (played.mv, played.promotion) {
+                    game.referee.promote(to, piece);
+                }
+                game.history.push(played);
+                game.draw_offer = None;
+
+                let moves = legal_moves(&mut game.referee);
+                let result = if moves.is_empty() && game.referee.is_check() {
+                    Some(GameResult { outcome: Outcome::win_for(color), termination: Termination::Checkmate })
+                } else if moves.is_empty() {
+                    Some(GameResult { outcome: Outcome::Draw, termination: Termination::Stalemate })
+                } else if game.history.len() >= MAX_PLIES {
+                    Some(GameResult { outcome: Outcome::Draw, termination: Termination::MoveLimit })
+                } else {
+                    None
+                };
+                let state = ServerToClient::State {
+                    board: convert_board(game.referee.get_pieces()),
+                    moves,
+                    joever: result.map_or(Joever::Ongoing, |result| result.outcome.joever()),
+                    // As the host understood it, with the promotion filled in.
+                    move_made: ProtocolMove {
+                        promotion: convert_promotion(played.promotion, color),
+                        ..convert_move(played.mv, color)
+                    },
+                };
+                let (white, black) = (game.white, game.black);
+                self.participants[white].send(&state);
+                self.participants[black].send(&state);
+                if let Some(result) = result {
+                    self.finish(game_index, result);
+                }
+            }
+            ClientToServer::Resign => {
+                let outcome = Outcome::win_for(color.opposite());
+                let resigned = ServerToClient::Resigned {
+                    board: convert_board(game.referee.get_pieces()),
+                    joever: outcome.joever(),
+                };
+                let (white, black) = (game.white, game.black);
+                self.participants[white].send(&resigned);
+                self.participants[black].send(&resigned);
+                self.finish(game_index, GameResult { outcome, termination: Termination::Resignation });
+            }
+            ClientToServer::Draw => {
+                if game.draw_offer == Some(color) {
+                    return;
+                }
+                if game.draw_offer != Some(color.opposite()) {
+                    game.draw_offer = Some(color);
+                    // The protocol has no way to pass the offer on, so tell the opponent in
+                    // the chat.
+                    let opponent = if color == Color::White { game.black } else { game.white };
+                    let text = format!("{} offers a draw. Offer one back to accept it.", self.participants[player].name);
+                    self.participants[opponent].notify(text);
+                    return;
+                }
+                let draw = ServerToClient::Draw {
+                    board: convert_board(game.referee.get_pieces()),
+                    moves: legal_moves(&mut game.referee),
+                };
+                let (white, black) = (game.white, game.black);
+                self.participants[white].send(&draw);
+                self.participants[black].send(&draw);
+                self.finish(game_index, GameResult { outcome: Outcome::Draw, termination: Termination::Agreement });
+            }
+        }
+    }
+
+    fn handle_extension(&mut self, player: usize, message: ExtensionMessage) {
+        match message {
+            ExtensionMessage::Hello { extensions } => {
+                self.participants[player].extensions = extensions;
+            }
+            ExtensionMessage::Chat { text } => {
+                // Passed on to the opponent, if there is one right now.
+                let Some(game) = self.game_of(player).map(|index| &self.games[index]) else {
+                    return;
+                };
+                let opponent = if game.white == player { game.black } else { game.white };
+                if let Some(text) = extension::clean_chat(&text) {
+                    if self.participants[opponent].supports(extension::CHAT) {
+                        self.participants[opponent].send(&ExtensionMessage::Chat { text });
+                    }
+                }
+            }
+            _ => {
+                // The host decides when games start, and the other extensions aren't
+                // advertised.
+            }
+        }
+    }
+
+    /// End the live game at `game_index` with `result` and record it in the tournament.
+    fn finish(&mut self, game_index: usize, result: GameResult) {
+        let game = self.games.remove(game_index);
+        let tournament = self.tournament.as_mut().expect("Games are only played in a tournament");
+        let round = tournament.rounds.len() - 1;
+        log::info!(
+            target: logging::NETWORK,
+            "{} - {}: {} by {}",
+            tournament.players[game.white],
+            tournament.players[game.black],
+            result.outcome.pgn(),
+            result.termination.description(),
+        );
+        tournament.record(round, game.pairing, result, game.history);
+    }
+
+    /// Let the opponents of participants who have left win their games.
+    fn forfeit_disconnected(&mut self) {
+        while let Some(game_index) = self.games.iter().position(|game| {
+            !self.participants[game.white].is_connected() || !self.participants[game.black].is_connected()
+        }) {
+            let game = &self.games[game_index];
+            let result = forfeit_result(
+                self.participants[game.white].is_connected(),
+                self.participants[game.black].is_connected(),
+            );
+            let resigned = ServerToClient::Resigned {
+                board: convert_board(game.referee.get_pieces()),
+                joever: result.outcome.joever(),
+            };
+            let (white, black) = (game.white, game.black);
+            self.participants[white].send(&resigned);
+            self.participants[black].send(&resigned);
+            self.finish(game_index, result);
+        }
+    }
+
+    /// Start the next round once all games of the current one are over. Rounds where every
+    /// game is forfeited are skipped right away.
+    fn start_rounds(&mut self) {
+        if !self.games.is_empty() {
+            return;
+        }
+        while self.games.is_empty() {
+            let Some(tournament) = &mut self.tournament else {
+                return;
+            };
+            let Some(round_index) = tournament.start_round() else {
+                return;
+            };
+            let round = tournament.rounds[round_index].clone();
+            log::info!(target: logging::NETWORK, "Starting round {} of {}", round_index + 1, tournament.round_count());
+
+            if let Some(bye) = round.bye {
+                self.participants[bye].notify(format!("You sit out round {}.", round_index + 1));
+            }
+            for (pairing_index, pairing) in round.pairings.iter().enumerate() {
+                let (white, black) = (pairing.white, pairing.black);
+                if !self.participants[white].is_connected() || !self.participants[black].is_connected() {
+                    let result = forfeit_result(self.participants[white].is_connected(), self.participants[black].is_connected());
+                    self.tournament.as_mut().unwrap().record(round_index, pairing_index, result, vec![]);
+                    continue;
+                }
+                let mut game = LiveGame {
+                    pairing: pairing_index,
+                    white,
+                    black,
+                    referee: self.backend.new_game(),
+                    history: vec![],
+                    draw_offer: None,
+                };
+                let board = convert_board(game.referee.get_pieces());
+                let moves = legal_moves(&mut game.referee);
+                for (player, opponent, server_color) in [(white, black, ProtocolColor::Black), (black, white, ProtocolColor::White)] {
+                    let opponent_name = self.participants[opponent].name.clone();
+                    let participant = &mut self.participants[player];
+                    participant.send(&ExtensionMessage::RematchStarted { server_color, board, moves: moves.clone() });
+                    let color = if player == white { "white" } else { "black" };
+                    participant.notify(format!("Round {}: you play {color} against {opponent_name}.", round_index + 1));
+                }
+                self.games.push(game);
+            }
+        }
+    }
+}
+
+/// The result of a game that one or both players have left.
+fn forfeit_result(white_connected: bool, black_connected: bool) -> GameResult {
+    let outcome = match (white_connected, black_connected) {
+        (true, false) => Outcome::WhiteWins,
+        (false, true) => Outcome::BlackWins,
+        _ => Outcome::Draw,
+    };
+    GameResult { outcome, termination: Termination::Forfeit }
+}
+
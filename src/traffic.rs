@@ -0,0 +1,177 @@
+//! A record of every message sent and received over the network.
+//!
+//! Every message going through a [`JsonTcpStream`](crate::json_tcp_stream::JsonTcpStream)
+//! is kept in memory so that the latest messages can be shown in the inspector, and
+//! appended to a log file if that has been turned on. This is mostly useful for finding out
+//! why another implementation of the protocol doesn't agree with ours.
+//!
+//! Once the log file reaches its size limit it is renamed with `.old` appended, replacing
+//! the previous one, and a new file is started.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::logging;
+
+const TRAFFIC_LOG_FILE: &str = "traffic.log";
+/// How large the traffic log file may grow before it is rotated, in bytes.
+pub const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+/// How many messages are kept in memory for the inspector.
+const RECENT_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    pub fn arrow(self) -> &'static str {
+        match self {
+            Direction::Sent => "->",
+            Direction::Received => "<-",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrafficEntry {
+    /// The time of day in UTC, like `14:03:27.512`.
+    pub time: String,
+    pub direction: Direction,
+    /// The address of the other side of the connection.
+    pub peer: String,
+    pub json: String,
+}
+
+impl TrafficEntry {
+    pub fn line(&self) -> String {
+        format!("{} {} {} {}", self.time, self.direction.arrow(), self.peer, self.json)
+    }
+}
+
+/// The file messages are appended to.
+struct LogFile {
+    file: File,
+    path: PathBuf,
+    /// The size of the file so far, in bytes.
+    size: u64,
+    max_size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path, max_size: u64) -> io::Result<Self> {
+        if fs::metadata(path).is_ok_and(|metadata| metadata.len() >= max_size) {
+            fs::rename(path, old_path(path))?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { file, path: path.to_path_buf(), size, max_size })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.file, "{line}")?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// Where a full log file at `path` is moved to.
+fn old_path(path: &Path) -> PathBuf {
+    let mut old = path.as_os_str().to_owned();
+    old.push(".old");
+    PathBuf::from(old)
+}
+
+struct TrafficLog {
+    file: Option<LogFile>,
+    recent: VecDeque<TrafficEntry>,
+}
+
+fn log() -> MutexGuard<'static, TrafficLog> {
+    static LOG: OnceLock<Mutex<TrafficLog>> = OnceLock::new();
+    LOG.get_or_init(|| Mutex::new(TrafficLog { file: None, recent: VecDeque::new() }))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl TrafficLog {
+    fn write_line(&mut self, line: &str) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        if file.size >= file.max_size {
+            let (path, max_size) = (file.path.clone(), file.max_size);
+            // Closed first, since open files can't be renamed everywhere.
+            self.file = None;
+            match LogFile::open(&path, max_size) {
+                Ok(file) => self.file = Some(file),
+                Err(err) => {
+                    log::warn!(target: logging::NETWORK, "Failed to rotate the traffic log {}: {err}", path.display());
+                    return;
+                }
+            }
+        }
+        if let Some(Err(err)) = self.file.as_mut().map(|file| file.write_line(line)) {
+            log::warn!(target: logging::NETWORK, "Failed to write to the traffic log, closing it: {err}");
+            self.file = None;
+        }
+    }
+}
+
+/// Start appending messages to the file at `path`. When it grows past `max_size` bytes it
+/// is rotated, see the [module documentation](self).
+pub fn open_file(path: &Path, max_size: u64) {
+    let file = path.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| LogFile::open(path, max_size));
+    match file {
+        Ok(file) => {
+            let mut log = log();
+            log.file = Some(file);
+            log.write_line(&format!("--- {} session started", time_of_day()));
+        }
+        Err(err) => log::warn!(target: logging::NETWORK, "Failed to open traffic log {}: {err}", path.display()),
+    }
+}
+
+/// Where the traffic log is written by default.
+pub fn default_path() -> Option<PathBuf> {
+    crate::settings::config_dir().map(|dir| dir.join(TRAFFIC_LOG_FILE))
+}
+
+pub fn record(direction: Direction, peer: &str, json: &str) {
+    let entry = TrafficEntry {
+        time: time_of_day(),
+        direction,
+        peer: peer.to_string(),
+        json: json.to_string(),
+    };
+    let mut log = log();
+    log.write_line(&entry.line());
+    if log.recent.len() == RECENT_CAPACITY {
+        log.recent.pop_front();
+    }
+    log.recent.push_back(entry);
+}
+
+/// The latest messages, oldest first.
+pub fn recent() -> Vec<TrafficEntry> {
+    log().recent.iter().cloned().collect()
+}
+
+fn time_of_day() -> String {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() % (24 * 60 * 60);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_millis(),
+    )
+}
@@ -1,12 +1,29 @@
 use std::cell::RefCell;
 use ggez::event::{EventHandler, MouseButton};
+use ggez::input::keyboard::KeyInput;
 use ggez::{Context, GameError, GameResult};
 
 pub mod main_menu;
 pub mod board_view;
+pub mod replay_view;
+pub mod settings_view;
+pub mod tournament_view;
+pub mod traffic_view;
+mod animation;
+mod layout;
 
 pub struct MainState{
-    current_view: RefCell<Box<dyn View>>,
+    /// The views that are open. The last one is shown and receives input while the ones
+    /// below it are only updated, so that network games keep going behind other views.
+    views: RefCell<Vec<Box<dyn View>>>,
+}
+
+/// A change of which view is shown, requested by the view that is currently shown.
+pub enum Transition {
+    /// Show a new view on top of the current one.
+    Push(Box<dyn View>),
+    /// Close the current view and go back to the one below it.
+    Pop,
 }
 
 pub trait View {
@@ -18,6 +35,16 @@ pub trait View {
     fn mouse_motion_event(&mut self, _ctx: &mut Context, _x: f32, _y: f32, _dx: f32, _dy: f32) -> GameResult {
         Ok(())
     }
+    fn key_down_event(&mut self, _ctx: &mut Context, _input: KeyInput, _repeated: bool) -> GameResult {
+        Ok(())
+    }
+    fn text_input_event(&mut self, _ctx: &mut Context, _character: char) -> GameResult {
+        Ok(())
+    }
+    /// Called after every event to see if the view wants to open or close a view.
+    fn transition(&mut self) -> Option<Transition> {
+        None
+    }
 }
 
 struct NoopView {}
@@ -32,29 +59,64 @@ impl View for NoopView {
 
 impl MainState {
     pub fn new() -> Self {
-        Self { current_view: RefCell::new(Box::new(NoopView {})) }
+        Self { views: RefCell::new(vec![Box::new(NoopView {})]) }
     }
 
     pub fn set_view(&self, view: impl View + 'static) {
-        let mut v = self.current_view.borrow_mut();
-        *v = Box::new(view);
+        let mut views = self.views.borrow_mut();
+        views.pop();
+        views.push(Box::new(view));
+    }
+
+    /// Call `f` with the view that is shown and then apply any transition it requests.
+    fn with_top_view(&self, f: impl FnOnce(&mut dyn View) -> GameResult) -> GameResult {
+        let mut views = self.views.borrow_mut();
+        let top = views.last_mut().expect("There is always a view");
+        f(top.as_mut())?;
+        match top.transition() {
+            Some(Transition::Push(view)) => views.push(view),
+            // The bottom view can't be closed since there would be nothing to show.
+            Some(Transition::Pop) if views.len() > 1 => {
+                views.pop();
+            }
+            Some(Transition::Pop) | None => {}
+        }
+        Ok(())
+    }
+}
+
+impl Default for MainState {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl EventHandler<GameError> for MainState {
     fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
-        self.current_view.borrow_mut().update(ctx)
+        let covered_views = self.views.borrow().len() - 1;
+        for view in self.views.borrow_mut().iter_mut().take(covered_views) {
+            view.update(ctx)?;
+        }
+        self.with_top_view(|view| view.update(ctx))
     }
 
     fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
-        self.current_view.borrow_mut().draw(ctx)
+        self.with_top_view(|view| view.draw(ctx))
     }
 
     fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
-        self.current_view.borrow_mut().mouse_button_down_event(ctx, button, x, y)
+        self.with_top_view(|view| view.mouse_button_down_event(ctx, button, x, y))
     }
 
     fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) -> Result<(), GameError> {
-        self.current_view.borrow_mut().mouse_motion_event(ctx, x, y, dx, dy)
+        self.with_top_view(|view| view.mouse_motion_event(ctx, x, y, dx, dy))
     }
-}
\ No newline at end of file
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeated: bool) -> Result<(), GameError> {
+        self.with_top_view(|view| view.key_down_event(ctx, input, repeated))
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) -> Result<(), GameError> {
+        self.with_top_view(|view| view.text_input_event(ctx, character))
+    }
+}
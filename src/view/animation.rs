@@ -0,0 +1,130 @@
+//! Animations of pieces moving between squares.
+//!
+//! The board is compared against the one from the previous frame, so moves are animated
+//! no matter where they came from, be it a local click or a packet from the opponent.
+
+use std::time::Duration;
+use crate::bridge::{Move, Piece, Square};
+
+/// Changes touching more squares than this are not animated. They are things like a new
+/// game being started where sliding pieces around would just be confusing.
+const MAX_ANIMATED_CHANGES: usize = 8;
+
+pub enum AnimationKind {
+    Slide { from: Square, to: Square },
+    FadeOut { at: Square },
+}
+
+pub struct Animation {
+    pub piece: Piece,
+    pub kind: AnimationKind,
+    start: Duration,
+}
+
+pub struct Animator {
+    previous: [[Option<Piece>; 8]; 8],
+    animations: Vec<Animation>,
+    duration: Duration,
+}
+
+impl Animator {
+    pub fn new(board: [[Option<Piece>; 8]; 8], duration: Duration) -> Self {
+        Self { previous: board, animations: vec![], duration }
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    /// Compare `board` against the board from the last call and start animating the pieces
+    /// that have moved. `last_move` is used to pair up the squares of the move that was
+    /// just made, which matters for promotions where the piece changes type.
+    pub fn update(&mut self, board: [[Option<Piece>; 8]; 8], last_move: Option<Move>, now: Duration) {
+        let duration = self.duration;
+        self.animations.retain(|animation| now < animation.start + duration);
+
+        let mut removed: Vec<(Square, Piece)> = vec![];
+        let mut added: Vec<(Square, Piece)> = vec![];
+        for (rank, (old_row, new_row)) in self.previous.iter().zip(board.iter()).enumerate() {
+            for (file, (&old, &new)) in old_row.iter().zip(new_row.iter()).enumerate() {
+                if old == new {
+                    continue;
+                }
+                let square: Square = (file as i32, rank as i32).try_into().unwrap();
+                if let Some(piece) = old {
+                    removed.push((square, piece));
+                }
+                if let Some(piece) = new {
+                    added.push((square, piece));
+                }
+            }
+        }
+        self.previous = board;
+
+        if self.duration.is_zero() || removed.len() + added.len() > MAX_ANIMATED_CHANGES {
+            return;
+        }
+
+        if let Some(Move::Normal { from, to }) = last_move {
+            let from_index = removed.iter().position(|(square, _)| *square == from);
+            let to_index = added.iter().position(|(square, _)| *square == to);
+            if let (Some(from_index), Some(to_index)) = (from_index, to_index) {
+                removed.remove(from_index);
+                let (_, piece) = added.remove(to_index);
+                self.start(piece, AnimationKind::Slide { from, to }, now);
+            }
+        }
+
+        // Whatever else moved, like the rook when castling, is paired with the closest
+        // square that lost an identical piece.
+        for (to, piece) in added {
+            let closest = removed.iter()
+                .enumerate()
+                .filter(|(_, (_, old))| *old == piece)
+                .min_by_key(|(_, (from, _))| distance(*from, to))
+                .map(|(index, _)| index);
+            if let Some(index) = closest {
+                let (from, _) = removed.remove(index);
+                self.start(piece, AnimationKind::Slide { from, to }, now);
+            }
+        }
+
+        // Pieces that disappeared without going anywhere were captured.
+        for (at, piece) in removed {
+            self.start(piece, AnimationKind::FadeOut { at }, now);
+        }
+    }
+
+    fn start(&mut self, piece: Piece, kind: AnimationKind, now: Duration) {
+        self.animations.push(Animation { piece, kind, start: now });
+    }
+
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations
+    }
+
+    /// Whether a piece is currently sliding into `square`. The piece standing there should
+    /// not be drawn until the animation has finished.
+    pub fn is_sliding_to(&self, square: Square) -> bool {
+        self.animations.iter().any(|animation| match animation.kind {
+            AnimationKind::Slide { to, .. } => to == square,
+            AnimationKind::FadeOut { .. } => false,
+        })
+    }
+
+    /// How far along `animation` is, from 0 to 1, eased so that pieces slow down as they
+    /// arrive.
+    pub fn progress(&self, animation: &Animation, now: Duration) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        let t = (now.saturating_sub(animation.start).as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        1.0 - (1.0 - t).powi(3)
+    }
+}
+
+fn distance(a: Square, b: Square) -> i32 {
+    let file_distance = a.file() - b.file();
+    let rank_distance = a.rank() - b.rank();
+    file_distance.abs() + rank_distance.abs()
+}
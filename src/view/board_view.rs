@@ -1,30 +1,90 @@
-use erikfran_chess::{util::{Square, BoardMove, Rank}, PieceTypes, MoveError, Move, CastlingSide};
+use std::time::Duration;
 use ggez::{event::MouseButton, Context, GameResult, graphics::{self, Image, MeshBuilder, FillOptions, Rect, Color, Mesh, Text, DrawParam}, glam::Vec2};
-use crate::bridge;
-use crate::view::View;
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
+use crate::bridge::{self, CastlingSide, Move, PieceType, Square};
+use crate::clock::{self, Clocks};
+use crate::extension;
+use crate::logging;
+use crate::notation;
+use crate::saved_game::SavedGame;
+use crate::session::{Decision, DrawState, GameSession, RematchState, TakebackState};
+use crate::settings::{Orientation, SharedSettings};
+use crate::theme::{self, Theme};
+use crate::view::{Transition, View};
+use crate::view::settings_view::SettingsView;
+use crate::view::traffic_view::TrafficView;
+use crate::view::animation::{AnimationKind, Animator};
+use crate::view::layout::Layout;
 
 const SQUARE_SIZE: f32 = 64.0;
 const BOARD_SIZE: f32 = SQUARE_SIZE * 8.0;
+const DEFAULT_ANIMATION_DURATION: Duration = Duration::from_millis(200);
+const LAST_MOVE_COLOR: Color = Color::new(1.0, 1.0, 0.4, 0.4);
+const CHECK_COLOR: Color = Color::new(1.0, 0.0, 0.0, 0.6);
+const SELECTED_COLOR: Color = Color::new(0.2, 0.6, 1.0, 1.0);
+const PREMOVE_COLOR: Color = Color::new(0.3, 0.5, 1.0, 0.4);
+/// How much of the panel the chat takes up, when the game has chat.
+const CHAT_FRACTION: f32 = 0.4;
 
 struct CastlingPossibility {
-    _color: erikfran_chess::Color,
+    _color: bridge::Color,
     kingside: bool,
     queenside: bool,
 }
 
-pub struct BoardView<T: bridge::ChessGame> {
+pub struct BoardView<T: GameSession> {
     frames: usize,
     board: Mesh,
+    /// A white square that is tinted when drawn to highlight squares.
+    square_fill: Mesh,
+    /// A white square outline that is tinted when drawn.
+    square_outline: Mesh,
     game: T,
     board_start: Vec2,
     scale: f32,
-    possible_moves: Option<BoardMove>,
+    /// The moves the selected piece can make, except castling.
+    possible_moves: Option<Vec<Move>>,
     possible_castling: Option<CastlingPossibility>,
+    selected_square: Option<Square>,
+    /// Moves queued while waiting for the opponent. They are made as soon as it is our
+    /// turn, or all cancelled if the first one turns out to be illegal.
+    premoves: Vec<Move>,
+    premove_from: Option<Square>,
     white_icons: PieceIcons,
     black_icons: PieceIcons,
-    latest_error: Option<MoveError>,
+    themes: Vec<Theme>,
+    theme_index: usize,
+    latest_error: Option<String>,
     promotion_square: Option<Square>,
     promotion_coordinates: Option<PromotionCoordinates>,
+    /// The length of the game history the last time it was checked. Used to notice
+    /// when moves have been taken back.
+    history_len: usize,
+    /// The number of rematches the last time it was checked, to notice new games.
+    rematches: usize,
+    animator: Animator,
+    clocks: Clocks,
+    settings: Option<SharedSettings>,
+    /// The theme name from the settings that was last applied.
+    applied_theme: Option<String>,
+    orientation: Orientation,
+    transition: Option<Transition>,
+    autosave: bool,
+    /// The history length, whether the last move was a promotion and whether the game was
+    /// resigned or drawn by agreement when the game was last saved. Promotions are chosen
+    /// after the move, so the history length alone doesn't notice every change.
+    saved_position: (usize, bool, bool),
+    /// Set while asking whether to start a new game in a game that plays both colors, where
+    /// there is no opponent to accept it.
+    confirming_new_game: bool,
+    /// Set while asking whether to resign.
+    confirming_resign: bool,
+    /// When locked the board can't be clicked and the clocks don't run. Used for replays.
+    locked: bool,
+    /// Extra text shown under whose turn it is.
+    status: Option<String>,
+    /// The chat message being typed, or none if the keyboard controls the game.
+    chat_draft: Option<String>,
 }
 
 struct PieceIcons {
@@ -45,117 +105,671 @@ struct PromotionCoordinates {
 }
 
 impl PieceIcons {
-    fn new(ctx: &Context, prefix: &str) -> GameResult<Self> {
+    fn new(ctx: &Context, theme: &Theme, prefix: &str) -> GameResult<Self> {
         Ok(Self {
-            king: Image::from_path(ctx, format!("/{prefix}_king.png"))?,
-            queen: Image::from_path(ctx, format!("/{prefix}_queen.png"))?,
-            bishop: Image::from_path(ctx, format!("/{prefix}_bishop.png"))?,
-            rook: Image::from_path(ctx, format!("/{prefix}_rook.png"))?,
-            knight: Image::from_path(ctx, format!("/{prefix}_knight.png"))?,
-            pawn: Image::from_path(ctx, format!("/{prefix}_pawn.png"))?,
+            king: Image::from_path(ctx, theme.piece_path(&format!("{prefix}_king")))?,
+            queen: Image::from_path(ctx, theme.piece_path(&format!("{prefix}_queen")))?,
+            bishop: Image::from_path(ctx, theme.piece_path(&format!("{prefix}_bishop")))?,
+            rook: Image::from_path(ctx, theme.piece_path(&format!("{prefix}_rook")))?,
+            knight: Image::from_path(ctx, theme.piece_path(&format!("{prefix}_knight")))?,
+            pawn: Image::from_path(ctx, theme.piece_path(&format!("{prefix}_pawn")))?,
         })
     }
 
-    fn get_image(&self, piece_type: PieceTypes) -> &Image {
+    fn get_image(&self, piece_type: PieceType) -> &Image {
         match piece_type {
-            PieceTypes::King => &self.king,
-            PieceTypes::Queen => &self.queen,
-            PieceTypes::Bishop => &self.bishop,
-            PieceTypes::Rook => &self.rook,
-            PieceTypes::Knight => &self.knight,
-            PieceTypes::Pawn(_) => &self.pawn,
+            PieceType::King => &self.king,
+            PieceType::Queen => &self.queen,
+            PieceType::Bishop => &self.bishop,
+            PieceType::Rook => &self.rook,
+            PieceType::Knight => &self.knight,
+            PieceType::Pawn => &self.pawn,
         }
     }
 }
 
-impl<T: bridge::ChessGame> BoardView<T> {
-    pub fn new(ctx: &mut Context, game: T) -> GameResult<BoardView<T>> {
-        let white_icons = PieceIcons::new(ctx, "white")?;
-        let black_icons = PieceIcons::new(ctx, "black")?;
-
-        let black_square_color: Color = Color::from_rgb(120, 100, 80);
-        let white_square_color: Color = Color::from_rgb(220, 190, 30);
+fn board_mesh(ctx: &Context, theme: &Theme) -> Mesh {
+    let [r, g, b] = theme.dark_squares;
+    let black_square_color: Color = Color::from_rgb(r, g, b);
+    let [r, g, b] = theme.light_squares;
+    let white_square_color: Color = Color::from_rgb(r, g, b);
 
-        let mut board_builder = MeshBuilder::new();
-        for i in 0..8 {
-            for j in 0..8 {
-                let color = if (i + j) % 2 == 0 { white_square_color } else { black_square_color };
-                board_builder.rectangle(
-                    graphics::DrawMode::Fill(FillOptions::default()),
-                    Rect::new(i as f32 * SQUARE_SIZE, j as f32 * SQUARE_SIZE, SQUARE_SIZE, SQUARE_SIZE),
-                    color
-                ).expect("Failed to draw rectangle.");
-            }
+    let mut board_builder = MeshBuilder::new();
+    for i in 0..8 {
+        for j in 0..8 {
+            let color = if (i + j) % 2 == 0 { white_square_color } else { black_square_color };
+            board_builder.rectangle(
+                graphics::DrawMode::Fill(FillOptions::default()),
+                Rect::new(i as f32 * SQUARE_SIZE, j as f32 * SQUARE_SIZE, SQUARE_SIZE, SQUARE_SIZE),
+                color
+            ).expect("Failed to draw rectangle.");
         }
-        let board = Mesh::from_data(ctx, board_builder.build());
+    }
+    Mesh::from_data(ctx, board_builder.build())
+}
+
+impl<T: GameSession> BoardView<T> {
+    pub fn new(ctx: &mut Context, game: T) -> GameResult<BoardView<T>> {
+        let themes = theme::load_themes(ctx);
+        let white_icons = PieceIcons::new(ctx, &themes[0], "white")?;
+        let black_icons = PieceIcons::new(ctx, &themes[0], "black")?;
+        let board = board_mesh(ctx, &themes[0]);
+
+        let square_rect = Rect::new(0.0, 0.0, SQUARE_SIZE, SQUARE_SIZE);
+        let square_fill = Mesh::from_data(ctx, MeshBuilder::new().rectangle(
+            graphics::DrawMode::Fill(FillOptions::default()),
+            square_rect,
+            Color::WHITE,
+        )?.build());
+        let square_outline = Mesh::from_data(ctx, MeshBuilder::new().rectangle(
+            graphics::DrawMode::stroke(4.0),
+            Rect::new(2.0, 2.0, SQUARE_SIZE - 4.0, SQUARE_SIZE - 4.0),
+            Color::WHITE,
+        )?.build());
+        let animator = Animator::new(game.get_pieces(), DEFAULT_ANIMATION_DURATION);
+        let history_len = game.history().len();
 
         Ok(BoardView {
             frames: 0,
             game,
             board,
+            square_fill,
+            square_outline,
             board_start: Vec2::new(0.0, 0.0),
             scale: 1.0,
             possible_moves: None,
             possible_castling: None,
+            selected_square: None,
+            premoves: vec![],
+            premove_from: None,
             white_icons,
             black_icons,
+            themes,
+            theme_index: 0,
             latest_error: None,
             promotion_square: None,
             promotion_coordinates: None,
+            history_len,
+            rematches: 0,
+            animator,
+            clocks: Clocks::default(),
+            settings: None,
+            applied_theme: None,
+            orientation: Orientation::Auto,
+            transition: None,
+            autosave: false,
+            saved_position: (0, false, false),
+            confirming_new_game: false,
+            confirming_resign: false,
+            locked: false,
+            status: None,
+            chat_draft: None,
         })
     }
+
+    /// Only show the game, without letting moves be made on the board.
+    pub fn with_input_locked(mut self) -> Self {
+        self.locked = true;
+        self
+    }
+
+    pub fn game(&self) -> &T {
+        &self.game
+    }
+
+    pub fn game_mut(&mut self) -> &mut T {
+        &mut self.game
+    }
+
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    /// Start the clocks at `clocks` instead of zero, used when resuming a saved game.
+    pub fn with_clocks(mut self, clocks: Clocks) -> Self {
+        self.clocks = clocks;
+        self
+    }
+
+    /// Save the game to disk after every move so that it can be resumed later.
+    pub fn with_autosave(mut self) -> Self {
+        self.autosave = true;
+        self.saved_position = self.position_key();
+        self
+    }
+
+    fn position_key(&self) -> (usize, bool, bool) {
+        let history = self.game.history();
+        (history.len(), history.last().is_some_and(|played| played.promotion.is_some()), self.game.decision().is_some())
+    }
+
+    fn autosave(&mut self) {
+        let position = self.position_key();
+        if !self.autosave || position == self.saved_position {
+            return;
+        }
+        self.saved_position = position;
+        if self.game.is_over() {
+            SavedGame::clear();
+        } else if let Some(session) = self.game.saved_session() {
+            SavedGame::new(session, self.game.history(), self.clocks).save();
+        }
+    }
+
+    /// Follow the theme, orientation and animation settings in `settings`, and allow
+    /// opening the settings view.
+    pub fn with_settings(mut self, ctx: &Context, settings: SharedSettings) -> Self {
+        self.settings = Some(settings);
+        self.apply_settings(ctx);
+        self
+    }
+
+    fn apply_settings(&mut self, ctx: &Context) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let settings = settings.borrow().get().clone();
+        self.set_animation_duration(settings.animation_duration());
+        self.orientation = settings.orientation;
+        if settings.theme != self.applied_theme {
+            if let Some(name) = &settings.theme {
+                self.set_theme(ctx, name);
+            }
+            self.applied_theme = settings.theme;
+        }
+    }
+
+    /// Whether black is at the bottom of the board.
+    fn is_flipped(&self) -> bool {
+        self.orientation.bottom_color(self.game.player_color()) == bridge::Color::Black
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.themes[self.theme_index]
+    }
+
+    /// Switch to the theme called `name`. Returns false if there is no such theme or its
+    /// pieces could not be loaded, in which case the current theme is kept.
+    pub fn set_theme(&mut self, ctx: &Context, name: &str) -> bool {
+        let Some(index) = self.themes.iter().position(|theme| theme.name == name) else {
+            return false;
+        };
+        let applied = self.apply_theme(ctx, &self.themes[index].clone());
+        if applied {
+            self.theme_index = index;
+        }
+        applied
+    }
+
+    /// Switch to the next theme. The theme file is read again first so that themes can be
+    /// edited without restarting.
+    fn next_theme(&mut self, ctx: &Context) {
+        let themes = theme::load_themes(ctx);
+        let current_index = themes.iter().position(|theme| theme.name == self.theme().name);
+        let next_index = current_index.map_or(0, |index| (index + 1) % themes.len());
+        if !self.apply_theme(ctx, &themes[next_index]) {
+            return;
+        }
+        let name = themes[next_index].name.clone();
+        self.themes = themes;
+        self.theme_index = next_index;
+        if let Some(settings) = &self.settings {
+            settings.borrow_mut().change(|settings| settings.theme = Some(name.clone()));
+            self.applied_theme = Some(name);
+        }
+    }
+
+    /// Load the pieces and board colors of `theme`. Returns false if the pieces could not be
+    /// loaded, in which case nothing is changed.
+    fn apply_theme(&mut self, ctx: &Context, theme: &Theme) -> bool {
+        let icons = PieceIcons::new(ctx, theme, "white")
+            .and_then(|white_icons| Ok((white_icons, PieceIcons::new(ctx, theme, "black")?)));
+        match icons {
+            Ok((white_icons, black_icons)) => {
+                self.white_icons = white_icons;
+                self.black_icons = black_icons;
+                self.board = board_mesh(ctx, theme);
+                true
+            }
+            Err(err) => {
+                log::warn!(target: logging::RENDERING, "Failed to load the pieces of theme {}: {err}", theme.name);
+                false
+            }
+        }
+    }
+
+    /// How long it takes for a piece to slide to its new square. Zero disables animations.
+    pub fn set_animation_duration(&mut self, duration: Duration) {
+        self.animator.set_duration(duration);
+    }
+
+    /// The screen position of the top left corner of the square at `file` and `rank`.
+    /// Fractional coordinates are used for pieces in between squares.
+    fn board_position(&self, file: f32, rank: f32) -> Vec2 {
+        let (column, row) = if self.is_flipped() { (7.0 - file, rank) } else { (file, 7.0 - rank) };
+        self.board_start + Vec2::new(column * SQUARE_SIZE * self.scale, row * SQUARE_SIZE * self.scale)
+    }
+
+    fn highlight_square(&self, canvas: &mut graphics::Canvas, square: Square, mesh: &Mesh, color: Color) {
+        let pos = self.board_position(square.file() as f32, square.rank() as f32);
+        let param = DrawParam::new().dest(pos).scale(Vec2::new(self.scale, self.scale)).color(color);
+        canvas.draw(mesh, param);
+    }
+
+    /// The square of the king belonging to the player whose turn it is.
+    fn king_in_turn(&self) -> Option<Square> {
+        let turn = self.game.current_turn();
+        for (rank_index, row) in self.game.get_pieces().iter().enumerate() {
+            for (file_index, piece) in row.iter().enumerate() {
+                if let Some(piece) = piece {
+                    if matches!(piece.kind, PieceType::King) && piece.color == turn {
+                        return (file_index as i32, rank_index as i32).try_into().ok();
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// The square at the screen coordinates `x` and `y`, if they are on the board.
+    fn square_at(&self, x: f32, y: f32) -> Option<Square> {
+        let rel_x = x - self.board_start.x;
+        let rel_y = y - self.board_start.y;
+        if rel_x < 0.0 || rel_y < 0.0 {
+            return None;
+        }
+        let column = (rel_x / SQUARE_SIZE / self.scale) as i32;
+        let row = (rel_y / SQUARE_SIZE / self.scale) as i32;
+        let (file, rank) = if self.is_flipped() { (7 - column, row) } else { (column, 7 - row) };
+        (file, rank).try_into().ok()
+    }
+
+    /// Perform `mv` and update the selection and promotion state accordingly.
+    fn make_move(&mut self, mv: Move) {
+        let result = self.game.perform_move(mv);
+        match result {
+            Ok(_) => {
+                self.possible_moves = None;
+                self.possible_castling = None;
+                self.selected_square = None;
+                self.latest_error = None;
+                if let Move::Normal { to, .. } = mv {
+                    let is_pawn = self.game.get_piece(to).is_some_and(|piece| piece.kind == PieceType::Pawn);
+                    if (to.rank() == 0 || to.rank() == 7) && is_pawn {
+                        // Promotion
+                        self.promotion_square = Some(to);
+                    }
+                }
+            },
+            Err(err) => {
+                self.latest_error = Some(err.to_string());
+            },
+        }
+    }
+
+    /// Our color when queueing premoves, which is the one whose turn it isn't.
+    fn premove_color(&self) -> bridge::Color {
+        self.game.player_color().unwrap_or_else(|| self.game.current_turn().opposite())
+    }
+
+    /// The squares a queued premove goes from and to.
+    fn premove_squares(&self, premove: Move) -> (Square, Square) {
+        match premove {
+            Move::Normal { from, to } => (from, to),
+            Move::Castle { side } => side.king_squares(self.premove_color()),
+        }
+    }
+
+    /// Handle a click on `square` while it is the opponent's turn, which queues premoves.
+    fn click_premove(&mut self, square: Square) {
+        let ours = self.premove_color();
+        let Some(from) = self.premove_from.take() else {
+            // A piece that is already on its way somewhere by an earlier premove can be
+            // moved on from there.
+            let is_ours = self.game.get_piece(square).is_some_and(|piece| piece.color == ours);
+            let is_premove_target = self.premoves.iter().any(|mv| self.premove_squares(*mv).1 == square);
+            if is_ours || is_premove_target {
+                self.premove_from = Some(square);
+            }
+            return;
+        };
+        if from == square {
+            return;
+        }
+        // Moving the king two squares from its starting square is castling.
+        let king = Some(bridge::Piece { kind: PieceType::King, color: ours });
+        let castle = [CastlingSide::KingSide, CastlingSide::QueenSide].into_iter()
+            .find(|side| side.king_squares(ours) == (from, square) && self.game.get_piece(from) == king);
+        self.premoves.push(match castle {
+            Some(side) => Move::Castle { side },
+            None => Move::Normal { from, to: square },
+        });
+    }
+
+    /// Make the first queued premove if it is our turn. If it has become illegal the whole
+    /// queue is dropped, since the later premoves were planned with it in mind.
+    fn play_premove(&mut self) {
+        if self.premoves.is_empty() || !self.game.can_play_right_now() || self.promotion_square.is_some() {
+            return;
+        }
+        let premove = self.premoves.remove(0);
+        let (from, to) = self.premove_squares(premove);
+        let moves = self.game.possible_moves(from).unwrap_or_default();
+        let legal_move = match premove {
+            Move::Normal { .. } => move_to(&moves, to),
+            Move::Castle { .. } => moves.contains(&premove).then_some(premove),
+        };
+        let Some(mv) = legal_move else {
+            self.premoves.clear();
+            self.latest_error = Some(String::from("A premove was no longer legal, so the premoves were cancelled."));
+            return;
+        };
+        self.make_move(mv);
+        if self.latest_error.is_some() {
+            self.premoves.clear();
+        }
+    }
+
+    /// Draw the status text, clocks, move list and chat in the panel next to the board.
+    fn draw_panel(&self, ctx: &Context, canvas: &mut graphics::Canvas, layout: &Layout) -> GameResult {
+        let text_scale = layout.text_scale;
+        let chat_area = self.game.supports_chat().then(|| {
+            let height = layout.panel.h * CHAT_FRACTION;
+            Rect::new(layout.panel.x, layout.panel.bottom() - height, layout.panel.w, height)
+        });
+        let panel = match chat_area {
+            Some(chat_area) => Rect::new(layout.panel.x, layout.panel.y, layout.panel.w, layout.panel.h - chat_area.h - text_scale),
+            None => layout.panel,
+        };
+        let line_height = text_scale * 1.3;
+        let (status_width, moves_area) = if layout.panel_has_columns() {
+            let column_width = panel.w / 2.0;
+            (column_width, Some(Rect::new(panel.x + column_width, panel.y, column_width, panel.h)))
+        } else {
+            (panel.w, None)
+        };
+
+        let mut y = panel.y;
+        let mut line = |canvas: &mut graphics::Canvas, text: &str, color: Color| -> GameResult {
+            y += draw_wrapped_text(ctx, canvas, text, Vec2::new(panel.x, y), status_width, text_scale, color)?;
+            Ok(())
+        };
+
+        let turn = self.game.current_turn();
+        let color_text = if turn == bridge::Color::White { "white" } else { "black" };
+        line(canvas, &format!("{color_text}'s turn"), Color::WHITE)?;
+        if let Some(status) = &self.status {
+            line(canvas, status, Color::WHITE)?;
+        }
+
+        let decision_text = match self.game.decision() {
+            Some(Decision::Resignation(bridge::Color::White)) => Some("White resigned."),
+            Some(Decision::Resignation(bridge::Color::Black)) => Some("Black resigned."),
+            Some(Decision::DrawAgreed) => Some("The game was drawn by agreement."),
+            None => None,
+        };
+        if let Some(decision_text) = decision_text {
+            line(canvas, decision_text, Color::WHITE)?;
+        }
+
+        if self.game.is_over() && !self.locked && !self.confirming_new_game {
+            line(canvas, "The game is over. Press R to play again.", Color::WHITE)?;
+        }
+
+        if self.game.is_check() {
+            let sin = ((self.frames as f64) / 25.0).sin() + 1.0;
+            let color_value = (sin * 128.0) as u8;
+            line(canvas, "check", Color::from_rgb(255, color_value, color_value))?;
+        }
+
+        let takeback_text = match self.game.takeback_state() {
+            TakebackState::None => None,
+            TakebackState::Requested => Some("Waiting for the opponent to accept the takeback..."),
+            TakebackState::Offered => Some("The opponent wants to take back a move. Accept? (y/n)"),
+        };
+        if let Some(takeback_text) = takeback_text {
+            line(canvas, takeback_text, Color::WHITE)?;
+        }
+
+        let rematch_text = match self.game.rematch_state() {
+            RematchState::None => None,
+            RematchState::Requested { .. } => Some("Waiting for the opponent to accept the rematch..."),
+            RematchState::Offered { swap_colors: true } => Some("The opponent offers a rematch with the colors swapped. Accept? (y/n)"),
+            RematchState::Offered { swap_colors: false } => Some("The opponent offers a rematch with the same colors. Accept? (y/n)"),
+        };
+        if let Some(rematch_text) = rematch_text {
+            line(canvas, rematch_text, Color::WHITE)?;
+        }
+
+        let draw_text = match self.game.draw_state() {
+            DrawState::None => None,
+            DrawState::Requested => Some("Waiting for the opponent to accept the draw..."),
+            DrawState::Offered => Some("The opponent offers a draw. Press D to accept."),
+        };
+        if let Some(draw_text) = draw_text {
+            line(canvas, draw_text, Color::WHITE)?;
+        }
+        if self.confirming_resign {
+            line(canvas, "Resign? (y/n)", Color::WHITE)?;
+        }
+        if self.confirming_new_game {
+            line(canvas, "Start a new game? (y/n)", Color::WHITE)?;
+        }
+
+        if let Some(error) = &self.latest_error {
+            line(canvas, error, Color::from_rgb(255, 160, 160))?;
+        }
+
+        if let Some(problem) = self.game.sync_problem() {
+            line(canvas, &format!("Out of sync: {problem}"), Color::from_rgb(255, 200, 100))?;
+        }
+
+        if !self.locked {
+            line(canvas, "", Color::WHITE)?;
+            for (color, name) in [(bridge::Color::White, "White"), (bridge::Color::Black, "Black")] {
+                // The clock that is running is brighter.
+                let text_color = if color == turn { Color::WHITE } else { Color::from_rgb(150, 150, 150) };
+                line(canvas, &format!("{name}  {}", clock::format_duration(self.clocks.get(color))), text_color)?;
+            }
+        }
+        line(canvas, "", Color::WHITE)?;
+
+        let moves_area = moves_area.unwrap_or(Rect::new(panel.x, y, panel.w, panel.bottom() - y));
+        let history = self.game.history();
+        let rows: Vec<String> = history.chunks(2)
+            .enumerate()
+            .map(|(index, pair)| {
+                let names: Vec<String> = pair.iter().map(notation::move_name).collect();
+                format!("{}. {}", index + 1, names.join("  "))
+            })
+            .collect();
+        // Only the latest moves are shown if they don't all fit.
+        let visible_rows = ((moves_area.h / line_height) as usize).max(1);
+        let mut y = moves_area.y;
+        for row in &rows[rows.len().saturating_sub(visible_rows)..] {
+            y += draw_wrapped_text(ctx, canvas, row, Vec2::new(moves_area.x, y), moves_area.w, text_scale, Color::WHITE)?;
+        }
+
+        if let Some(chat_area) = chat_area {
+            self.draw_chat(ctx, canvas, chat_area, text_scale)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw the chat messages with the newest at the bottom, above the message being typed.
+    fn draw_chat(&self, ctx: &Context, canvas: &mut graphics::Canvas, area: Rect, text_scale: f32) -> GameResult {
+        let (input, input_color) = match &self.chat_draft {
+            Some(draft) => (format!("> {draft}_"), Color::WHITE),
+            None => (String::from("Press enter to chat"), Color::from_rgb(150, 150, 150)),
+        };
+        let lines = self.game.chat_messages().iter()
+            .map(|message| {
+                let (name, color) = if message.ours {
+                    ("You", Color::from_rgb(160, 220, 255))
+                } else {
+                    ("Opponent", Color::from_rgb(255, 220, 160))
+                };
+                (format!("{name}: {}", message.text), color)
+            })
+            .chain([(input, input_color)]);
+
+        let mut bottom = area.bottom();
+        for (text, color) in lines.rev() {
+            let mut text = Text::new(text);
+            text.set_scale(text_scale);
+            text.set_bounds(Vec2::new(area.w, f32::INFINITY));
+            text.set_wrap(true);
+            bottom -= text.measure(ctx)?.y.max(text_scale) * 1.3;
+            if bottom < area.y {
+                break;
+            }
+            canvas.draw(&text, DrawParam::new().dest(Vec2::new(area.x, bottom)).color(color));
+        }
+        Ok(())
+    }
+
+    /// Handle a key press while a chat message is being typed.
+    fn chat_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                if let Some(draft) = self.chat_draft.take() {
+                    self.game.send_chat(&draft);
+                }
+            }
+            KeyCode::Escape => self.chat_draft = None,
+            KeyCode::Back => {
+                if let Some(draft) = &mut self.chat_draft {
+                    draft.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn icons(&self, color: bridge::Color) -> &PieceIcons {
+        match color {
+            bridge::Color::White => &self.white_icons,
+            bridge::Color::Black => &self.black_icons,
+        }
+    }
 }
 
-impl<T: bridge::ChessGame> View for BoardView<T> {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+/// Draw `text` wrapped to `width` and return how much vertical space it took up.
+fn draw_wrapped_text(ctx: &Context, canvas: &mut graphics::Canvas, text: &str, pos: Vec2, width: f32, scale: f32, color: Color) -> GameResult<f32> {
+    let mut text = Text::new(text);
+    text.set_scale(scale);
+    text.set_bounds(Vec2::new(width, f32::INFINITY));
+    text.set_wrap(true);
+    let height = text.measure(ctx)?.y.max(scale);
+    canvas.draw(&text, DrawParam::new().dest(pos).color(color));
+    Ok(height * 1.3)
+}
+
+impl<T: GameSession> View for BoardView<T> {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.apply_settings(ctx);
         self.game.update();
+        if self.game.is_over() {
+            self.confirming_resign = false;
+        } else if !self.locked {
+            self.clocks.tick(self.game.current_turn(), ctx.time.delta());
+        }
+
+        let rematches = self.game.rematches();
+        if rematches != self.rematches {
+            self.rematches = rematches;
+            self.clocks = Clocks::default();
+            self.latest_error = None;
+        }
+
+        let history_len = self.game.history().len();
+        if history_len < self.history_len {
+            // Moves were taken back, so whatever was selected may no longer be valid.
+            self.possible_moves = None;
+            self.possible_castling = None;
+            self.selected_square = None;
+            self.latest_error = None;
+            self.promotion_square = None;
+            self.promotion_coordinates = None;
+            self.premoves.clear();
+            self.premove_from = None;
+            self.confirming_new_game = false;
+        }
+        self.history_len = history_len;
+
+        if let Some(error) = self.game.take_error() {
+            // Our move was rejected and has been taken back, so start over.
+            self.possible_moves = None;
+            self.possible_castling = None;
+            self.selected_square = None;
+            self.promotion_square = None;
+            self.promotion_coordinates = None;
+            self.premoves.clear();
+            self.premove_from = None;
+            self.latest_error = Some(error);
+        }
+
+        self.play_premove();
+        self.autosave();
+
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         let mut canvas =
             graphics::Canvas::from_frame(ctx, graphics::Color::from([0.1, 0.2, 0.3, 1.0]));
-        
-        let screen_coordinates = canvas.screen_coordinates();
-
-        let mut scale = 1_f32;
-        if let Some(screen) = screen_coordinates {
-            loop {
-                let next_size = BOARD_SIZE * (scale + 1.0);
-                if next_size > screen.w || next_size > screen.h {
-                    break;
-                }
-                scale += 1.0;
-            }
-        }
+        // The board is scaled by fractional amounts, which looks jagged without filtering.
+        canvas.set_sampler(graphics::Sampler::linear_clamp());
+
+        let screen = canvas.screen_coordinates().unwrap_or(Rect::new(0.0, 0.0, BOARD_SIZE, BOARD_SIZE));
+        let layout = Layout::new(screen);
+
+        let scale = layout.board.w / BOARD_SIZE;
         self.scale = scale;
         let scale_vec = Vec2::new(scale, scale);
-
-        self.board_start = match screen_coordinates {
-            None => Vec2::new(0.0, 0.0),
-            Some(rect) => {
-                Vec2::new((rect.w - BOARD_SIZE * scale) / 2.0, (rect.h - BOARD_SIZE * scale) / 2.0)
-            }
-        };
+        self.board_start = Vec2::new(layout.board.x, layout.board.y);
 
         let params = DrawParam::new().dest(self.board_start).scale(scale_vec);
         canvas.draw(&self.board, params);
 
-        for (rank_index, row) in self.game.get_pieces().iter().enumerate() {
+        if let Some(Move::Normal { from, to }) = self.game.history().last().map(|played| played.mv) {
+            self.highlight_square(&mut canvas, from, &self.square_fill, LAST_MOVE_COLOR);
+            self.highlight_square(&mut canvas, to, &self.square_fill, LAST_MOVE_COLOR);
+        }
+        if self.game.is_check() {
+            if let Some(king) = self.king_in_turn() {
+                self.highlight_square(&mut canvas, king, &self.square_fill, CHECK_COLOR);
+            }
+        }
+        for premove in &self.premoves {
+            let (from, to) = self.premove_squares(*premove);
+            self.highlight_square(&mut canvas, from, &self.square_fill, PREMOVE_COLOR);
+            self.highlight_square(&mut canvas, to, &self.square_fill, PREMOVE_COLOR);
+        }
+        if let Some(selected) = self.selected_square.or(self.premove_from) {
+            self.highlight_square(&mut canvas, selected, &self.square_outline, SELECTED_COLOR);
+        }
+
+        let pieces = self.game.get_pieces();
+        let now = ctx.time.time_since_start();
+        let last_move = self.game.history().last().map(|played| played.mv);
+        self.animator.update(pieces, last_move, now);
+
+        for (rank_index, row) in pieces.iter().enumerate() {
             for (file_index, piece) in row.iter().enumerate() {
                 let square: Square = (file_index as i32, rank_index as i32).try_into().unwrap();
-                let pos = self.board_start + Vec2::new(file_index as f32 * SQUARE_SIZE * scale, (7 - rank_index) as f32 * SQUARE_SIZE * scale);
+                let pos = self.board_position(file_index as f32, rank_index as f32);
                 let draw_param = DrawParam::new().dest(pos).scale(scale_vec);
                 if let Some(piece) = piece {
-                    let icons = match piece.color {
-                        erikfran_chess::Color::White => &self.white_icons,
-                        erikfran_chess::Color::Black => &self.black_icons,
-                    };
-                    let image = icons.get_image(piece.piece);
-                    canvas.draw(image, draw_param);
+                    // Pieces that are on their way here are drawn by the animation instead.
+                    if !self.animator.is_sliding_to(square) {
+                        let image = self.icons(piece.color).get_image(piece.kind);
+                        canvas.draw(image, draw_param);
+                    }
                 }
-                if let Some(possible_moves) = self.possible_moves {
-                    if possible_moves[square].is_some() && self.game.has_possible_moves() {
+                if let Some(possible_moves) = &self.possible_moves {
+                    if move_to(possible_moves, square).is_some() && self.game.has_possible_moves() {
                         let sin = ((self.frames as f64) / 25.0).sin() + 1.0;
                         let green = (sin * 32.0) as u8 + 192;
                         let radius = (sin * 2.0 + 16.0) as f32;
@@ -173,6 +787,25 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
             }
         }
 
+        // Captured pieces fade out underneath the pieces that slide in to take their place.
+        for animation in self.animator.animations() {
+            if let AnimationKind::FadeOut { at } = animation.kind {
+                let pos = self.board_position(at.file() as f32, at.rank() as f32);
+                let alpha = 1.0 - self.animator.progress(animation, now);
+                let param = DrawParam::new().dest(pos).scale(scale_vec).color(Color::new(1.0, 1.0, 1.0, alpha));
+                canvas.draw(self.icons(animation.piece.color).get_image(animation.piece.kind), param);
+            }
+        }
+        for animation in self.animator.animations() {
+            if let AnimationKind::Slide { from, to } = animation.kind {
+                let progress = self.animator.progress(animation, now);
+                let from_pos = self.board_position(from.file() as f32, from.rank() as f32);
+                let to_pos = self.board_position(to.file() as f32, to.rank() as f32);
+                let param = DrawParam::new().dest(from_pos.lerp(to_pos, progress)).scale(scale_vec);
+                canvas.draw(self.icons(animation.piece.color).get_image(animation.piece.kind), param);
+            }
+        }
+
         if let Some(possible_castling) = &self.possible_castling {
             // TODO implement when backend stops panicking when castling is possible.
             if possible_castling.queenside {
@@ -194,80 +827,44 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
                 (BOARD_SIZE * scale - height) / 2.0,
             );
 
-            let color = self.game.get_piece(promotion_square).map_or(erikfran_chess::Color::White, |piece| piece.color);
-            let piece_icons = match color {
-                erikfran_chess::Color::White => &self.white_icons,
-                erikfran_chess::Color::Black => &self.black_icons,
-            };
+            let color = self.game.get_piece(promotion_square).map_or(bridge::Color::White, |piece| piece.color);
+            let piece_icons = self.icons(color);
 
             let mut mesh = MeshBuilder::new();
             mesh.rectangle(
                 graphics::DrawMode::Fill(FillOptions::default()),
                 Rect::new(0.0, 0.0, width, height),
-                if color == erikfran_chess::Color::White { Color::BLACK } else { Color::WHITE }
+                if color == bridge::Color::White { Color::BLACK } else { Color::WHITE }
             ).expect("Failed to draw rectangle.");
             let mesh = Mesh::from_data(ctx, mesh.build());
             canvas.draw(&mesh, pos);
 
             let queen_pos = pos + Vec2::new(4.0 * scale, 4.0 * scale);
             let params = DrawParam::new().dest(queen_pos).scale(scale_vec);
-            canvas.draw(piece_icons.get_image(PieceTypes::Queen), params);
+            canvas.draw(piece_icons.get_image(PieceType::Queen), params);
 
             let bishop_pos = pos + Vec2::new(SQUARE_SIZE * scale + 8.0 * scale, 4.0 * scale);
             let params = DrawParam::new().dest(bishop_pos).scale(scale_vec);
-            canvas.draw(piece_icons.get_image(PieceTypes::Bishop), params);
+            canvas.draw(piece_icons.get_image(PieceType::Bishop), params);
 
             let rook_pos = pos + Vec2::new(2.0 * SQUARE_SIZE * scale + 16.0 * scale, 4.0 * scale);
             let params = DrawParam::new().dest(rook_pos).scale(scale_vec);
-            canvas.draw(piece_icons.get_image(PieceTypes::Rook), params);
+            canvas.draw(piece_icons.get_image(PieceType::Rook), params);
             
             let knight_pos = pos + Vec2::new(3.0 * SQUARE_SIZE * scale + 24.0 * scale, 4.0 * scale);
             let params = DrawParam::new().dest(knight_pos).scale(scale_vec);
-            canvas.draw(piece_icons.get_image(PieceTypes::Knight), params);
+            canvas.draw(piece_icons.get_image(PieceType::Knight), params);
 
             self.promotion_coordinates = Some(PromotionCoordinates { queen_pos, bishop_pos, rook_pos, knight_pos });
         }
 
-        if self.game.is_check() {
-            let mut text = Text::new("check");
-            text.set_scale(16.0 * scale);
-            let sin = ((self.frames as f64) / 25.0).sin() + 1.0;
-            let color_value = (sin * 128.0) as u8;
-            let color = Color::from_rgb(255, color_value, color_value);
-            let text_size = text.measure(ctx).expect("measure");
-            let pos = Vec2::new(self.board_start.x + (BOARD_SIZE * scale - text_size.x) / 2.0, self.board_start.y - (20.0 * scale));
-            let param = DrawParam::new().dest(pos).color(color);
-            canvas.draw(&text, param);
-
-        }
-
-        if let Some(error) = &self.latest_error {
-            let mut text = Text::new(error.to_string());
-            text.set_scale(16.0 * self.scale);
-            let text_size = text.measure(ctx).unwrap();
-            let text_pos = match screen_coordinates {
-                None => Vec2::new(0.0, 0.0),
-                Some(screen) => {
-                    let board_end = self.board_start.y + BOARD_SIZE * scale;
-                    Vec2::new(
-                        (screen.w - text_size.x) / 2.0,
-                        board_end + (screen.h - board_end - text_size.y) / 2.0
-                    )
-                }
-            };
-            canvas.draw(&text, text_pos);
-        }
-
-        let color_text = if self.game.current_turn() == erikfran_chess::Color::White { "white" } else { "black" };
-        let mut color_text = Text::new(format!("{color_text}'s turn"));
-        color_text.set_scale(16.0 * self.scale);
-        canvas.draw(&color_text, Vec2::new(20.0, 40.0));
+        self.draw_panel(ctx, &mut canvas, &layout)?;
 
         canvas.finish(ctx)?;
 
         self.frames += 1;
-        if (self.frames % 100) == 0 {
-            println!("FPS: {:.2}", ctx.time.fps());
+        if self.frames.is_multiple_of(100) {
+            log::debug!(target: logging::RENDERING, "FPS: {:.2}", ctx.time.fps());
         }
 
         Ok(())
@@ -280,7 +877,16 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
         x: f32,
         y: f32,
     ) -> GameResult {
+        if self.locked {
+            return Ok(());
+        }
         if !self.game.can_play_right_now() {
+            if button == MouseButton::Right {
+                self.premoves.clear();
+                self.premove_from = None;
+            } else if let Some(square) = self.square_at(x, y) {
+                self.click_premove(square);
+            }
             return Ok(());
         }
 
@@ -291,23 +897,23 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
                 && y > piece_pos.y && y < piece_pos.y + size
             };
             let mut promoted = false;
-            let mut promote = |piece_type: PieceTypes| {
-                println!("promoting to {piece_type:?}");
+            let mut promote = |piece_type: PieceType| {
+                log::debug!(target: logging::INPUT, "Promoting to {piece_type:?}");
                 self.game.promote(promotion_square, piece_type);
                 promoted = true;
             };
 
             if clicked_inside(coords.queen_pos) {
-                promote(PieceTypes::Queen);
+                promote(PieceType::Queen);
             }
             if clicked_inside(coords.bishop_pos) {
-                promote(PieceTypes::Bishop);
+                promote(PieceType::Bishop);
             }
             if clicked_inside(coords.rook_pos) {
-                promote(PieceTypes::Rook);
+                promote(PieceType::Rook);
             }
             if clicked_inside(coords.knight_pos) {
-                promote(PieceTypes::Knight);
+                promote(PieceType::Knight);
             }
 
             if promoted {
@@ -319,45 +925,31 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
             return Ok(());
         }
 
-        let rel_x = x - self.board_start.x;
-        let rel_y = y - self.board_start.y;
-        let file = (rel_x / SQUARE_SIZE / self.scale) as i32;
-        let rank = 7 - (rel_y / SQUARE_SIZE / self.scale) as i32;
-        let square: Option<Square> = (file, rank).try_into().ok();
-        println!("Mouse button pressed: {button:?}, x: {file}, y: {rank}");
+        let square = self.square_at(x, y);
+        log::trace!(target: logging::INPUT, "Mouse button pressed: {button:?}, square: {square:?}");
 
         if let Some(square) = square {
-            println!("Clicked {square:?}");
-            if let Some(mv) = self.possible_moves.and_then(|moves| moves[square]) {
-                let result = self.game.perform_move(mv);
-                match result {
-                    Ok(_) => {
-                        self.possible_moves = None;
-                        self.possible_castling = None;
-                        self.latest_error = None;
-                        match mv {
-                            Move::Normal { from: _, to } => {
-                                let is_pawn: bool = self.game.get_piece(to).map_or(false, |piece| match piece.piece { PieceTypes::Pawn(_) => true, _ => false });
-                                if (to.rank == Rank::R1 || to.rank == Rank::R8) && is_pawn {
-                                    // Promotion
-                                    self.promotion_square = Some(to);
-                                }
-                            },
-                            _ => {},
-                        }
-                    },
-                    Err(err) => {
-                        self.latest_error = Some(err);
-                    },
-                }
+            log::debug!(target: logging::INPUT, "Clicked {square:?}");
+            let turn = self.game.current_turn();
+            let is_ours = |at: Square| self.game.get_piece(at).is_some_and(|piece| piece.color == turn);
+            // When the possible moves are only hints, a move they left out is still sent
+            // for whoever decides what is legal to judge.
+            let unhinted_from = self.selected_square
+                .filter(|&from| self.game.moves_are_hints() && is_ours(from) && !is_ours(square));
+            if let Some(mv) = self.possible_moves.as_ref().and_then(|moves| move_to(moves, square)) {
+                self.make_move(mv);
+            } else if let Some(from) = unhinted_from {
+                self.make_move(Move::Normal { from, to: square });
             } else {
                 let possible_moves = self.game.possible_moves(square);
                 match possible_moves {
                     Ok(possible_moves) => {
-                        let (board_move, castling_moves) = possible_moves;
-                        self.possible_moves = Some(board_move);
+                        let (normal_moves, castling_moves) = possible_moves.into_iter()
+                            .partition(|mv| matches!(mv, Move::Normal { .. }));
+                        self.possible_moves = Some(normal_moves);
+                        self.selected_square = Some(square);
                         
-                        let color = self.game.get_piece(square).map_or(erikfran_chess::Color::White, |piece| piece.color);
+                        let color = self.game.get_piece(square).map_or(bridge::Color::White, |piece| piece.color);
                         let mut possible_castling = CastlingPossibility {
                             _color: color,
                             kingside: false,
@@ -376,7 +968,7 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
                         self.possible_castling = Some(possible_castling);
                     },
                     Err(err) => {
-                        self.latest_error = Some(err);
+                        self.latest_error = Some(err.to_string());
                     },
                 }
             }
@@ -386,4 +978,75 @@ impl<T: bridge::ChessGame> View for BoardView<T> {
         Ok(())
     }
 
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult {
+        if self.chat_draft.is_some() {
+            if let Some(keycode) = input.keycode {
+                self.chat_key(keycode);
+            }
+            return Ok(());
+        }
+        match input.keycode {
+            Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) if self.game.supports_chat() => {
+                self.chat_draft = Some(String::new());
+            }
+            Some(KeyCode::T) => self.next_theme(ctx),
+            Some(KeyCode::S) | Some(KeyCode::Escape) => {
+                if let Some(settings) = &self.settings {
+                    let view = SettingsView::new(ctx, settings.clone());
+                    self.transition = Some(Transition::Push(Box::new(view)));
+                }
+            }
+            Some(KeyCode::I) => self.transition = Some(Transition::Push(Box::new(TrafficView::new()))),
+            Some(KeyCode::U) | Some(KeyCode::Back) => self.game.request_takeback(),
+            // Once the game is over, R offers a rematch with the colors swapped and shift+R one
+            // with the same colors. Without an opponent to accept it, we ask first instead.
+            Some(KeyCode::R) if !self.locked && self.game.is_over() => {
+                if self.game.player_color().is_some() {
+                    self.game.offer_rematch(!input.mods.contains(KeyMods::SHIFT));
+                } else {
+                    self.confirming_new_game = true;
+                }
+            }
+            Some(KeyCode::D) if !self.locked && !self.game.is_over() => self.game.offer_draw(),
+            Some(KeyCode::Q) if !self.locked && !self.game.is_over() && self.game.player_color().is_some() => {
+                self.confirming_resign = true;
+            }
+            // A new game is confirmed or a rematch offer answered first since it replaces the
+            // game anyway.
+            Some(KeyCode::Y) if self.confirming_new_game => {
+                self.confirming_new_game = false;
+                self.game.offer_rematch(false);
+            }
+            Some(KeyCode::N) if self.confirming_new_game => self.confirming_new_game = false,
+            Some(KeyCode::Y) if matches!(self.game.rematch_state(), RematchState::Offered { .. }) => self.game.answer_rematch(true),
+            Some(KeyCode::N) if matches!(self.game.rematch_state(), RematchState::Offered { .. }) => self.game.answer_rematch(false),
+            Some(KeyCode::Y) if self.confirming_resign => {
+                self.confirming_resign = false;
+                self.game.resign();
+            }
+            Some(KeyCode::N) if self.confirming_resign => self.confirming_resign = false,
+            Some(KeyCode::Y) => self.game.answer_takeback(true),
+            Some(KeyCode::N) => self.game.answer_takeback(false),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+        if let Some(draft) = &mut self.chat_draft {
+            if !character.is_control() && draft.chars().count() < extension::MAX_CHAT_LENGTH {
+                draft.push(character);
+            }
+        }
+        Ok(())
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        self.transition.take()
+    }
+}
+
+/// The move in `moves` that goes to `to`, if any.
+fn move_to(moves: &[Move], to: Square) -> Option<Move> {
+    moves.iter().copied().find(|mv| matches!(mv, Move::Normal { to: target, .. } if *target == to))
 }
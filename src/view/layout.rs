@@ -0,0 +1,73 @@
+//! Placement of the board and the side panel for the current window shape.
+
+use ggez::graphics::Rect;
+
+/// The space around the board and panel relative to the smallest window side.
+const MARGIN_FRACTION: f32 = 0.03;
+/// The part of the window the panel gets along the window's longest side.
+const PANEL_FRACTION: f32 = 0.3;
+const MIN_PANEL_SIZE: f32 = 180.0;
+
+pub struct Layout {
+    /// Where the board goes. This is always a square.
+    pub board: Rect,
+    /// Where the move list, clocks and status text go. In landscape windows this is to the
+    /// right of the board and in portrait windows below it.
+    pub panel: Rect,
+    /// A text size that fits the size of the board.
+    pub text_scale: f32,
+}
+
+impl Layout {
+    pub fn new(screen: Rect) -> Self {
+        let margin = screen.w.min(screen.h) * MARGIN_FRACTION;
+
+        let (board, panel) = if screen.w >= screen.h {
+            let panel_width = (screen.w * PANEL_FRACTION).max(MIN_PANEL_SIZE).min(screen.w / 2.0);
+            let available_width = screen.w - panel_width - 3.0 * margin;
+            let board_size = available_width.min(screen.h - 2.0 * margin).max(0.0);
+            let board = Rect::new(
+                screen.x + margin + (available_width - board_size) / 2.0,
+                screen.y + (screen.h - board_size) / 2.0,
+                board_size,
+                board_size,
+            );
+            let panel = Rect::new(
+                board.right() + margin,
+                screen.y + margin,
+                (screen.right() - board.right() - 2.0 * margin).max(0.0),
+                screen.h - 2.0 * margin,
+            );
+            (board, panel)
+        } else {
+            let panel_height = (screen.h * PANEL_FRACTION).max(MIN_PANEL_SIZE).min(screen.h / 2.0);
+            let available_height = screen.h - panel_height - 3.0 * margin;
+            let board_size = available_height.min(screen.w - 2.0 * margin).max(0.0);
+            let board = Rect::new(
+                screen.x + (screen.w - board_size) / 2.0,
+                screen.y + margin + (available_height - board_size) / 2.0,
+                board_size,
+                board_size,
+            );
+            let panel = Rect::new(
+                screen.x + margin,
+                board.bottom() + margin,
+                screen.w - 2.0 * margin,
+                (screen.bottom() - board.bottom() - 2.0 * margin).max(0.0),
+            );
+            (board, panel)
+        };
+
+        Self {
+            board,
+            panel,
+            text_scale: (board.w / 30.0).clamp(14.0, 28.0),
+        }
+    }
+
+    /// Whether the panel is wide enough to put the move list next to the status text
+    /// rather than below it.
+    pub fn panel_has_columns(&self) -> bool {
+        self.panel.w > self.panel.h * 1.5
+    }
+}
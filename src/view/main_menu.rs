@@ -2,6 +2,7 @@ use ggez::{Context, GameResult};
 use ggez::event::MouseButton;
 use ggez::glam::Vec2;
 use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, FillOptions, Mesh, MeshBuilder, Rect, Text};
+use crate::local_game::LocalGame;
 use crate::view::board_view::BoardView;
 use crate::view::{MainState, View};
 
@@ -112,8 +113,7 @@ impl<'a> View for MainMenu<'a> {
 
         if self.single_player_button.is_inside(x, y) {
             // Start single player game
-            let game = erikfran_chess::Game::new();
-            let view = BoardView::new(ctx, game).unwrap();
+            let view = BoardView::new(ctx, LocalGame::new()).unwrap();
 
             self.main_state.set_view(view);
         }
@@ -0,0 +1,123 @@
+use std::time::Duration;
+use ggez::{Context, GameResult};
+use ggez::event::MouseButton;
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use crate::backend::Backend;
+use crate::bridge::PlayedMove;
+use crate::replay_game::ReplayGame;
+use crate::settings::SharedSettings;
+use crate::view::{Transition, View};
+use crate::view::board_view::BoardView;
+
+/// The times between moves when playing automatically.
+const SPEEDS: [Duration; 5] = [
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(4),
+];
+const DEFAULT_SPEED: usize = 2;
+
+/// Steps through a recorded game. The board itself is drawn by a locked [`BoardView`].
+pub struct ReplayView {
+    board_view: BoardView<ReplayGame>,
+    playing: bool,
+    speed_index: usize,
+    /// When the next move is made while playing, as time since the start of the app.
+    next_step: Duration,
+}
+
+impl ReplayView {
+    /// Replay `moves`, following the rules of `backend`.
+    pub fn new(ctx: &mut Context, moves: Vec<PlayedMove>, backend: Backend) -> GameResult<Self> {
+        let board_view = BoardView::new(ctx, ReplayGame::new(moves).with_backend(backend))?.with_input_locked();
+        Ok(Self {
+            board_view,
+            playing: false,
+            speed_index: DEFAULT_SPEED,
+            next_step: Duration::ZERO,
+        })
+    }
+
+    /// Follow the settings, like [`BoardView::with_settings`].
+    pub fn with_settings(mut self, ctx: &Context, settings: SharedSettings) -> Self {
+        self.board_view = self.board_view.with_settings(ctx, settings);
+        self
+    }
+
+    fn go_to(&mut self, position: usize) {
+        self.playing = false;
+        self.board_view.game_mut().go_to(position);
+    }
+
+    fn status(&self) -> String {
+        let game = self.board_view.game();
+        let state = if self.playing {
+            format!("playing, {:.2} s per move", SPEEDS[self.speed_index].as_secs_f32())
+        } else {
+            String::from("paused")
+        };
+        format!(
+            "Move {} of {} ({state}). Left/right to step, home/end to jump, space to play, +/- to change speed.",
+            game.position(),
+            game.move_count(),
+        )
+    }
+}
+
+impl View for ReplayView {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        let now = ctx.time.time_since_start();
+        if self.playing && now >= self.next_step {
+            let stepped = self.board_view.game_mut().step_forward();
+            self.next_step = now + SPEEDS[self.speed_index];
+            if !stepped || self.board_view.game().is_at_end() {
+                self.playing = false;
+            }
+        }
+        let status = self.status();
+        self.board_view.set_status(Some(status));
+        self.board_view.update(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.board_view.draw(ctx)
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+        self.board_view.mouse_button_down_event(ctx, button, x, y)
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeated: bool) -> GameResult {
+        let position = self.board_view.game().position();
+        match input.keycode {
+            Some(KeyCode::Left) => {
+                self.playing = false;
+                self.board_view.game_mut().step_back();
+            }
+            Some(KeyCode::Right) => self.go_to(position + 1),
+            Some(KeyCode::Home) => self.go_to(0),
+            Some(KeyCode::End) => self.go_to(usize::MAX),
+            Some(KeyCode::Space) => {
+                if self.board_view.game().is_at_end() {
+                    self.board_view.game_mut().go_to(0);
+                }
+                self.playing = !self.playing;
+                self.next_step = ctx.time.time_since_start() + SPEEDS[self.speed_index];
+            }
+            Some(KeyCode::Plus) | Some(KeyCode::Equals) | Some(KeyCode::NumpadAdd) => {
+                self.speed_index = self.speed_index.saturating_sub(1);
+            }
+            Some(KeyCode::Minus) | Some(KeyCode::NumpadSubtract) => {
+                self.speed_index = (self.speed_index + 1).min(SPEEDS.len() - 1);
+            }
+            _ => return self.board_view.key_down_event(ctx, input, repeated),
+        }
+        Ok(())
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        self.board_view.transition()
+    }
+}
@@ -0,0 +1,242 @@
+use ggez::{Context, GameResult};
+use ggez::event::MouseButton;
+use ggez::glam::Vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Rect, Text};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use crate::settings::{self, Orientation, SharedSettings};
+use crate::theme;
+use crate::view::{Transition, View};
+
+/// The animation times that can be picked, in milliseconds.
+const ANIMATION_PRESETS: [u64; 5] = [0, 100, 200, 400, 800];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Theme,
+    Orientation,
+    Animation,
+    Port,
+    TrafficLog,
+    Tls,
+}
+
+const ROWS: [Row; 6] = [Row::Theme, Row::Orientation, Row::Animation, Row::Port, Row::TrafficLog, Row::Tls];
+
+/// Lets the user change the settings. Changes are saved right away, except for a port being
+/// typed, which is saved once it is entered or another setting is picked.
+pub struct SettingsView {
+    settings: SharedSettings,
+    theme_names: Vec<String>,
+    selected: usize,
+    /// The port being typed, if one is.
+    port_draft: Option<String>,
+    /// Why the last port that was typed wasn't saved.
+    port_error: Option<String>,
+    /// Where each row was drawn last frame, used to handle clicks.
+    row_rects: Vec<Rect>,
+    close: bool,
+}
+
+impl SettingsView {
+    pub fn new(ctx: &Context, settings: SharedSettings) -> Self {
+        let theme_names = theme::load_themes(ctx).into_iter().map(|theme| theme.name).collect();
+        Self {
+            settings,
+            theme_names,
+            selected: 0,
+            port_draft: None,
+            port_error: None,
+            row_rects: vec![],
+            close: false,
+        }
+    }
+
+    fn row_text(&self, row: Row) -> String {
+        let settings = self.settings.borrow();
+        let settings = settings.get();
+        match row {
+            Row::Theme => {
+                let theme = settings.theme.as_deref().or(self.theme_names.first().map(String::as_str)).unwrap_or("");
+                format!("Theme: {theme}")
+            }
+            Row::Orientation => format!("Board orientation: {}", settings.orientation.name()),
+            Row::Animation => format!("Animation time: {} ms", settings.animation_ms),
+            Row::Port => match &self.port_draft {
+                Some(draft) => format!("Port: {draft}_ (Enter to save)"),
+                None => format!("Port: {}", settings.port),
+            },
+            Row::TrafficLog => {
+                let state = if settings.traffic_log { "on" } else { "off" };
+                format!("Log network traffic: {state} (from next start)")
+            }
+            Row::Tls => {
+                let state = if settings.tls { "on" } else { "off" };
+                format!("Encrypt connections: {state} (from next game)")
+            }
+        }
+    }
+
+    /// Save the port being typed if it is valid, or keep the old one if it isn't.
+    fn commit_port(&mut self) {
+        let Some(draft) = self.port_draft.take() else {
+            return;
+        };
+        match settings::parse_port(&draft) {
+            Ok(port) => {
+                self.settings.borrow_mut().change(|settings| settings.port = port);
+                self.port_error = None;
+            }
+            Err(err) => self.port_error = Some(err),
+        }
+    }
+
+    /// Change the port being typed with `edit`, starting from the saved port.
+    fn edit_port(&mut self, edit: impl FnOnce(&mut String)) {
+        let port = self.settings.borrow().get().port;
+        let draft = self.port_draft.get_or_insert_with(|| port.to_string());
+        edit(draft);
+    }
+
+    /// Step the setting on `row` to its next or previous value.
+    fn step(&mut self, row: Row, forward: bool) {
+        self.commit_port();
+        let step = |index: usize, len: usize| if forward { (index + 1) % len } else { (index + len - 1) % len };
+        let mut settings = self.settings.borrow_mut();
+        let current = settings.get().clone();
+        match row {
+            Row::Theme => {
+                if self.theme_names.is_empty() {
+                    return;
+                }
+                let index = current.theme.as_ref()
+                    .and_then(|name| self.theme_names.iter().position(|theme| theme == name))
+                    .unwrap_or(0);
+                let theme = self.theme_names[step(index, self.theme_names.len())].clone();
+                settings.change(|settings| settings.theme = Some(theme.clone()));
+            }
+            Row::Orientation => {
+                let index = Orientation::ALL.iter().position(|orientation| *orientation == current.orientation).unwrap_or(0);
+                let orientation = Orientation::ALL[step(index, Orientation::ALL.len())];
+                settings.change(|settings| settings.orientation = orientation);
+            }
+            Row::Animation => {
+                let index = ANIMATION_PRESETS.iter().position(|ms| *ms >= current.animation_ms).unwrap_or(0);
+                let animation_ms = ANIMATION_PRESETS[step(index, ANIMATION_PRESETS.len())];
+                settings.change(|settings| settings.animation_ms = animation_ms);
+            }
+            Row::Port => {
+                // Port 0 isn't a valid port, so step over it.
+                let port = match (forward, current.port) {
+                    (true, u16::MAX) => 1,
+                    (true, port) => port + 1,
+                    (false, 0 | 1) => u16::MAX,
+                    (false, port) => port - 1,
+                };
+                settings.change(|settings| settings.port = port);
+                self.port_error = None;
+            }
+            Row::TrafficLog => {
+                settings.change(|settings| settings.traffic_log = !current.traffic_log);
+            }
+            Row::Tls => {
+                settings.change(|settings| settings.tls = !current.tls);
+            }
+        }
+    }
+}
+
+impl View for SettingsView {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = Canvas::from_frame(ctx, Color::from([0.1, 0.2, 0.3, 1.0]));
+
+        let mut title = Text::new("Settings");
+        title.set_scale(40.0);
+        canvas.draw(&title, Vec2::new(30.0, 30.0));
+
+        self.row_rects.clear();
+        let mut y = 100.0;
+        for (index, row) in ROWS.into_iter().enumerate() {
+            let mut text = Text::new(self.row_text(row));
+            text.set_scale(28.0);
+            let size = text.measure(ctx)?;
+            let color = if index == self.selected { Color::from_rgb(255, 220, 100) } else { Color::WHITE };
+            canvas.draw(&text, DrawParam::new().dest(Vec2::new(50.0, y)).color(color));
+            self.row_rects.push(Rect::new(50.0, y, size.x, size.y));
+            y += size.y + 16.0;
+        }
+
+        let mut help = Text::new("Up/down to pick a setting, left/right or click to change it. Type digits and press Enter to set the port. Escape to go back.");
+        help.set_scale(18.0);
+        if let Some(screen) = canvas.screen_coordinates() {
+            help.set_bounds(Vec2::new(screen.w - 60.0, f32::INFINITY));
+            help.set_wrap(true);
+        }
+        canvas.draw(&help, DrawParam::new().dest(Vec2::new(30.0, y + 20.0)).color(Color::from_rgb(180, 180, 180)));
+
+        if let Some(error) = &self.port_error {
+            let mut text = Text::new(error.as_str());
+            text.set_scale(20.0);
+            canvas.draw(&text, DrawParam::new().dest(Vec2::new(30.0, y + 80.0)).color(Color::from_rgb(255, 120, 120)));
+        }
+
+        canvas.finish(ctx)
+    }
+
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+        if let Some(index) = self.row_rects.iter().position(|rect| rect.contains(Vec2::new(x, y))) {
+            self.selected = index;
+            self.step(ROWS[index], button != MouseButton::Right);
+        }
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::Escape) | Some(KeyCode::S) => {
+                self.commit_port();
+                self.close = true;
+            }
+            Some(KeyCode::Up) => {
+                self.commit_port();
+                self.selected = (self.selected + ROWS.len() - 1) % ROWS.len();
+            }
+            Some(KeyCode::Down) => {
+                self.commit_port();
+                self.selected = (self.selected + 1) % ROWS.len();
+            }
+            Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) if self.port_draft.is_some() => self.commit_port(),
+            Some(KeyCode::Left) => self.step(ROWS[self.selected], false),
+            Some(KeyCode::Right) | Some(KeyCode::Return) => self.step(ROWS[self.selected], true),
+            Some(KeyCode::Back) if ROWS[self.selected] == Row::Port => self.edit_port(|draft| {
+                draft.pop();
+            }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+        if ROWS[self.selected] != Row::Port {
+            return Ok(());
+        }
+        if character.is_ascii_digit() {
+            // Longer numbers can't be ports, which are checked when saved.
+            self.edit_port(|draft| if draft.len() < 5 {
+                draft.push(character);
+            });
+        }
+        Ok(())
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        if self.close {
+            self.close = false;
+            return Some(Transition::Pop);
+        }
+        None
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs;
+use std::path::PathBuf;
+use ggez::{Context, GameResult};
+use ggez::glam::Vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Text};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use crate::settings::config_dir;
+use crate::tournament::{Format, Tournament, TournamentHost};
+use crate::view::View;
+
+const TEXT_SCALE: f32 = 18.0;
+const LINE_HEIGHT: f32 = 24.0;
+const STANDINGS_FILE: &str = "tournament_standings.csv";
+const GAMES_FILE: &str = "tournament_games.pgn";
+const EVENT_NAME: &str = "alvinw-chess-gui tournament";
+const GREY: Color = Color::new(0.7, 0.7, 0.7, 1.0);
+
+/// Runs a [`TournamentHost`] and shows who has joined, the games of the current round and
+/// the standings.
+pub struct TournamentView {
+    host: TournamentHost,
+    /// The result of the last thing the user did, like exporting.
+    message: Option<String>,
+}
+
+impl TournamentView {
+    pub fn new(host: TournamentHost) -> Self {
+        Self { host, message: None }
+    }
+
+    /// Write the standings as CSV and the finished games as PGN next to the settings.
+    fn export(&self, tournament: &Tournament) -> Result<String, String> {
+        let dir = config_dir().unwrap_or_else(|| PathBuf::from("."));
+        fs::create_dir_all(&dir).map_err(|err| format!("Failed to create {}: {err}", dir.display()))?;
+        let standings_path = dir.join(STANDINGS_FILE);
+        let games_path = dir.join(GAMES_FILE);
+        fs::write(&standings_path, tournament.standings_csv())
+            .map_err(|err| format!("Failed to write {}: {err}", standings_path.display()))?;
+        fs::write(&games_path, tournament.pgn(EVENT_NAME, self.host.backend()))
+            .map_err(|err| format!("Failed to write {}: {err}", games_path.display()))?;
+        Ok(format!("Exported to {} and {}", standings_path.display(), games_path.display()))
+    }
+
+    /// The lines shown before the tournament has started.
+    fn registration_lines(&self) -> Vec<(String, Color)> {
+        let mut lines = vec![(
+            format!("Waiting for participants on port {}. Press S to start.", self.host.local_port()),
+            Color::WHITE,
+        )];
+        lines.push((String::from("Only clients that support the rematch extension can play."), GREY));
+        if self.host.participants().is_empty() {
+            lines.push((String::from("Nobody has joined yet."), GREY));
+        }
+        for participant in self.host.participants() {
+            let color = if participant.can_play() { Color::WHITE } else { GREY };
+            lines.push((format!("{}: {}", participant.name, participant.status()), color));
+        }
+        lines
+    }
+
+    /// The lines shown once the tournament has started.
+    fn tournament_lines(&self, tournament: &Tournament) -> Vec<(String, Color)> {
+        let mut lines = vec![];
+        let players = tournament.players();
+        let name = |player: usize| {
+            let connected = self.host.participants()[player].is_connected();
+            if connected { players[player].clone() } else { format!("{} (left)", players[player]) }
+        };
+
+        if tournament.is_finished() {
+            lines.push((String::from("The tournament is over. Press E to export the results."), Color::WHITE));
+        } else if let Some(round) = tournament.rounds().last() {
+            lines.push((format!("Round {} of {}", tournament.rounds().len(), tournament.round_count()), Color::WHITE));
+            for (pairing_index, pairing) in round.pairings.iter().enumerate() {
+                let state = match (pairing.result, self.host.live_plies(pairing_index)) {
+                    (Some(result), _) => format!("{} ({})", result.outcome.pgn(), result.termination.description()),
+                    (None, Some(plies)) => format!("playing, move {}", plies / 2 + 1),
+                    (None, None) => String::from("waiting"),
+                };
+                lines.push((format!("  {} - {}: {state}", name(pairing.white), name(pairing.black)), GREY));
+            }
+            if let Some(bye) = round.bye {
+                lines.push((format!("  {} sits out", name(bye)), GREY));
+            }
+        }
+
+        lines.push((String::new(), Color::WHITE));
+        lines.push((String::from("Standings (points, wins, draws, losses)"), Color::WHITE));
+        for (rank, standing) in tournament.standings().iter().enumerate() {
+            lines.push((
+                format!(
+                    "  {}. {}: {}, {} / {} / {}",
+                    rank + 1,
+                    name(standing.player),
+                    standing.points,
+                    standing.wins,
+                    standing.draws,
+                    standing.losses,
+                ),
+                GREY,
+            ));
+        }
+        lines
+    }
+}
+
+impl View for TournamentView {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        self.host.update();
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = Canvas::from_frame(ctx, Color::from([0.05, 0.05, 0.1, 1.0]));
+
+        let format = match self.host.format() {
+            Format::RoundRobin => String::from("round robin"),
+            Format::Swiss { rounds } => format!("Swiss, {rounds} rounds"),
+        };
+        let mut title = Text::new(format!("Tournament ({format}), E to export"));
+        title.set_scale(24.0);
+        canvas.draw(&title, Vec2::new(20.0, 20.0));
+
+        let mut lines = match self.host.tournament() {
+            None => self.registration_lines(),
+            Some(tournament) => self.tournament_lines(tournament),
+        };
+        if let Some(message) = &self.message {
+            lines.insert(0, (message.clone(), Color::from_rgb(255, 220, 160)));
+        }
+
+        let mut y = 60.0;
+        for (line, color) in lines {
+            let mut text = Text::new(line);
+            text.set_scale(TEXT_SCALE);
+            canvas.draw(&text, DrawParam::new().dest(Vec2::new(20.0, y)).color(color));
+            y += LINE_HEIGHT;
+        }
+
+        canvas.finish(ctx)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::S) => {
+                self.message = self.host.start().err();
+            }
+            Some(KeyCode::E) => {
+                self.message = Some(match self.host.tournament() {
+                    Some(tournament) => self.export(tournament).unwrap_or_else(|err| err),
+                    None => String::from("The tournament hasn't started yet."),
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
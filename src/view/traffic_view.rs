@@ -0,0 +1,90 @@
+use ggez::{Context, GameResult};
+use ggez::glam::Vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Rect, Text};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use crate::traffic::{self, Direction};
+use crate::view::{Transition, View};
+
+const TEXT_SCALE: f32 = 16.0;
+
+/// Shows the latest network messages, newest at the bottom. The game keeps running
+/// underneath, so new messages show up as they arrive.
+pub struct TrafficView {
+    /// How many of the newest messages are scrolled past.
+    scroll: usize,
+    close: bool,
+}
+
+impl TrafficView {
+    pub fn new() -> Self {
+        Self { scroll: 0, close: false }
+    }
+}
+
+impl Default for TrafficView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl View for TrafficView {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = Canvas::from_frame(ctx, Color::from([0.05, 0.05, 0.1, 1.0]));
+        let screen = canvas.screen_coordinates().unwrap_or(Rect::new(0.0, 0.0, 800.0, 600.0));
+
+        let mut title = Text::new("Network traffic (up/down to scroll, escape to go back)");
+        title.set_scale(24.0);
+        canvas.draw(&title, Vec2::new(20.0, 20.0));
+
+        let entries = traffic::recent();
+        if entries.is_empty() {
+            let mut text = Text::new("No messages have been sent or received.");
+            text.set_scale(TEXT_SCALE);
+            canvas.draw(&text, DrawParam::new().dest(Vec2::new(20.0, 60.0)).color(Color::from_rgb(180, 180, 180)));
+            return canvas.finish(ctx);
+        }
+
+        // Lay out from the bottom up so that the newest message is always visible.
+        self.scroll = self.scroll.min(entries.len() - 1);
+        let mut y = screen.h - 20.0;
+        for entry in entries.iter().rev().skip(self.scroll) {
+            let mut text = Text::new(entry.line());
+            text.set_scale(TEXT_SCALE);
+            text.set_bounds(Vec2::new(screen.w - 40.0, f32::INFINITY));
+            text.set_wrap(true);
+            y -= text.measure(ctx)?.y + 4.0;
+            if y < 60.0 {
+                break;
+            }
+            let color = match entry.direction {
+                Direction::Sent => Color::from_rgb(160, 220, 255),
+                Direction::Received => Color::from_rgb(255, 220, 160),
+            };
+            canvas.draw(&text, DrawParam::new().dest(Vec2::new(20.0, y)).color(color));
+        }
+
+        canvas.finish(ctx)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::Escape) | Some(KeyCode::I) => self.close = true,
+            Some(KeyCode::Up) => self.scroll += 1,
+            Some(KeyCode::Down) => self.scroll = self.scroll.saturating_sub(1),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn transition(&mut self) -> Option<Transition> {
+        if self.close {
+            self.close = false;
+            return Some(Transition::Pop);
+        }
+        None
+    }
+}
@@ -0,0 +1,43 @@
+//! Parsing the address typed when joining a game.
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use alvinw_chess_gui::client::parse_address;
+
+const DEFAULT_PORT: u16 = 8384;
+
+fn parse(input: &str) -> Vec<SocketAddr> {
+    parse_address(input, DEFAULT_PORT).unwrap_or_else(|err| panic!("{input}: {err}"))
+}
+
+#[test]
+fn ipv4_with_and_without_port() {
+    assert_eq!(parse("192.168.1.5"), vec![SocketAddr::from((Ipv4Addr::new(192, 168, 1, 5), DEFAULT_PORT))]);
+    assert_eq!(parse("192.168.1.5:9000"), vec![SocketAddr::from((Ipv4Addr::new(192, 168, 1, 5), 9000))]);
+}
+
+#[test]
+fn ipv6_with_and_without_port() {
+    let expected = SocketAddr::from((Ipv6Addr::LOCALHOST, DEFAULT_PORT));
+    assert_eq!(parse("::1"), vec![expected]);
+    assert_eq!(parse("[::1]"), vec![expected]);
+    assert_eq!(parse("[::1]:9000"), vec![SocketAddr::from((Ipv6Addr::LOCALHOST, 9000))]);
+}
+
+#[test]
+fn hostname_is_looked_up() {
+    let addrs = parse("localhost:9000");
+    assert!(!addrs.is_empty());
+    assert!(addrs.iter().all(|addr| addr.ip().is_loopback() && addr.port() == 9000), "{addrs:?}");
+
+    let addrs = parse(" localhost ");
+    assert!(addrs.iter().all(|addr| addr.port() == DEFAULT_PORT), "{addrs:?}");
+}
+
+#[test]
+fn invalid_addresses_are_errors() {
+    assert!(parse_address("", DEFAULT_PORT).is_err());
+    assert!(parse_address("localhost:chess", DEFAULT_PORT).is_err());
+    assert!(parse_address("[::1", DEFAULT_PORT).is_err());
+    assert!(parse_address("localhost:99999", DEFAULT_PORT).is_err());
+}
@@ -0,0 +1,95 @@
+//! The bridge's own chess types and choosing a backend.
+
+mod common;
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::bridge::{CastlingSide, ChessGame, Color, Move, PieceType, PlayedMove, Square};
+use common::{board_after, board_text, normal_move};
+
+#[test]
+fn squares_must_be_on_the_board() {
+    let square = Square::try_from((4, 1)).unwrap();
+    assert_eq!((square.file(), square.rank()), (4, 1));
+    for off_board in [(-1, 0), (0, -1), (8, 0), (0, 8)] {
+        assert!(Square::try_from(off_board).is_err(), "{off_board:?}");
+    }
+}
+
+#[test]
+fn backends_are_parsed_by_name() {
+    for backend in Backend::ALL {
+        assert_eq!(Backend::parse(backend.name()), Some(backend));
+    }
+    assert_eq!(Backend::parse("stockfish"), None);
+}
+
+#[test]
+fn every_backend_plays_the_same_game() {
+    let names = ["e2e4", "e7e5", "g1f3", "b8c6"];
+    let history: Vec<PlayedMove> = names.iter()
+        .map(|name| PlayedMove { mv: normal_move(name), promotion: None })
+        .collect();
+    for backend in Backend::ALL {
+        let mut game = backend.replay(&history);
+        assert_eq!(board_text(game.get_pieces()), board_text(board_after(&names)), "{}", backend.name());
+        assert_eq!(game.current_turn(), Color::White);
+
+        let knight = Square::try_from((5, 2)).unwrap();
+        assert_eq!(game.get_piece(knight).map(|piece| piece.kind), Some(PieceType::Knight));
+        let moves = game.possible_moves(knight).unwrap();
+        assert!(moves.contains(&normal_move("f3g5")), "{}: {moves:?}", backend.name());
+        assert!(!moves.iter().any(|mv| matches!(mv, Move::Castle { .. })));
+    }
+}
+
+#[test]
+fn game_is_over_after_checkmate() {
+    let names = ["f2f3", "e7e5", "g2g4", "d8h4"];
+    let history: Vec<PlayedMove> = names.iter()
+        .map(|name| PlayedMove { mv: normal_move(name), promotion: None })
+        .collect();
+    for backend in Backend::ALL {
+        assert!(!backend.replay(&history[..3]).is_over(), "{}", backend.name());
+        assert!(backend.replay(&history).is_over(), "{}", backend.name());
+    }
+}
+
+#[test]
+fn every_backend_castles_and_takes_en_passant() {
+    let names = ["e2e4", "a7a6", "e4e5", "d7d5", "g1f3", "b7b6", "f1e2", "c7c6"];
+    let history: Vec<PlayedMove> = names.iter()
+        .map(|name| PlayedMove { mv: normal_move(name), promotion: None })
+        .collect();
+    for backend in Backend::ALL {
+        let mut game = backend.replay(&history);
+        let king = Square::try_from((4, 0)).unwrap();
+        let castle = Move::Castle { side: CastlingSide::KingSide };
+        assert!(game.possible_moves(king).unwrap().contains(&castle), "{}", backend.name());
+        game.perform_move(castle).unwrap();
+        assert_eq!(game.get_piece(Square::try_from((6, 0)).unwrap()).map(|piece| piece.kind), Some(PieceType::King));
+        assert_eq!(game.get_piece(Square::try_from((5, 0)).unwrap()).map(|piece| piece.kind), Some(PieceType::Rook));
+
+        // d5 was pushed two squares right after e4e5, but black has moved since.
+        let pawn = Square::try_from((4, 4)).unwrap();
+        game.perform_move(normal_move("f7f5")).unwrap();
+        let moves = game.possible_moves(pawn).unwrap();
+        assert!(moves.contains(&normal_move("e5f6")), "{}: {moves:?}", backend.name());
+        assert!(!moves.contains(&normal_move("e5d6")), "{}: {moves:?}", backend.name());
+        game.perform_move(normal_move("e5f6")).unwrap();
+        assert_eq!(game.get_piece(Square::try_from((5, 4)).unwrap()), None, "{}", backend.name());
+    }
+}
+
+#[test]
+fn builtin_backend_guesses_castling_from_the_board() {
+    let game_after = |names: &[&str]| {
+        let mut game = Backend::Builtin.seeded(&board_after(names), Color::White);
+        let king = Square::try_from((4, 0)).unwrap();
+        game.possible_moves(king).unwrap()
+    };
+    let castle = Move::Castle { side: CastlingSide::KingSide };
+    let developed = ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6"];
+    assert!(game_after(&developed).contains(&castle));
+    // The rook has left its square, so there is nothing to castle with.
+    assert!(!game_after(&[&developed[..], &["h2h4", "a7a6", "h1h3", "a6a5"]].concat()).contains(&castle));
+}
@@ -0,0 +1,160 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alvinw_chess_gui::bridge::{ChessGame, Color, Move, Piece, PieceType, Square};
+use alvinw_chess_gui::json_tcp_stream::JsonTcpStream;
+use alvinw_chess_gui::local_game::LocalGame;
+use alvinw_chess_gui::notation;
+use alvinw_chess_gui::server::convert_board;
+use chess_network_protocol::{Move as ProtocolMove, Piece as ProtocolPiece};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// How long to wait for the other side before failing a test.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Call `condition` until it returns true. Panics with `what` if that takes too long.
+pub fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "Timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Call `f` a few times to let it process anything that has arrived, for checking that
+/// nothing happens.
+pub fn settle(mut f: impl FnMut()) {
+    for _ in 0..20 {
+        f();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+pub fn square(name: &str) -> Square {
+    notation::parse_square(name).unwrap_or_else(|| panic!("Invalid square {name}"))
+}
+
+/// A move in coordinate notation like `e2e4`.
+pub fn normal_move(name: &str) -> Move {
+    Move::Normal { from: square(&name[0..2]), to: square(&name[2..4]) }
+}
+
+pub fn protocol_move(name: &str) -> ProtocolMove {
+    let (from, to) = (square(&name[0..2]), square(&name[2..4]));
+    ProtocolMove {
+        start_x: from.file() as usize,
+        start_y: from.rank() as usize,
+        end_x: to.file() as usize,
+        end_y: to.rank() as usize,
+        promotion: ProtocolPiece::None,
+    }
+}
+
+/// Make the move `name`, in coordinate notation like `e2e4`, in `game`.
+pub fn play(game: &mut impl ChessGame, name: &str) {
+    game.perform_move(normal_move(name)).unwrap_or_else(|err| panic!("Illegal move {name}: {err}"));
+}
+
+/// The board after playing `moves` from the starting position.
+pub fn board_after(moves: &[&str]) -> [[Option<Piece>; 8]; 8] {
+    let mut game = LocalGame::new();
+    for name in moves {
+        play(&mut game, name);
+    }
+    game.get_pieces()
+}
+
+pub fn protocol_board_after(moves: &[&str]) -> [[ProtocolPiece; 8]; 8] {
+    convert_board(board_after(moves))
+}
+
+/// The board as text with one rank per line, rank 8 first, using FEN letters. Boards are
+/// compared like this since pieces can't be compared directly and so that failures are
+/// readable.
+pub fn board_text(board: [[Option<Piece>; 8]; 8]) -> String {
+    board.iter().rev()
+        .map(|rank| rank.iter().map(|piece| match piece {
+            None => '.',
+            Some(piece) => {
+                let letter = match piece.kind {
+                    PieceType::Pawn => 'p',
+                    PieceType::Knight => 'n',
+                    PieceType::Bishop => 'b',
+                    PieceType::Rook => 'r',
+                    PieceType::Queen => 'q',
+                    PieceType::King => 'k',
+                };
+                if piece.color == Color::White { letter.to_ascii_uppercase() } else { letter }
+            }
+        }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A scripted peer on the other end of a connection, which sends exactly what the test
+/// tells it to.
+pub struct FakePeer {
+    /// Used for sending, so that bytes can be split up however the test wants.
+    raw: TcpStream,
+    stream: JsonTcpStream,
+}
+
+impl FakePeer {
+    fn new(stream: TcpStream) -> Self {
+        let raw = stream.try_clone().unwrap();
+        raw.set_nodelay(true).unwrap();
+        Self { raw, stream: JsonTcpStream::new(stream) }
+    }
+
+    /// Connect to a server listening on `port` on this machine.
+    pub fn connect(port: u16) -> Self {
+        Self::new(TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap())
+    }
+
+    /// Accept the next connection to `listener`.
+    pub fn accept(listener: &TcpListener) -> Self {
+        listener.set_nonblocking(false).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        Self::new(stream)
+    }
+
+    pub fn send<T: Serialize>(&mut self, message: &T) {
+        self.send_raw(serde_json::to_string(message).unwrap().as_bytes());
+    }
+
+    pub fn send_raw(&mut self, bytes: &[u8]) {
+        self.raw.write_all(bytes).unwrap();
+        self.raw.flush().unwrap();
+    }
+
+    /// Wait for a message of type `T`, skipping other messages.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> T {
+        self.receive_while(|| {})
+    }
+
+    /// Wait for a message of type `T` while calling `tick`, which should let the other
+    /// side process what it has received.
+    pub fn receive_while<T: DeserializeOwned>(&mut self, mut tick: impl FnMut()) -> T {
+        let mut message = None;
+        wait_until("a message", || {
+            tick();
+            message = self.stream.read();
+            message.is_some()
+        });
+        message.unwrap()
+    }
+}
+
+/// A listener on a free port on this machine, for a fake server.
+pub fn listen() -> (TcpListener, u16) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
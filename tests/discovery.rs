@@ -0,0 +1,65 @@
+//! Announcing games on the local network and finding them.
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::discovery::{Announcement, Beacon, Browser};
+use alvinw_chess_gui::saved_game::SavedColor;
+use alvinw_chess_gui::server::ServerGame;
+use common::{settle, wait_until};
+
+/// A browser on a free port and a beacon that sends to it.
+fn browser_and_beacon() -> (Browser, Beacon) {
+    let browser = Browser::bind(0).unwrap();
+    let port = browser.local_port().unwrap();
+    let beacon = Beacon::to(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).unwrap();
+    (browser, beacon)
+}
+
+#[test]
+fn announced_game_is_found() {
+    let (mut browser, mut beacon) = browser_and_beacon();
+    let announcement = Announcement::new(String::from("Test game"), 9000, SavedColor::White, false, true);
+    beacon.announce(&announcement);
+
+    wait_until("the browser to find the game", || {
+        browser.poll();
+        !browser.games().is_empty()
+    });
+    let games = browser.games();
+    assert_eq!(games.len(), 1);
+    assert_eq!(games[0].announcement, announcement);
+    assert_eq!(games[0].address, SocketAddr::from((Ipv4Addr::LOCALHOST, 9000)));
+}
+
+#[test]
+fn other_datagrams_are_ignored() {
+    let mut browser = Browser::bind(0).unwrap();
+    let port = browser.local_port().unwrap();
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    socket.send_to(b"not json", (Ipv4Addr::LOCALHOST, port)).unwrap();
+    socket.send_to(br#"{"app":"something-else","name":"x","port":1,"color":"white","reserved":false,"tls":false}"#, (Ipv4Addr::LOCALHOST, port)).unwrap();
+
+    settle(|| browser.poll());
+    assert!(browser.games().is_empty());
+}
+
+#[test]
+fn waiting_server_announces_itself() {
+    let (mut browser, beacon) = browser_and_beacon();
+    let mut server = ServerGame::new(|| Backend::default().new_game(), 0)
+        .with_beacon(beacon, String::from("Waiting server"));
+
+    wait_until("the browser to find the server", || {
+        server.try_accept_client();
+        browser.poll();
+        !browser.games().is_empty()
+    });
+    let game = &browser.games()[0];
+    assert_eq!(game.announcement.name, "Waiting server");
+    assert_eq!(game.announcement.port, server.local_port());
+    assert_eq!(game.announcement.color, SavedColor::White);
+    assert!(!game.announcement.reserved);
+}
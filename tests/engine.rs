@@ -0,0 +1,189 @@
+//! The engine backend, on its own and hosted for a client to play against.
+
+mod common;
+
+use std::net::Ipv4Addr;
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::bridge::{CastlingSide, ChessGame, Color, GameState, Move, MoveError, Piece, PieceType, PlayedMove, Square};
+use alvinw_chess_gui::client::ClientGame;
+use alvinw_chess_gui::engine::EngineGame;
+use alvinw_chess_gui::saved_game::SavedSession;
+use alvinw_chess_gui::server::{ProtocolState, ServerGame};
+use alvinw_chess_gui::session::GameSession;
+use common::{board_after, board_text, normal_move, play, wait_until};
+
+/// The move the engine wants to make after `moves`.
+fn engine_move_after(moves: &[&str]) -> Option<PlayedMove> {
+    let mut game = EngineGame::new(Backend::default());
+    for name in moves {
+        play(&mut game, name);
+    }
+    let mut played = None;
+    wait_until("the engine to choose a move", || {
+        played = game.engine_move();
+        played.is_some()
+    });
+    played
+}
+
+#[test]
+fn engine_takes_a_free_queen() {
+    let played = engine_move_after(&["e2e4", "d7d5", "d1g4"]).unwrap();
+    assert_eq!(played.mv, normal_move("c8g4"));
+}
+
+#[test]
+fn engine_mates_in_one() {
+    let played = engine_move_after(&["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6"]).unwrap();
+    assert_eq!(played.mv, normal_move("h5f7"));
+}
+
+#[test]
+fn engine_castles_when_nothing_wins_material() {
+    let played = engine_move_after(&["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5"]).unwrap();
+    assert_eq!(played.mv, Move::Castle { side: CastlingSide::KingSide });
+}
+
+#[test]
+fn engine_promotes_to_a_queen() {
+    let played = engine_move_after(&["a2a4", "h7h6", "a4a5", "h6h5", "a5a6", "h5h4", "a6b7", "h4h3"]).unwrap();
+    assert_eq!(played, PlayedMove { mv: normal_move("b7a8"), promotion: Some(PieceType::Queen) });
+}
+
+/// Start `server` and connect a client to it, which plays white.
+fn connect<G: ChessGame>(server: &mut ServerGame<G>) -> ClientGame {
+    let mut client = ClientGame::connect((Ipv4Addr::LOCALHOST, server.local_port())).unwrap();
+    wait_until("the server to finish the handshake", || {
+        match server.get_protocol_state() {
+            ProtocolState::NotConnected => server.try_accept_client(),
+            ProtocolState::Handshake => server.try_handshake(),
+            ProtocolState::Play => return true,
+        }
+        false
+    });
+    wait_until("the client to finish the handshake", || {
+        client.update();
+        client.is_ready()
+    });
+    assert!(client.player_color() == Some(Color::White));
+    client
+}
+
+#[test]
+fn host_castling_reaches_the_client() {
+    let mut server = ServerGame::new(|| Backend::default().new_game(), 0);
+    let mut client = connect(&mut server);
+    for (index, name) in ["e2e4", "e7e5", "g1f3", "g8f6", "f1c4", "f8e7", "d2d3"].iter().enumerate() {
+        if index % 2 == 0 {
+            play(&mut client, name);
+        } else {
+            play(&mut server, name);
+        }
+        wait_until("both sides to see the move", || {
+            server.update();
+            client.update();
+            server.history().len() == index + 1 && client.history().len() == index + 1
+        });
+    }
+
+    server.perform_move(Move::Castle { side: CastlingSide::KingSide }).unwrap();
+    wait_until("the client to see the castling", || {
+        client.update();
+        client.history().len() == 8
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+    assert_eq!(client.history()[7].mv, Move::Castle { side: CastlingSide::KingSide });
+}
+
+#[test]
+fn host_promotion_reaches_the_client() {
+    let mut server = ServerGame::new(|| Backend::default().new_game(), 0);
+    let mut client = connect(&mut server);
+    for (index, name) in ["a2a3", "h7h5", "a3a4", "h5h4", "a4a5", "h4h3", "a5a6", "h3g2", "b2b3"].iter().enumerate() {
+        if index % 2 == 0 {
+            play(&mut client, name);
+        } else {
+            play(&mut server, name);
+        }
+        wait_until("both sides to see the move", || {
+            server.update();
+            client.update();
+            server.history().len() == index + 1 && client.history().len() == index + 1
+        });
+    }
+
+    play(&mut server, "g2h1");
+    let h1 = Square::try_from((7, 0)).unwrap();
+    server.promote(h1, PieceType::Queen);
+    let queen = Piece { kind: PieceType::Queen, color: Color::Black };
+    wait_until("the client to see the queen", || {
+        client.update();
+        client.get_piece(h1) == Some(queen)
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+    assert_eq!(client.history().len(), 10);
+    assert_eq!(client.history()[9].promotion, Some(PieceType::Queen));
+}
+
+#[test]
+fn client_plays_against_the_engine() {
+    let mut server = ServerGame::new(|| EngineGame::new(Backend::default()), 0);
+    let mut client = connect(&mut server);
+
+    play(&mut client, "e2e4");
+    wait_until("the engine to answer", || {
+        server.update();
+        client.update();
+        server.history().len() == 2 && client.history().len() == 2
+    });
+    assert_eq!(server.history()[0].mv, normal_move("e2e4"));
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+    assert!(client.current_turn() == Color::White);
+    assert_ne!(board_text(server.get_pieces()), board_text(board_after(&["e2e4"])));
+}
+
+/// An engine that always wants to move its rook through its own pawn.
+struct StubbornEngine(Box<dyn ChessGame>);
+
+impl ChessGame for StubbornEngine {
+    fn update(&mut self) {}
+    fn get_pieces(&self) -> alvinw_chess_gui::bridge::Board { self.0.get_pieces() }
+    fn get_piece(&self, at: Square) -> Option<Piece> { self.0.get_piece(at) }
+    fn get_state(&self) -> GameState { self.0.get_state() }
+    fn is_check(&self) -> bool { self.0.is_check() }
+    fn current_turn(&self) -> Color { self.0.current_turn() }
+    fn promote(&mut self, at: Square, piece: PieceType) { self.0.promote(at, piece) }
+    fn possible_moves(&mut self, at: Square) -> Result<Vec<Move>, MoveError> { self.0.possible_moves(at) }
+    fn perform_move(&mut self, mv: Move) -> Result<(), MoveError> { self.0.perform_move(mv) }
+    fn can_play_right_now(&self) -> bool { true }
+    fn has_possible_moves(&self) -> bool { true }
+    fn is_over(&self) -> bool { self.0.is_over() }
+
+    fn engine_move(&mut self) -> Option<PlayedMove> {
+        Some(PlayedMove { mv: normal_move("a8a5"), promotion: None })
+    }
+}
+
+#[test]
+fn illegal_engine_move_is_replaced_by_a_legal_one() {
+    let mut server = ServerGame::new(|| StubbornEngine(Backend::default().new_game()), 0);
+    let mut client = connect(&mut server);
+
+    play(&mut client, "e2e4");
+    wait_until("the server to answer", || {
+        server.update();
+        client.update();
+        server.history().len() == 2 && client.history().len() == 2
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+    assert!(client.can_play_right_now());
+}
+
+#[test]
+fn engine_game_is_saved_as_one() {
+    let server = ServerGame::new(|| EngineGame::new(Backend::default()), 0);
+    assert!(matches!(server.saved_session(), Some(SavedSession::Server { engine: true, .. })));
+    let server = ServerGame::new(|| Backend::default().new_game(), 0);
+    assert!(matches!(server.saved_session(), Some(SavedSession::Server { engine: false, .. })));
+}
@@ -0,0 +1,264 @@
+//! Our server against scripted clients that send edge cases.
+
+mod common;
+
+use std::net::{Ipv4Addr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::bridge::{ChessGame, Color};
+use alvinw_chess_gui::extension::{self, ExtensionMessage};
+use alvinw_chess_gui::server::{ProtocolState, ServerGame};
+use alvinw_chess_gui::session::{Decision, DrawState, GameSession};
+use alvinw_chess_gui::tls::{self, Identity, Transport};
+use chess_network_protocol::{ClientToServer, ClientToServerHandshake, Color as ProtocolColor, Joever, ServerToClient, ServerToClientHandshake};
+use common::{board_after, board_text, play, protocol_move, settle, wait_until, FakePeer};
+
+/// Start a server and connect a fake client to it, without doing the handshake.
+fn start() -> (ServerGame, FakePeer) {
+    let mut server = ServerGame::new(|| Backend::default().new_game(), 0);
+    let client = FakePeer::connect(server.local_port());
+    wait_until("the server to accept the client", || {
+        server.try_accept_client();
+        matches!(server.get_protocol_state(), ProtocolState::Handshake)
+    });
+    (server, client)
+}
+
+fn finish_handshake(server: &mut ServerGame) {
+    wait_until("the server to finish the handshake", || {
+        server.try_handshake();
+        matches!(server.get_protocol_state(), ProtocolState::Play)
+    });
+}
+
+fn handshake() -> ClientToServerHandshake {
+    ClientToServerHandshake { server_color: ProtocolColor::Black }
+}
+
+#[test]
+fn handshake_split_into_pieces() {
+    let (mut server, mut client) = start();
+
+    let json = serde_json::to_string(&handshake()).unwrap();
+    let (first, second) = json.as_bytes().split_at(json.len() / 2);
+    client.send_raw(first);
+    settle(|| server.try_handshake());
+    assert!(matches!(server.get_protocol_state(), ProtocolState::Handshake));
+
+    client.send_raw(second);
+    finish_handshake(&mut server);
+    let reply: ServerToClientHandshake = client.receive();
+    assert_eq!(reply.moves.len(), 20);
+}
+
+#[test]
+fn handshake_and_move_in_one_packet() {
+    let (mut server, mut client) = start();
+
+    let handshake = serde_json::to_string(&handshake()).unwrap();
+    let mv = serde_json::to_string(&ClientToServer::Move(protocol_move("g1f3"))).unwrap();
+    client.send_raw(format!("{handshake}{mv}").as_bytes());
+
+    finish_handshake(&mut server);
+    wait_until("the server to make the move", || {
+        server.update();
+        server.history().len() == 1
+    });
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&["g1f3"])));
+}
+
+#[test]
+fn moves_split_across_packets_and_coalesced() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    let json = serde_json::to_string(&ClientToServer::Move(protocol_move("e2e4"))).unwrap();
+    for byte in json.as_bytes() {
+        client.send_raw(&[*byte]);
+        server.update();
+    }
+    let _: ServerToClient = client.receive_while(|| server.update());
+    assert_eq!(server.history().len(), 1);
+
+    play(&mut server, "e7e5");
+    let _: ServerToClient = client.receive();
+
+    // Two moves where the second is illegal, written at once with whitespace between.
+    let first = serde_json::to_string(&ClientToServer::Move(protocol_move("g1f3"))).unwrap();
+    let second = serde_json::to_string(&ClientToServer::Move(protocol_move("f1c4"))).unwrap();
+    client.send_raw(format!("{first}\n  {second}").as_bytes());
+    settle(|| server.update());
+
+    assert_eq!(server.history().len(), 3);
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&["e2e4", "e7e5", "g1f3"])));
+}
+
+#[test]
+fn illegal_move_is_answered_with_an_error() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    client.send(&ClientToServer::Move(protocol_move("e2e5")));
+    let reply: ServerToClient = client.receive_while(|| server.update());
+    let ServerToClient::Error { moves, .. } = reply else {
+        panic!("Expected an error, got {reply:?}");
+    };
+    assert_eq!(moves.len(), 20);
+    assert!(server.history().is_empty());
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&[])));
+}
+
+#[test]
+fn moves_out_of_turn_are_answered_with_an_error() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    client.send(&ClientToServer::Move(protocol_move("e2e4")));
+    let _: ServerToClient = client.receive_while(|| server.update());
+
+    // Moving the server's pieces, then moving again instead of waiting for the server.
+    for name in ["e7e5", "d2d4"] {
+        client.send(&ClientToServer::Move(protocol_move(name)));
+        let reply: ServerToClient = client.receive_while(|| server.update());
+        let ServerToClient::Error { message, .. } = reply else {
+            panic!("Expected an error for {name}, got {reply:?}");
+        };
+        assert_eq!(message, "It is not your turn.");
+    }
+    assert_eq!(server.history().len(), 1);
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&["e2e4"])));
+}
+
+#[test]
+fn checkmate_is_reported_in_the_state() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    let mut reply = None;
+    for (ours, theirs) in [("f2f3", "e7e5"), ("g2g4", "d8h4")] {
+        client.send(&ClientToServer::Move(protocol_move(ours)));
+        let _: ServerToClient = client.receive_while(|| server.update());
+        play(&mut server, theirs);
+        reply = Some(client.receive::<ServerToClient>());
+    }
+    let reply = reply.unwrap();
+    assert!(matches!(reply, ServerToClient::State { joever: Joever::Black, .. }), "{reply:?}");
+}
+
+#[test]
+fn resignation_ends_the_game() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    client.send(&ClientToServer::Resign);
+    let reply: ServerToClient = client.receive_while(|| server.update());
+    assert!(matches!(reply, ServerToClient::Resigned { joever: Joever::Black, .. }), "{reply:?}");
+    assert_eq!(server.decision(), Some(Decision::Resignation(Color::White)));
+    assert!(server.is_over());
+
+    client.send(&ClientToServer::Move(protocol_move("e2e4")));
+    let reply: ServerToClient = client.receive_while(|| server.update());
+    let ServerToClient::Error { message, joever, .. } = reply else {
+        panic!("Expected an error, got {reply:?}");
+    };
+    assert_eq!(message, "The game is over.");
+    assert!(matches!(joever, Joever::Black));
+    assert!(server.history().is_empty());
+}
+
+#[test]
+fn draw_offered_by_the_client_is_accepted_by_the_host() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    client.send(&ClientToServer::Draw);
+    wait_until("the server to see the draw offer", || {
+        server.update();
+        server.draw_state() == DrawState::Offered
+    });
+    server.offer_draw();
+    let reply: ServerToClient = client.receive();
+    assert!(matches!(reply, ServerToClient::Draw { .. }), "{reply:?}");
+    assert_eq!(server.decision(), Some(Decision::DrawAgreed));
+}
+
+#[test]
+fn client_leaving_does_not_stop_the_server() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+    client.send(&ExtensionMessage::Hello { extensions: vec![extension::CHAT.to_string()] });
+    client.send(&ClientToServer::Move(protocol_move("e2e4")));
+    wait_until("the server to make the move", || {
+        server.update();
+        server.history().len() == 1
+    });
+    drop(client);
+
+    // Sending to a client that has gone away fails sooner or later.
+    play(&mut server, "e7e5");
+    for _ in 0..10 {
+        server.send_chat("Are you still there?");
+        settle(|| server.update());
+    }
+    assert_eq!(server.history().len(), 2);
+}
+
+#[test]
+fn silent_connection_does_not_block_the_server() {
+    let identity = Identity::generate().unwrap();
+    let mut server = ServerGame::new(|| Backend::default().new_game(), 0).with_tls(identity.server_config().unwrap());
+    // Never starts the TLS handshake.
+    let _silent = TcpStream::connect((Ipv4Addr::LOCALHOST, server.local_port())).unwrap();
+    let started = Instant::now();
+    for _ in 0..10 {
+        server.try_accept_client();
+    }
+    assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+
+    let port = server.local_port();
+    let expected = identity.fingerprint();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        Transport::connect(stream, tls::client_config(Some(expected)))
+    });
+    wait_until("the encrypted client to connect", || {
+        server.try_accept_client();
+        matches!(server.get_protocol_state(), ProtocolState::Handshake)
+    });
+    assert!(client.join().unwrap().is_ok());
+}
+
+#[test]
+fn unexpected_messages_are_ignored() {
+    let (mut server, mut client) = start();
+    client.send(&handshake());
+    finish_handshake(&mut server);
+    let _: ServerToClientHandshake = client.receive();
+
+    client.send_raw(br#"{"Teleport":{"piece":"WhiteKing","to":"e8"}}"#);
+    client.send_raw(br#"[1, 2, 3]"#);
+    client.send(&ServerToClient::Draw { board: common::protocol_board_after(&[]), moves: vec![] });
+    client.send(&ClientToServer::Move(protocol_move("d2d4")));
+
+    wait_until("the server to make the move", || {
+        server.update();
+        server.history().len() == 1
+    });
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&["d2d4"])));
+}
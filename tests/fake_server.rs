@@ -0,0 +1,368 @@
+//! Our client against scripted servers that send edge cases.
+
+mod common;
+
+use std::net::Ipv4Addr;
+
+use alvinw_chess_gui::bridge::{ChessGame, Color};
+use alvinw_chess_gui::client::ClientGame;
+use alvinw_chess_gui::session::GameSession;
+use chess_network_protocol::{ClientToServer, ClientToServerHandshake, Features, Joever, Move as ProtocolMove, Piece as ProtocolPiece, ServerToClient, ServerToClientHandshake};
+use common::{board_after, board_text, listen, normal_move, play, protocol_board_after, protocol_move, settle, wait_until, FakePeer};
+
+/// Connect our client to a fake server and read the client's handshake.
+fn start() -> (ClientGame, FakePeer) {
+    let (listener, port) = listen();
+    let client = ClientGame::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    let mut server = FakePeer::accept(&listener);
+    let _: ClientToServerHandshake = server.receive();
+    (client, server)
+}
+
+/// The handshake of a server that only speaks the base protocol and sends no moves, so
+/// that the client works out the legal moves itself.
+fn handshake() -> ServerToClientHandshake {
+    ServerToClientHandshake {
+        features: vec![],
+        board: protocol_board_after(&[]),
+        moves: vec![],
+        joever: Joever::Ongoing,
+    }
+}
+
+fn state_after(moves: &[&str]) -> ServerToClient {
+    ServerToClient::State {
+        board: protocol_board_after(moves),
+        moves: vec![],
+        joever: Joever::Ongoing,
+        move_made: protocol_move(moves.last().unwrap()),
+    }
+}
+
+fn finish_handshake(client: &mut ClientGame) {
+    wait_until("the client to finish the handshake", || {
+        client.update();
+        client.is_ready()
+    });
+}
+
+#[test]
+fn handshake_split_into_pieces() {
+    let (mut client, mut server) = start();
+
+    let json = serde_json::to_string(&handshake()).unwrap();
+    let (first, second) = json.as_bytes().split_at(json.len() / 3);
+    server.send_raw(first);
+    settle(|| client.update());
+    assert!(!client.is_ready());
+
+    server.send_raw(second);
+    finish_handshake(&mut client);
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&[])));
+    assert!(client.can_play_right_now());
+}
+
+#[test]
+fn handshake_and_state_in_one_packet() {
+    let (mut client, mut server) = start();
+
+    let handshake = serde_json::to_string(&handshake()).unwrap();
+    let state = serde_json::to_string(&state_after(&["e2e4"])).unwrap();
+    server.send_raw(format!("{handshake}{state}").as_bytes());
+
+    finish_handshake(&mut client);
+    wait_until("the client to see the move", || {
+        client.update();
+        client.history().len() == 1
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&["e2e4"])));
+}
+
+#[test]
+fn several_states_in_one_packet() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    let states: Vec<String> = [&["e2e4"][..], &["e2e4", "e7e5"], &["e2e4", "e7e5", "g1f3"]]
+        .iter()
+        .map(|moves| serde_json::to_string(&state_after(moves)).unwrap())
+        .collect();
+    server.send_raw(states.join("\r\n").as_bytes());
+
+    wait_until("the client to see all moves", || {
+        client.update();
+        client.history().len() == 3
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&["e2e4", "e7e5", "g1f3"])));
+    assert!(!client.can_play_right_now());
+}
+
+#[test]
+fn server_decides_whether_a_move_is_legal() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    // Our rules engine doesn't allow this, but it is only used for hints.
+    let e2 = common::square("e2");
+    assert!(!client.possible_moves(e2).unwrap().contains(&normal_move("e2e5")));
+    play(&mut client, "e2e5");
+    let sent: ClientToServer = server.receive();
+    let ClientToServer::Move(mv) = sent else {
+        panic!("Expected a move, got {sent:?}");
+    };
+    assert_eq!((mv.start_x, mv.start_y, mv.end_x, mv.end_y), (4, 1, 4, 4));
+}
+
+/// Send the client the states after each of `moves` and wait until it has seen them.
+fn send_moves(client: &mut ClientGame, server: &mut FakePeer, moves: &[&str]) {
+    for plies in 1..=moves.len() {
+        server.send(&state_after(&moves[..plies]));
+    }
+    wait_until("the client to see all moves", || {
+        client.update();
+        client.history().len() == moves.len()
+    });
+}
+
+#[test]
+fn hints_follow_the_history() {
+    use alvinw_chess_gui::bridge::Move;
+
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    // The kings have moved and are back, so neither can castle any more.
+    send_moves(&mut client, &mut server, &["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8", "g1f3", "g8f6", "f1c4", "f8c5"]);
+    let king_moves = client.possible_moves(common::square("e1")).unwrap();
+    assert!(!king_moves.iter().any(|mv| matches!(mv, Move::Castle { .. })), "{king_moves:?}");
+}
+
+#[test]
+fn hints_include_en_passant() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    send_moves(&mut client, &mut server, &["e2e4", "a7a6", "e4e5", "d7d5"]);
+    assert!(client.possible_moves(common::square("e5")).unwrap().contains(&normal_move("e5d6")));
+}
+
+#[test]
+fn check_waits_for_the_server() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    send_moves(&mut client, &mut server, &["e2e4", "f7f6"]);
+    play(&mut client, "d1h5");
+    let _: ClientToServer = server.receive();
+    assert!(!client.is_check(), "Our move could still be rejected");
+
+    server.send(&state_after(&["e2e4", "f7f6", "d1h5"]));
+    wait_until("the client to see the move", || {
+        client.update();
+        client.is_check()
+    });
+}
+
+#[test]
+fn error_from_server_restores_its_board() {
+    let (mut client, mut server) = start();
+    server.send(&ServerToClientHandshake { features: vec![Features::PossibleMoveGeneration], ..handshake() });
+    finish_handshake(&mut client);
+
+    // The server generates the moves, so the client doesn't check this one itself.
+    play(&mut client, "a2a5");
+    let _: ClientToServer = server.receive();
+    assert!(client.get_piece(common::square("a5")).is_some(), "The move should be shown until the server answers");
+    assert!(!client.can_play_right_now());
+
+    server.send(&ServerToClient::Error {
+        board: protocol_board_after(&[]),
+        moves: vec![protocol_move("a2a3")],
+        joever: Joever::Ongoing,
+        message: String::from("That pawn can't move that far"),
+    });
+    settle(|| client.update());
+
+    assert!(client.history().is_empty());
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&[])));
+    assert!(client.can_play_right_now());
+    assert_eq!(client.take_error().as_deref(), Some("That pawn can't move that far"));
+    assert_eq!(client.take_error(), None);
+}
+
+#[test]
+fn unexpected_messages_are_ignored() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    server.send_raw(br#"{"Weather":"sunny"}"#);
+    server.send_raw(br#""just a string""#);
+    server.send(&ClientToServer::Resign);
+    server.send(&state_after(&["d2d4"]));
+
+    wait_until("the client to see the move", || {
+        client.update();
+        client.history().len() == 1
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&["d2d4"])));
+}
+
+#[test]
+fn repeated_state_is_not_counted_twice() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    server.send(&state_after(&["e2e4"]));
+    server.send(&state_after(&["e2e4"]));
+    settle(|| client.update());
+
+    assert_eq!(client.history().len(), 1);
+    assert!(client.current_turn() == Color::Black);
+    assert_eq!(client.sync_problem(), None);
+}
+
+#[test]
+fn moves_off_the_board_are_ignored() {
+    let (mut client, mut server) = start();
+    server.send(&ServerToClientHandshake {
+        moves: vec![ProtocolMove { start_x: 12, start_y: 1, end_x: 4, end_y: 3, promotion: ProtocolPiece::None }],
+        ..handshake()
+    });
+    finish_handshake(&mut client);
+
+    let ServerToClient::State { board, moves, joever, move_made } = state_after(&["e2e4"]) else {
+        unreachable!();
+    };
+    server.send(&ServerToClient::State { board, moves, joever, move_made: ProtocolMove { end_x: 8, ..move_made } });
+    server.send(&state_after(&["d2d4"]));
+    wait_until("the client to see the next move", || {
+        client.update();
+        !client.history().is_empty()
+    });
+
+    assert_eq!(client.history().len(), 1);
+    assert_eq!(client.history()[0].mv, normal_move("d2d4"));
+}
+
+#[test]
+fn missed_state_is_reported_and_turn_follows_the_board() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    // As if the state for e2e4 was lost on the way.
+    server.send(&state_after(&["e2e4", "e7e5"]));
+    wait_until("the client to see the move", || {
+        client.update();
+        !client.history().is_empty()
+    });
+
+    assert!(client.sync_problem().is_some());
+    assert!(client.current_turn() == Color::White);
+    assert!(client.can_play_right_now());
+}
+
+#[test]
+fn missed_state_is_only_reported_once() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    server.send(&state_after(&["e2e4", "e7e5"]));
+    wait_until("the client to see the move", || {
+        client.update();
+        client.history().len() == 1
+    });
+    assert!(client.sync_problem().is_some());
+
+    server.send(&state_after(&["e2e4", "e7e5", "g1f3"]));
+    wait_until("the client to see the next move", || {
+        client.update();
+        client.history().len() == 2
+    });
+    assert_eq!(client.sync_problem(), None);
+    assert!(client.current_turn() == Color::Black);
+}
+
+#[test]
+fn position_hash_mismatch_leads_to_resync() {
+    use alvinw_chess_gui::extension::{self, ExtensionMessage};
+
+    let (mut client, mut server) = start();
+    server.send(&ServerToClientHandshake {
+        features: vec![Features::Other(extension::POSITION_HASH.to_string())],
+        ..handshake()
+    });
+    finish_handshake(&mut client);
+    let ExtensionMessage::Hello { extensions } = server.receive() else {
+        panic!("Expected the client to say which extensions it supports");
+    };
+    assert_eq!(extensions, vec![extension::POSITION_HASH.to_string()]);
+
+    server.send(&state_after(&["e2e4"]));
+    server.send(&ExtensionMessage::PositionHash { plies: 1, hash: 12345 });
+    let ExtensionMessage::PositionHash { plies, .. } = server.receive_while(|| client.update()) else {
+        panic!("Expected the client to answer with its hash");
+    };
+    assert_eq!(plies, 1);
+    let request: ExtensionMessage = server.receive_while(|| client.update());
+    assert!(matches!(request, ExtensionMessage::ResyncRequest), "Expected a resync request, got {request:?}");
+    assert!(client.sync_problem().is_some());
+
+    server.send(&ExtensionMessage::Resync {
+        board: protocol_board_after(&["e2e4", "e7e5"]),
+        moves: vec![],
+        history: vec![String::from("e2e4"), String::from("e7e5")],
+    });
+    wait_until("the client to resync", || {
+        client.update();
+        client.history().len() == 2
+    });
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&["e2e4", "e7e5"])));
+    assert_eq!(client.sync_problem(), None);
+    assert!(client.can_play_right_now());
+}
+
+#[test]
+fn matching_position_hash_is_accepted() {
+    use alvinw_chess_gui::extension::{self, ExtensionMessage};
+
+    let (mut client, mut server) = start();
+    server.send(&ServerToClientHandshake {
+        features: vec![Features::Other(extension::POSITION_HASH.to_string())],
+        ..handshake()
+    });
+    finish_handshake(&mut client);
+    let _: ExtensionMessage = server.receive();
+
+    server.send(&state_after(&["e2e4"]));
+    let hash = extension::position_hash(&board_after(&["e2e4"]), Color::Black);
+    server.send(&ExtensionMessage::PositionHash { plies: 1, hash });
+    let answer: ExtensionMessage = server.receive_while(|| client.update());
+    assert!(matches!(answer, ExtensionMessage::PositionHash { plies: 1, hash: answered } if answered == hash));
+    settle(|| client.update());
+    assert_eq!(client.sync_problem(), None);
+}
+
+#[test]
+fn chat_is_unavailable_when_the_server_lacks_it() {
+    let (mut client, mut server) = start();
+    server.send(&handshake());
+    finish_handshake(&mut client);
+
+    assert!(!client.supports_chat());
+    client.send_chat("hello?");
+    assert!(client.chat_messages().is_empty());
+
+    // The first thing the server hears about is the move, not the chat message.
+    play(&mut client, "e2e4");
+    let message: serde_json::Value = server.receive();
+    assert!(serde_json::from_value::<ClientToServer>(message.clone()).is_ok(), "Expected a move, got {message}");
+}
@@ -0,0 +1,304 @@
+//! Our server and client playing against each other.
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener};
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::bridge::{CastlingSide, ChessGame, Color, Move, Piece, PieceType};
+use alvinw_chess_gui::client::ClientGame;
+use alvinw_chess_gui::server::{ProtocolState, ServerGame};
+use alvinw_chess_gui::session::GameSession;
+use common::{board_after, board_text, play, wait_until};
+
+fn connect() -> (ServerGame, ClientGame) {
+    connect_over(Ipv4Addr::LOCALHOST.into())
+}
+
+fn connect_over(ip: IpAddr) -> (ServerGame, ClientGame) {
+    let mut server = ServerGame::new(|| Backend::default().new_game(), 0);
+    let mut client = ClientGame::connect((ip, server.local_port())).unwrap();
+    wait_until("the server to finish the handshake", || {
+        match server.get_protocol_state() {
+            ProtocolState::NotConnected => server.try_accept_client(),
+            ProtocolState::Handshake => server.try_handshake(),
+            ProtocolState::Play => return true,
+        }
+        false
+    });
+    wait_until("the client to finish the handshake", || {
+        client.update();
+        client.is_ready()
+    });
+    (server, client)
+}
+
+/// Update both sides until they have both seen `plies` moves.
+fn sync(server: &mut ServerGame, client: &mut ClientGame, plies: usize) {
+    wait_until("both sides to see the move", || {
+        server.update();
+        client.update();
+        server.history().len() == plies && client.history().len() == plies
+    });
+}
+
+#[test]
+fn client_can_join_over_ipv6() {
+    if TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).is_err() {
+        // This machine has no IPv6, which is nothing to do with us.
+        return;
+    }
+    let (server, client) = connect_over(Ipv6Addr::LOCALHOST.into());
+
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+}
+
+#[test]
+fn handshake_gives_both_sides_the_starting_position() {
+    let (server, client) = connect();
+
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&[])));
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&[])));
+    assert!(client.can_play_right_now());
+    assert!(!server.can_play_right_now());
+}
+
+#[test]
+fn moves_from_both_sides_reach_the_other_side() {
+    let (mut server, mut client) = connect();
+
+    play(&mut client, "e2e4");
+    sync(&mut server, &mut client, 1);
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&["e2e4"])));
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+    assert!(client.current_turn() == Color::Black);
+    assert!(server.can_play_right_now());
+
+    play(&mut server, "e7e5");
+    sync(&mut server, &mut client, 2);
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&["e2e4", "e7e5"])));
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+    assert!(client.can_play_right_now());
+}
+
+/// Play `moves` from the start, with the client playing white.
+fn play_alternately(server: &mut ServerGame, client: &mut ClientGame, moves: &[&str]) {
+    for (index, name) in moves.iter().enumerate() {
+        if index % 2 == 0 {
+            play(client, name);
+        } else {
+            play(server, name);
+        }
+        sync(server, client, index + 1);
+    }
+}
+
+#[test]
+fn client_promotion_reaches_the_server() {
+    let (mut server, mut client) = connect();
+    play_alternately(&mut server, &mut client, &["h2h4", "a7a5", "h4h5", "a5a4", "h5h6", "a4a3", "h6g7", "a3b2"]);
+
+    play(&mut client, "g7h8");
+    let h8 = common::square("h8");
+    client.promote(h8, PieceType::Knight);
+    sync(&mut server, &mut client, 9);
+
+    let knight = Piece { kind: PieceType::Knight, color: Color::White };
+    assert_eq!(server.get_piece(h8), Some(knight));
+    assert_eq!(server.history()[8].promotion, Some(PieceType::Knight));
+    assert_eq!(client.history()[8].promotion, Some(PieceType::Knight));
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+}
+
+#[test]
+fn castling_is_recorded_as_castling_on_both_sides() {
+    let (mut server, mut client) = connect();
+    play_alternately(&mut server, &mut client, &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6"]);
+
+    client.perform_move(Move::Castle { side: CastlingSide::KingSide }).unwrap();
+    sync(&mut server, &mut client, 7);
+
+    let castle = Move::Castle { side: CastlingSide::KingSide };
+    assert_eq!(server.history()[6].mv, castle);
+    assert_eq!(client.history()[6].mv, castle);
+    assert_eq!(board_text(client.get_pieces()), board_text(server.get_pieces()));
+}
+
+#[test]
+fn takeback_is_applied_on_both_sides() {
+    let (mut server, mut client) = connect();
+
+    play(&mut client, "d2d4");
+    sync(&mut server, &mut client, 1);
+
+    client.request_takeback();
+    wait_until("the server to see the takeback request", || {
+        server.update();
+        server.takeback_state() == alvinw_chess_gui::session::TakebackState::Offered
+    });
+    server.answer_takeback(true);
+    sync(&mut server, &mut client, 0);
+
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&[])));
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&[])));
+    assert!(client.can_play_right_now());
+}
+
+#[test]
+fn crossed_takeback_requests_take_back_both_moves() {
+    use alvinw_chess_gui::session::TakebackState;
+
+    let (mut server, mut client) = connect();
+    play(&mut client, "e2e4");
+    sync(&mut server, &mut client, 1);
+    play(&mut server, "e7e5");
+    sync(&mut server, &mut client, 2);
+
+    wait_until("the server to learn that the client has takebacks", || {
+        server.update();
+        server.request_takeback();
+        server.takeback_state() == TakebackState::Requested
+    });
+    // Sent before the client has read the server's request.
+    client.request_takeback();
+    sync(&mut server, &mut client, 0);
+
+    assert_eq!(server.takeback_state(), TakebackState::None);
+    assert_eq!(client.takeback_state(), TakebackState::None);
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&[])));
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&[])));
+}
+
+#[test]
+fn resignation_reaches_the_client() {
+    use alvinw_chess_gui::session::Decision;
+
+    let (mut server, mut client) = connect();
+    play(&mut client, "e2e4");
+    sync(&mut server, &mut client, 1);
+
+    client.resign();
+    wait_until("both sides to see the resignation", || {
+        server.update();
+        client.update();
+        server.decision().is_some() && client.decision().is_some()
+    });
+    assert_eq!(server.decision(), Some(Decision::Resignation(Color::White)));
+    assert_eq!(client.decision(), Some(Decision::Resignation(Color::White)));
+    assert!(server.is_over());
+    assert!(client.is_over());
+}
+
+#[test]
+fn draw_offered_by_the_host_is_accepted_by_the_client() {
+    use alvinw_chess_gui::session::{Decision, DrawState};
+
+    let (mut server, mut client) = connect();
+    wait_until("the server to learn that the client has chat", || {
+        server.update();
+        server.supports_chat()
+    });
+
+    server.offer_draw();
+    assert_eq!(server.draw_state(), DrawState::Requested);
+    wait_until("the client to be told about the offer", || {
+        client.update();
+        !client.chat_messages().is_empty()
+    });
+    client.offer_draw();
+    wait_until("both sides to see the draw", || {
+        server.update();
+        client.update();
+        server.decision().is_some() && client.decision().is_some()
+    });
+    assert_eq!(server.decision(), Some(Decision::DrawAgreed));
+    assert_eq!(client.decision(), Some(Decision::DrawAgreed));
+    assert!(client.is_over());
+}
+
+#[test]
+fn chat_messages_reach_the_other_side() {
+    use alvinw_chess_gui::session::ChatMessage;
+
+    let (mut server, mut client) = connect();
+    assert!(client.supports_chat());
+    wait_until("the server to learn that the client has chat", || {
+        server.update();
+        server.supports_chat()
+    });
+
+    client.send_chat("  good luck!  ");
+    wait_until("the server to receive the message", || {
+        server.update();
+        !server.chat_messages().is_empty()
+    });
+    server.send_chat("you too");
+    wait_until("the client to receive the message", || {
+        client.update();
+        client.chat_messages().len() == 2
+    });
+
+    let ours = |text: &str| ChatMessage { ours: true, text: text.to_string() };
+    let theirs = |text: &str| ChatMessage { ours: false, text: text.to_string() };
+    assert_eq!(client.chat_messages(), [ours("good luck!"), theirs("you too")]);
+    assert_eq!(server.chat_messages(), [theirs("good luck!"), ours("you too")]);
+}
+
+#[test]
+fn rematch_resets_the_game_and_swaps_colors() {
+    use alvinw_chess_gui::session::RematchState;
+
+    let (mut server, mut client) = connect();
+    play(&mut client, "e2e4");
+    sync(&mut server, &mut client, 1);
+
+    client.offer_rematch(true);
+    wait_until("the server to see the rematch offer", || {
+        server.update();
+        server.rematch_state() == RematchState::Offered { swap_colors: true }
+    });
+    server.answer_rematch(true);
+    wait_until("the client to start the new game", || {
+        client.update();
+        client.rematches() == 1
+    });
+
+    assert_eq!(server.rematches(), 1);
+    assert!(server.history().is_empty() && client.history().is_empty());
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&[])));
+    assert_eq!(board_text(server.get_pieces()), board_text(board_after(&[])));
+    assert!(server.player_color() == Some(Color::White));
+    assert!(client.player_color() == Some(Color::Black));
+    assert!(server.can_play_right_now());
+    assert!(!client.can_play_right_now());
+
+    // The connection is still used for the new game.
+    play(&mut server, "d2d4");
+    sync(&mut server, &mut client, 1);
+    assert_eq!(board_text(client.get_pieces()), board_text(board_after(&["d2d4"])));
+    assert!(client.can_play_right_now());
+}
+
+#[test]
+fn declined_rematch_keeps_the_game() {
+    use alvinw_chess_gui::session::RematchState;
+
+    let (mut server, mut client) = connect();
+    play(&mut client, "e2e4");
+    sync(&mut server, &mut client, 1);
+
+    server.offer_rematch(false);
+    wait_until("the client to see the rematch offer", || {
+        client.update();
+        client.rematch_state() == RematchState::Offered { swap_colors: false }
+    });
+    client.answer_rematch(false);
+    wait_until("the server to see the answer", || {
+        server.update();
+        server.rematch_state() == RematchState::None
+    });
+
+    assert_eq!(server.rematches(), 0);
+    assert_eq!(server.history().len(), 1);
+    assert!(server.can_play_right_now());
+}
@@ -0,0 +1,40 @@
+//! Reading recorded games and stepping through them.
+
+mod common;
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::bridge::{ChessGame, PieceType, PlayedMove};
+use alvinw_chess_gui::pgn;
+use alvinw_chess_gui::replay_game::ReplayGame;
+use alvinw_chess_gui::session::GameSession;
+use common::normal_move;
+
+#[test]
+fn promotions_may_be_written_without_equals() {
+    let history = pgn::parse("1. h4 g5 2. hxg5 h6 3. gxh6 Bg7 4. hxg7 Nf6 5. gxh8Q *", Backend::default()).unwrap();
+    assert_eq!(history.last(), Some(&PlayedMove { mv: normal_move("g7h8"), promotion: Some(PieceType::Queen) }));
+}
+
+#[test]
+fn comments_must_be_closed_and_opened() {
+    let history = pgn::parse("1. e4 {Best by test. A { is just text here} e5 (1... c5 (1... e6)) *", Backend::default()).unwrap();
+    assert_eq!(history.len(), 2);
+    for broken in ["1. e4 } e5", "1. e4 {e5", "1. e4 ) e5"] {
+        assert!(pgn::parse(broken, Backend::default()).is_err(), "{broken}");
+    }
+}
+
+#[test]
+fn illegal_move_stops_the_replay() {
+    let moves = ["e2e4", "e7e5", "e4e5", "g1f3"].map(|name| PlayedMove { mv: normal_move(name), promotion: None });
+    let mut game = ReplayGame::new(moves.to_vec());
+    game.go_to(usize::MAX);
+    assert_eq!(game.position(), 2);
+    let error = game.take_error().expect("the illegal move should be reported");
+    assert!(error.starts_with("Move 3"), "{error}");
+
+    assert!(!game.step_forward());
+    assert!(game.take_error().is_some());
+    game.go_to(0);
+    assert_eq!(game.get_pieces(), Backend::default().new_game().get_pieces());
+}
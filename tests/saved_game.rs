@@ -0,0 +1,48 @@
+//! Saving games in progress to the config directory.
+
+mod common;
+
+use std::{env, fs, process};
+
+use alvinw_chess_gui::bridge::PlayedMove;
+use alvinw_chess_gui::clock::Clocks;
+use alvinw_chess_gui::saved_game::{SavedGame, SavedSession};
+use common::normal_move;
+
+#[test]
+fn finished_game_is_cleared() {
+    let config = env::temp_dir().join(format!("alvinw-chess-gui-saved-game-{}", process::id()));
+    // The saved game is kept in the config directory, which follows this variable.
+    env::set_var("XDG_CONFIG_HOME", &config);
+
+    let history = [PlayedMove { mv: normal_move("e2e4"), promotion: None }];
+    SavedGame::new(SavedSession::Local, &history, Clocks::default()).save();
+    let saved = SavedGame::load().expect("the game should have been saved");
+    assert_eq!(saved.moves, ["e2e4"]);
+    let path = SavedGame::default_path().unwrap();
+    assert!(path.exists());
+    // The temporary file the game was written to first has been renamed.
+    let files: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(files, [path.file_name().unwrap()]);
+
+    SavedGame::clear();
+    assert!(SavedGame::load().is_none());
+    assert!(!SavedGame::default_path().unwrap().exists());
+    // Clearing when nothing is saved does nothing.
+    SavedGame::clear();
+
+    let _ = fs::remove_dir_all(config);
+}
+
+#[test]
+fn games_saved_before_the_engine_are_not_engine_games() {
+    let path = env::temp_dir().join(format!("alvinw-chess-gui-old-save-{}.json", process::id()));
+    fs::write(&path, r#"{
+        "session": { "kind": "server", "port": 8384, "color": "white", "peer": null },
+        "moves": ["e2e4"],
+        "clocks": { "white": { "secs": 0, "nanos": 0 }, "black": { "secs": 0, "nanos": 0 } }
+    }"#).unwrap();
+    let saved = SavedGame::load_from(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(saved.unwrap().session, SavedSession::Server { engine: false, tls: false, .. }));
+}
@@ -0,0 +1,13 @@
+//! Checking settings typed by the user.
+
+use alvinw_chess_gui::settings::parse_port;
+
+#[test]
+fn ports_go_from_1_to_65535() {
+    assert_eq!(parse_port("1"), Ok(1));
+    assert_eq!(parse_port("8384"), Ok(8384));
+    assert_eq!(parse_port("65535"), Ok(65535));
+    for invalid in ["", "0", "65536", "-1", "port"] {
+        assert!(parse_port(invalid).is_err(), "{invalid:?}");
+    }
+}
@@ -0,0 +1,91 @@
+//! Encrypted connections, below the chess protocol.
+
+mod common;
+
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+
+use alvinw_chess_gui::json_tcp_stream::JsonTcpStream;
+use alvinw_chess_gui::tls::{self, Identity, Transport};
+use common::wait_until;
+
+/// Connect a client with the given expected fingerprint to a server using `identity`.
+/// Returns the server's and the client's end, or the client's error.
+fn connect(identity: &Identity, expected: Option<String>) -> (std::io::Result<Transport>, std::io::Result<Transport>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let config = identity.server_config().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        Transport::accept(stream, config)
+    });
+
+    let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    let client = Transport::connect(stream, tls::client_config(expected));
+    (server.join().unwrap(), client)
+}
+
+#[test]
+fn messages_go_through_encrypted() {
+    let identity = Identity::generate().unwrap();
+    let (server, client) = connect(&identity, None);
+    let mut server = JsonTcpStream::with_transport(server.unwrap());
+    let mut client = JsonTcpStream::with_transport(client.unwrap());
+
+    assert_eq!(client.server_fingerprint(), Some(identity.fingerprint()));
+    assert_eq!(server.server_fingerprint(), None);
+
+    client.write(&vec![1, 2, 3]).unwrap();
+    let mut received = None;
+    wait_until("the server to receive the message", || {
+        received = server.read::<Vec<u8>>();
+        received.is_some()
+    });
+    assert_eq!(received, Some(vec![1, 2, 3]));
+
+    server.write(&"hello").unwrap();
+    let mut received = None;
+    wait_until("the client to receive the message", || {
+        received = client.read::<String>();
+        received.is_some()
+    });
+    assert_eq!(received.as_deref(), Some("hello"));
+}
+
+#[test]
+fn expected_fingerprint_is_accepted_in_any_format() {
+    let identity = Identity::generate().unwrap();
+    let expected = identity.fingerprint().replace(':', "").to_lowercase();
+    let (server, client) = connect(&identity, Some(expected));
+    assert!(server.is_ok());
+    assert!(client.is_ok());
+}
+
+#[test]
+fn wrong_fingerprint_is_refused() {
+    let identity = Identity::generate().unwrap();
+    let other = Identity::generate().unwrap();
+    let (server, client) = connect(&identity, Some(other.fingerprint()));
+    assert!(server.is_err());
+    let err = client.err().expect("the client should refuse the server");
+    assert!(err.to_string().contains(&identity.fingerprint()), "{err}");
+}
+
+#[cfg(unix)]
+#[test]
+fn saved_key_is_only_readable_by_the_user() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::{env, fs, process};
+
+    let config = env::temp_dir().join(format!("alvinw-chess-gui-tls-{}", process::id()));
+    // The identity is kept in the config directory, which follows this variable.
+    env::set_var("XDG_CONFIG_HOME", &config);
+    let identity = Identity::load_or_create().unwrap();
+    let key_file = config.join("alvinw-chess-gui").join("tls_key.der");
+    let mode = fs::metadata(&key_file).map(|metadata| metadata.permissions().mode());
+    let reloaded = Identity::load_or_create().unwrap();
+    fs::remove_dir_all(&config).unwrap();
+
+    assert_eq!(mode.unwrap() & 0o777, 0o600);
+    assert_eq!(reloaded.fingerprint(), identity.fingerprint());
+}
@@ -0,0 +1,268 @@
+//! Pairing and scoring tournaments, and hosting one for our clients.
+
+mod common;
+
+use std::net::{Ipv4Addr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alvinw_chess_gui::backend::Backend;
+use alvinw_chess_gui::bridge::{ChessGame, Color};
+use alvinw_chess_gui::client::ClientGame;
+use alvinw_chess_gui::extension::{self, ExtensionMessage};
+use alvinw_chess_gui::session::GameSession;
+use alvinw_chess_gui::tls::{self, Identity, Transport};
+use alvinw_chess_gui::tournament::{Format, GameResult, Outcome, Termination, Tournament, TournamentHost};
+use chess_network_protocol::{ClientToServer, ClientToServerHandshake, Color as ProtocolColor, Move as ProtocolMove, Piece as ProtocolPiece, ServerToClient, ServerToClientHandshake};
+use common::{play, wait_until, FakePeer};
+
+fn players(count: usize) -> Vec<String> {
+    (1..=count).map(|number| format!("Bot {number}")).collect()
+}
+
+/// Start the next round and give every game in it `outcome`.
+fn play_round(tournament: &mut Tournament, outcome: Outcome) {
+    let round = tournament.start_round().expect("Another round should start");
+    for pairing in 0..tournament.rounds()[round].pairings.len() {
+        let result = GameResult { outcome, termination: Termination::Agreement };
+        tournament.record(round, pairing, result, vec![]);
+    }
+}
+
+#[test]
+fn round_robin_pairs_everyone_once() {
+    for count in 2..=7 {
+        let mut tournament = Tournament::new(players(count), Format::RoundRobin);
+        while !tournament.is_finished() {
+            play_round(&mut tournament, Outcome::Draw);
+        }
+        assert_eq!(tournament.start_round(), None);
+
+        for a in 0..count {
+            for b in a + 1..count {
+                let meetings = tournament.rounds().iter()
+                    .flat_map(|round| &round.pairings)
+                    .filter(|pairing| pairing.has_player(a) && pairing.has_player(b))
+                    .count();
+                assert_eq!(meetings, 1, "Players {a} and {b} of {count} should meet once");
+            }
+        }
+        for standing in tournament.standings() {
+            assert_eq!(standing.played(), count - 1);
+            assert_eq!(standing.byes, count % 2, "With {count} players");
+        }
+    }
+}
+
+#[test]
+fn round_waits_for_the_current_one() {
+    let mut tournament = Tournament::new(players(4), Format::RoundRobin);
+    assert_eq!(tournament.start_round(), Some(0));
+    assert_eq!(tournament.start_round(), None);
+    assert!(!tournament.is_finished());
+}
+
+#[test]
+fn swiss_avoids_meeting_twice() {
+    let mut tournament = Tournament::new(players(6), Format::Swiss { rounds: 3 });
+    while !tournament.is_finished() {
+        play_round(&mut tournament, Outcome::WhiteWins);
+    }
+    assert_eq!(tournament.rounds().len(), 3);
+
+    let mut met = vec![];
+    for pairing in tournament.rounds().iter().flat_map(|round| &round.pairings) {
+        let pair = (pairing.white.min(pairing.black), pairing.white.max(pairing.black));
+        assert!(!met.contains(&pair), "{pair:?} met twice");
+        met.push(pair);
+    }
+}
+
+#[test]
+fn swiss_gives_each_bye_to_a_new_player() {
+    let mut tournament = Tournament::new(players(5), Format::Swiss { rounds: 4 });
+    while !tournament.is_finished() {
+        play_round(&mut tournament, Outcome::BlackWins);
+    }
+    let mut byes: Vec<usize> = tournament.rounds().iter().filter_map(|round| round.bye).collect();
+    byes.sort();
+    byes.dedup();
+    assert_eq!(byes.len(), 4);
+}
+
+#[test]
+fn standings_are_exported_as_csv() {
+    let mut tournament = Tournament::new(vec![String::from("Alpha"), String::from("Beta, the bot"), String::from("Gamma")], Format::RoundRobin);
+    play_round(&mut tournament, Outcome::WhiteWins);
+    play_round(&mut tournament, Outcome::Draw);
+    play_round(&mut tournament, Outcome::BlackWins);
+    assert!(tournament.is_finished());
+
+    let standings = tournament.standings();
+    let points: Vec<f32> = standings.iter().map(|standing| standing.points).collect();
+    assert_eq!(points.iter().sum::<f32>(), 6.0, "Three games and three byes");
+    assert!(points.windows(2).all(|pair| pair[0] >= pair[1]));
+
+    let csv = tournament.standings_csv();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("rank,name,points,played,wins,draws,losses,byes"));
+    assert_eq!(lines.clone().count(), 3);
+    assert!(csv.contains("\"Beta, the bot\""), "{csv}");
+}
+
+#[test]
+fn host_referees_a_game_between_two_clients() {
+    let mut host = TournamentHost::new(0, Format::RoundRobin);
+    let mut first = ClientGame::connect((Ipv4Addr::LOCALHOST, host.local_port())).unwrap();
+    let mut second = ClientGame::connect((Ipv4Addr::LOCALHOST, host.local_port())).unwrap();
+    wait_until("both clients to be ready to play", || {
+        host.update();
+        first.update();
+        second.update();
+        host.participants().len() == 2 && host.participants().iter().all(|participant| participant.can_play())
+    });
+
+    host.start().unwrap();
+    wait_until("both games to start", || {
+        host.update();
+        first.update();
+        second.update();
+        first.rematches() == 1 && second.rematches() == 1
+    });
+    let (white, black) = if first.player_color() == Some(Color::White) {
+        (&mut first, &mut second)
+    } else {
+        (&mut second, &mut first)
+    };
+    assert!(black.player_color() == Some(Color::Black));
+
+    for (index, name) in ["f2f3", "e7e5", "g2g4", "d8h4"].iter().enumerate() {
+        let mover = if index % 2 == 0 { &mut *white } else { &mut *black };
+        play(mover, name);
+        wait_until("both sides to see the move", || {
+            host.update();
+            white.update();
+            black.update();
+            white.history().len() == index + 1 && black.history().len() == index + 1
+        });
+    }
+    wait_until("the host to finish the tournament", || {
+        host.update();
+        host.tournament().unwrap().is_finished()
+    });
+
+    let tournament = host.tournament().unwrap();
+    let result = tournament.rounds()[0].pairings[0].result.unwrap();
+    assert_eq!(result, GameResult { outcome: Outcome::BlackWins, termination: Termination::Checkmate });
+    let pgn = tournament.pgn("Test", Backend::default());
+    assert!(pgn.contains("1. f3 e5 2. g4 Qh4# 0-1"), "{pgn}");
+}
+
+#[test]
+fn draw_offer_is_passed_on_to_the_opponent() {
+    use alvinw_chess_gui::session::Decision;
+
+    let mut host = TournamentHost::new(0, Format::RoundRobin);
+    let mut first = ClientGame::connect((Ipv4Addr::LOCALHOST, host.local_port())).unwrap();
+    let mut second = ClientGame::connect((Ipv4Addr::LOCALHOST, host.local_port())).unwrap();
+    wait_until("both clients to be ready to play", || {
+        host.update();
+        first.update();
+        second.update();
+        host.participants().len() == 2 && host.participants().iter().all(|participant| participant.can_play())
+    });
+    host.start().unwrap();
+    wait_until("both games to start", || {
+        host.update();
+        first.update();
+        second.update();
+        first.rematches() == 1 && second.rematches() == 1
+    });
+    let chat_before = second.chat_messages().len();
+
+    first.offer_draw();
+    wait_until("the opponent to be told about the offer", || {
+        host.update();
+        second.update();
+        second.chat_messages()[chat_before..].iter().any(|message| message.text.contains("offers a draw"))
+    });
+    second.offer_draw();
+    wait_until("both sides to see the draw", || {
+        host.update();
+        first.update();
+        second.update();
+        first.decision().is_some() && second.decision().is_some()
+    });
+    assert_eq!(first.decision(), Some(Decision::DrawAgreed));
+    wait_until("the host to finish the tournament", || {
+        host.update();
+        host.tournament().unwrap().is_finished()
+    });
+    let result = host.tournament().unwrap().rounds()[0].pairings[0].result.unwrap();
+    assert_eq!(result, GameResult { outcome: Outcome::Draw, termination: Termination::Agreement });
+}
+
+#[test]
+fn move_off_the_board_is_rejected() {
+    let mut host = TournamentHost::new(0, Format::RoundRobin);
+    let mut client = ClientGame::connect((Ipv4Addr::LOCALHOST, host.local_port())).unwrap();
+    let mut bot = FakePeer::connect(host.local_port());
+    bot.send(&ClientToServerHandshake { server_color: ProtocolColor::Black });
+    let _: ServerToClientHandshake = bot.receive_while(|| host.update());
+    bot.send(&ExtensionMessage::Hello { extensions: vec![extension::REMATCH.to_string()] });
+    wait_until("both participants to be ready to play", || {
+        host.update();
+        client.update();
+        host.participants().len() == 2 && host.participants().iter().all(|participant| participant.can_play())
+    });
+
+    host.start().unwrap();
+    let started: ExtensionMessage = bot.receive_while(|| host.update());
+    let ExtensionMessage::RematchStarted { server_color, .. } = started else {
+        panic!("Expected the game to start, got {started:?}");
+    };
+    if server_color == ProtocolColor::White {
+        // The bot plays black, so the client moves first.
+        wait_until("the client's game to start", || {
+            host.update();
+            client.update();
+            client.rematches() == 1
+        });
+        play(&mut client, "e2e4");
+        let _: ServerToClient = bot.receive_while(|| {
+            host.update();
+            client.update();
+        });
+    }
+
+    bot.send(&ClientToServer::Move(ProtocolMove { start_x: 9, start_y: 1, end_x: 4, end_y: 3, promotion: ProtocolPiece::None }));
+    let reply: ServerToClient = bot.receive_while(|| host.update());
+    assert!(matches!(reply, ServerToClient::Error { ref message, .. } if message == "Invalid square"), "{reply:?}");
+    host.update();
+    assert!(!host.tournament().unwrap().is_finished());
+}
+
+#[test]
+fn silent_connection_does_not_block_the_host() {
+    let identity = Identity::generate().unwrap();
+    let mut host = TournamentHost::new(0, Format::RoundRobin).with_tls(identity.server_config().unwrap());
+    // Never starts the TLS handshake.
+    let _silent = TcpStream::connect((Ipv4Addr::LOCALHOST, host.local_port())).unwrap();
+    let started = Instant::now();
+    for _ in 0..10 {
+        host.update();
+    }
+    assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+
+    let port = host.local_port();
+    let expected = identity.fingerprint();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        Transport::connect(stream, tls::client_config(Some(expected)))
+    });
+    wait_until("the encrypted participant to join", || {
+        host.update();
+        host.participants().len() == 1
+    });
+    assert!(client.join().unwrap().is_ok());
+}
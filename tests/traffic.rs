@@ -0,0 +1,32 @@
+//! The record of network messages.
+
+use std::{env, fs, process};
+
+use alvinw_chess_gui::settings::Settings;
+use alvinw_chess_gui::traffic::{self, Direction};
+
+#[test]
+fn traffic_log_is_off_by_default() {
+    assert!(!Settings::default().traffic_log);
+}
+
+#[test]
+fn full_log_file_is_rotated() {
+    let dir = env::temp_dir().join(format!("alvinw-chess-gui-traffic-{}", process::id()));
+    let path = dir.join("traffic.log");
+    let old_path = dir.join("traffic.log.old");
+    traffic::open_file(&path, 200);
+    for number in 0..20 {
+        traffic::record(Direction::Sent, "127.0.0.1:8384", &format!("{{\"message\":{number}}}"));
+    }
+
+    let current = fs::read_to_string(&path).unwrap();
+    let old = fs::read_to_string(&old_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    // A file is only rotated once it is full, so it may go one line past the limit.
+    assert!(current.len() < 300, "{current}");
+    assert!(old.len() < 300, "{old}");
+    assert!(current.contains("{\"message\":19}"), "{current}");
+    assert!(!old.contains("{\"message\":19}"), "{old}");
+    assert_eq!(traffic::recent().len(), 20);
+}